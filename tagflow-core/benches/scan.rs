@@ -0,0 +1,86 @@
+//! 扫描吞吐基准
+//!
+//! 生成一棵 `DIRS × SUBDIRS × FILES_PER_DIR` 的目录树，分别测量首次扫描 (全部为新增)
+//! 与无变化重扫的耗时。运行：`cargo bench --bench scan`
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tagflow_core::engine::scanner::Scanner;
+use tagflow_core::infra::db::init_db;
+use tagflow_core::models::db::Library;
+use tempfile::TempDir;
+
+const DIRS: usize = 20;
+const SUBDIRS: usize = 10;
+const FILES_PER_DIR: usize = 10;
+
+fn generate_tree(root: &Path) -> u64 {
+    let mut count = 0;
+    for d in 0..DIRS {
+        for s in 0..SUBDIRS {
+            let dir = root.join(format!("dir{:02}/sub{:02}", d, s));
+            std::fs::create_dir_all(&dir).unwrap();
+            for f in 0..FILES_PER_DIR {
+                std::fs::write(dir.join(format!("file{:02}.txt", f)), format!("{}-{}-{}", d, s, f)).unwrap();
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// 在临时目录中创建数据库与资源库记录
+async fn setup_library(tree: &Path) -> (TempDir, Scanner, Library) {
+    let db_dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite:{}?mode=rwc", db_dir.path().join("bench.db").display());
+    let pool = init_db(&url).await.unwrap();
+    sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('bench', 'local', ?)")
+        .bind(tree.to_str().unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+    let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+    (db_dir, Scanner::new(pool), library)
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let tree = tempfile::tempdir().unwrap();
+    let files = generate_tree(tree.path());
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Elements(files));
+    group.sample_size(10);
+
+    group.bench_function(BenchmarkId::new("initial", files), |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let tree = tree.path().to_path_buf();
+            async move {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let (_db_dir, scanner, library) = setup_library(&tree).await;
+                    let start = Instant::now();
+                    scanner.scan_library(&library).await.unwrap();
+                    total += start.elapsed();
+                }
+                total
+            }
+        });
+    });
+
+    group.bench_function(BenchmarkId::new("rescan_unchanged", files), |b| {
+        let (_db_dir, scanner, library) = rt.block_on(async {
+            let (db_dir, scanner, library) = setup_library(tree.path()).await;
+            scanner.scan_library(&library).await.unwrap();
+            (db_dir, scanner, library)
+        });
+        b.to_async(&rt).iter(|| scanner.scan_library(&library));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
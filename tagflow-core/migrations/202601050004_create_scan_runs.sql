@@ -0,0 +1,17 @@
+-- 扫描运行记录表
+-- 每次资源库扫描对应一行，用于展示扫描历史与统计
+CREATE TABLE IF NOT EXISTS scan_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    library_id INTEGER NOT NULL REFERENCES libraries(id) ON DELETE CASCADE,
+    status INTEGER NOT NULL DEFAULT 1,      -- 状态: 1=进行中, 2=已完成, 3=失败
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME,
+    files_seen INTEGER NOT NULL DEFAULT 0,     -- 遍历到的文件数
+    files_added INTEGER NOT NULL DEFAULT 0,    -- 新增文件数
+    files_modified INTEGER NOT NULL DEFAULT 0, -- 修改文件数
+    files_lost INTEGER NOT NULL DEFAULT 0,     -- 标记丢失的文件数
+    errors INTEGER NOT NULL DEFAULT 0,         -- 遍历/读取失败的条目数
+    error_msg TEXT                             -- 扫描整体失败时的错误信息
+);
+
+CREATE INDEX IF NOT EXISTS idx_scan_runs_library ON scan_runs(library_id, id DESC);
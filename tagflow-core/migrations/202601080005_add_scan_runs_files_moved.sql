@@ -0,0 +1,2 @@
+-- 扫描记录增加移动/重命名计数
+ALTER TABLE scan_runs ADD COLUMN files_moved INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- 资源库定时扫描计划
+-- scan_interval_secs 与 scan_cron 至多设置其一，均为空表示不定时扫描
+ALTER TABLE libraries ADD COLUMN scan_interval_secs INTEGER;   -- 固定间隔 (秒)
+ALTER TABLE libraries ADD COLUMN scan_cron TEXT;               -- cron 表达式 (服务器本地时区)
+ALTER TABLE libraries ADD COLUMN next_scan_at DATETIME;        -- 下次计划扫描时间
@@ -0,0 +1,2 @@
+-- 移动检测按 (大小, 哈希) 在数据库中配对新增与丢失的记录
+CREATE INDEX IF NOT EXISTS idx_files_content ON files(library_id, size, hash);
@@ -0,0 +1,7 @@
+-- 记录文件被标记丢失的时间，用于展示与按保留期清理
+ALTER TABLE files ADD COLUMN lost_at DATETIME;
+UPDATE files SET lost_at = CURRENT_TIMESTAMP WHERE status = 0;
+CREATE INDEX IF NOT EXISTS idx_files_lost ON files(library_id, status, lost_at);
+
+-- 扫描记录增加恢复在线的文件计数
+ALTER TABLE scan_runs ADD COLUMN files_restored INTEGER NOT NULL DEFAULT 0;
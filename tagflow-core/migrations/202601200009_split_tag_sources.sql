@@ -0,0 +1,8 @@
+-- 自动标签的来源由统一的 'auto' 细分为生成它的标签器 (path/type/time)，
+-- 重新打标签时只替换对应标签器的输出
+UPDATE file_tags
+SET source = (SELECT category FROM tags WHERE tags.id = file_tags.tag_id)
+WHERE source = 'auto'
+  AND tag_id IN (SELECT id FROM tags WHERE category IN ('path', 'type', 'time'));
+
+CREATE INDEX IF NOT EXISTS idx_file_tags_source ON file_tags(source, file_id);
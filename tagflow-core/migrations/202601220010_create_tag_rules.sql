@@ -0,0 +1,12 @@
+-- 自动标签规则：命中条件的文件关联到指定标签 (file_tags.source = 'rule')
+CREATE TABLE IF NOT EXISTS tag_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    library_id INTEGER REFERENCES libraries(id) ON DELETE CASCADE, -- 为空表示所有资源库
+    name TEXT NOT NULL,
+    conditions TEXT NOT NULL,       -- JSON: filename_regex / path_glob / extensions
+    tag_path TEXT NOT NULL,         -- 'Finance/Invoices'
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_rules_library ON tag_rules(library_id);
@@ -0,0 +1,4 @@
+-- 文件列表按 (mtime, id) 倒序的游标分页
+CREATE INDEX IF NOT EXISTS idx_files_mtime ON files(status, mtime DESC, id DESC);
+-- 按标签查找文件 (主键为 (file_id, tag_id)，不能用于按 tag_id 查找)
+CREATE INDEX IF NOT EXISTS idx_file_tags_tag ON file_tags(tag_id, file_id);
@@ -0,0 +1,26 @@
+-- 文件名与路径全文索引
+-- trigram 分词按任意 3 个字符切分，中文文件名无需分词即可做任意位置的子串 (含前缀) 匹配。
+-- 外部内容表不重复存储文本，由下面的触发器随 files 表同步 (扫描写入、移动、清理与级联删除)。
+CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
+    filename,
+    parent_path,
+    content = 'files',
+    content_rowid = 'id',
+    tokenize = 'trigram case_sensitive 0'
+);
+
+CREATE TRIGGER IF NOT EXISTS files_fts_insert AFTER INSERT ON files BEGIN
+    INSERT INTO files_fts (rowid, filename, parent_path) VALUES (new.id, new.filename, new.parent_path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS files_fts_delete AFTER DELETE ON files BEGIN
+    INSERT INTO files_fts (files_fts, rowid, filename, parent_path) VALUES ('delete', old.id, old.filename, old.parent_path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS files_fts_update AFTER UPDATE OF filename, parent_path ON files BEGIN
+    INSERT INTO files_fts (files_fts, rowid, filename, parent_path) VALUES ('delete', old.id, old.filename, old.parent_path);
+    INSERT INTO files_fts (rowid, filename, parent_path) VALUES (new.id, new.filename, new.parent_path);
+END;
+
+-- 为已有文件建立索引
+INSERT INTO files_fts (files_fts) VALUES ('rebuild');
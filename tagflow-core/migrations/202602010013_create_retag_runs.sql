@@ -0,0 +1,12 @@
+-- 重新打标签任务记录表
+-- 每次后台重新打标签 (单个资源库或全部资源库) 对应一行
+CREATE TABLE IF NOT EXISTS retag_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    library_id INTEGER REFERENCES libraries(id) ON DELETE CASCADE, -- 为空表示所有资源库
+    tagger TEXT,                                -- 只替换该标签器的输出，为空表示全部
+    status INTEGER NOT NULL DEFAULT 1,          -- 状态: 1=进行中, 2=已完成, 3=失败
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME,
+    files_retagged INTEGER NOT NULL DEFAULT 0,  -- 已处理的文件数
+    error_msg TEXT
+);
@@ -0,0 +1,3 @@
+-- 记录文件最近一次被扫描遍历到的扫描代数，扫描结束时代数落后的文件即为丢失
+ALTER TABLE files ADD COLUMN scan_gen INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS idx_files_scan_gen ON files(library_id, scan_gen);
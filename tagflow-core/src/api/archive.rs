@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, StatusCode},
+    response::Response,
+    Json,
+};
+use sqlx::{QueryBuilder, SqlitePool};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info};
+
+use crate::api::auth::ErrorResponse;
+use crate::api::file::content_disposition;
+use crate::api::tag::file_selection;
+use crate::engine::archive::{plan_entries, tag_folders, write_zip};
+use crate::infra::storage::StorageManager;
+use crate::models::db::{FileEntry, Library};
+use crate::models::dto::{ArchiveLayout, ArchiveRequest};
+
+/// 单个压缩包最多包含的文件数
+const MAX_ARCHIVE_FILES: usize = 10_000;
+
+/// 输出管道缓冲区大小
+const PIPE_BUFFER: usize = 256 * 1024;
+
+/// 打包下载多个文件
+///
+/// # 路由
+/// POST /api/v1/files/archive
+///
+/// # 请求体
+/// ```json
+/// { "file_ids": [1, 2, 3] }
+/// ```
+/// 或按条件选择文件 (字段含义与 `POST /api/v1/tags/bulk` 相同)，并按标签路径建立目录:
+/// ```json
+/// { "query": { "tag_id": 5, "q": "type:Image" }, "layout": "tag_path", "name": "设计稿.zip" }
+/// ```
+///
+/// # 成功响应 (200)
+/// `application/zip` 流。压缩包边读边写，不产生临时文件；只包含在线文件，同名文件自动重命名为
+/// `name (1).ext`。`tag_path` 布局下文件放在 `tag_id` 对应标签及其子标签的路径中。
+/// 开始传输后某个文件读取失败时跳过该文件；写入失败时连接中断，客户端收到的压缩包不完整。
+///
+/// # 失败响应
+/// - 400: `file_ids` 与 `query` 未指定或同时指定、查询语法错误、`tag_path` 布局缺少 `query.tag_id`、
+///   没有可下载的文件或文件数超过上限
+pub async fn download_archive(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<ArchiveRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: &str| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: error.to_string() }));
+    let internal_error = |e: anyhow::Error| {
+        error!("准备打包下载失败: {:#}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "准备打包下载失败".to_string() }))
+    };
+
+    let root_tag_id = payload.query.as_ref().and_then(|q| q.tag_id);
+    if payload.layout == ArchiveLayout::TagPath && root_tag_id.is_none() {
+        return Err(bad_request("按标签路径打包需要指定 query.tag_id"));
+    }
+    let selection = file_selection(payload.file_ids, payload.query)
+        .map_err(|_| bad_request("需要且只能指定 file_ids 或有效的 query 之一"))?;
+
+    // 多取一行即可判断是否超过上限，避免把整个资源库读入内存
+    let mut qb = QueryBuilder::new("SELECT * FROM files WHERE status = 1 AND id IN (");
+    selection.push_select(&mut qb);
+    qb.push(") ORDER BY id LIMIT ").push_bind(MAX_ARCHIVE_FILES as i64 + 1);
+    let files: Vec<FileEntry> = qb
+        .build_query_as()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| internal_error(e.into()))?;
+    if files.is_empty() {
+        return Err(bad_request("没有可下载的文件"));
+    }
+    if files.len() > MAX_ARCHIVE_FILES {
+        return Err(bad_request(&format!("单次最多打包 {} 个文件", MAX_ARCHIVE_FILES)));
+    }
+
+    // 按文件所属资源库准备存储算子
+    let library_ids: Vec<i32> = files.iter().map(|f| f.library_id).collect();
+    let libraries: Vec<Library> = sqlx::query_as(
+        "SELECT * FROM libraries WHERE id IN (SELECT value FROM json_each(?))"
+    )
+    .bind(serde_json::to_string(&library_ids).unwrap_or_default())
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| internal_error(e.into()))?;
+    let mut ops = HashMap::new();
+    for library in &libraries {
+        ops.insert(library.id, StorageManager::get_operator(library).map_err(internal_error)?);
+    }
+
+    let (folders, default_name) = match (payload.layout, root_tag_id) {
+        (ArchiveLayout::TagPath, Some(tag_id)) => {
+            let file_ids: Vec<i32> = files.iter().map(|f| f.id).collect();
+            let folders = tag_folders(&pool, tag_id, &file_ids).await.map_err(internal_error)?;
+            let tag_name: Option<String> = sqlx::query_scalar("SELECT name FROM tags WHERE id = ?")
+                .bind(tag_id)
+                .fetch_optional(&pool)
+                .await
+                .map_err(|e| internal_error(e.into()))?;
+            (folders, tag_name.map(|n| format!("{}.zip", n)))
+        }
+        _ => (HashMap::new(), None),
+    };
+    let entries = plan_entries(&files, &folders);
+    let archive_name = match payload.name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()) {
+        Some(name) if name.to_lowercase().ends_with(".zip") => name,
+        Some(name) => format!("{}.zip", name),
+        None => default_name.unwrap_or_else(|| "tagflow.zip".to_string()),
+    };
+
+    // 后台任务写入管道一端，响应体从另一端读取；客户端断开时写入失败，任务随之结束
+    let (writer, reader) = tokio::io::duplex(PIPE_BUFFER);
+    let name = archive_name.clone();
+    tokio::spawn(async move {
+        match write_zip(&ops, &entries, writer).await {
+            Ok(stats) => info!("打包下载完成: {} ({} 个文件，跳过 {} 个)", name, stats.written, stats.skipped),
+            Err(e) => error!("打包下载中断: {} - {:#}", name, e),
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, content_disposition(&archive_name, true))
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from_stream(ReaderStream::new(reader)))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_zip::base::read::mem::ZipFileReader;
+    use crate::core::tag::TagManager;
+    use crate::infra::db::test_pool;
+
+    #[tokio::test]
+    async fn test_download_archive_by_tag_path() {
+        let lib_a = tempfile::tempdir().unwrap();
+        let lib_b = tempfile::tempdir().unwrap();
+        std::fs::write(lib_a.path().join("logo.png"), b"a").unwrap();
+        std::fs::write(lib_b.path().join("logo.png"), b"b").unwrap();
+        std::fs::write(lib_b.path().join("notes.txt"), b"c").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (id, name, protocol, base_path) VALUES (1, 'a', 'local', ?), (2, 'b', 'local', ?)")
+            .bind(lib_a.path().to_str().unwrap())
+            .bind(lib_b.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime) VALUES
+             (1, 1, '', 'logo.png', 1, 1), (2, 2, '', 'logo.png', 1, 1), (3, 2, '', 'notes.txt', 1, 1)"
+        )
+        .execute(&pool).await.unwrap();
+        let manager = TagManager::new(pool.clone());
+        let project = manager.create_user_tag("Project", None).await.unwrap();
+        let design = manager.create_user_tag("Design", Some(project.id)).await.unwrap();
+        manager.attach_manual(1, design.id).await.unwrap();
+        manager.attach_manual(2, design.id).await.unwrap();
+        manager.attach_manual(3, project.id).await.unwrap();
+
+        let request: ArchiveRequest = serde_json::from_value(serde_json::json!({
+            "query": { "tag_id": project.id },
+            "layout": "tag_path"
+        }))
+        .unwrap();
+        let response = download_archive(State(pool.clone()), Json(request)).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+        assert!(response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().contains("Project.zip"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let zip = ZipFileReader::new(body.to_vec()).await.unwrap();
+        let names: Vec<&str> = zip.file().entries().iter().map(|e| e.filename().as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Project/Design/logo.png", "Project/Design/logo (1).png", "Project/notes.txt"]);
+
+        let flat: ArchiveRequest = serde_json::from_value(serde_json::json!({ "file_ids": [], "layout": "tag_path" })).unwrap();
+        let err = download_archive(State(pool.clone()), Json(flat)).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_download_archive_rejects_too_many_files() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (id, name, protocol, base_path) VALUES (1, 'a', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i <= ?)
+             INSERT INTO files (id, library_id, parent_path, filename, size, mtime)
+             SELECT i, 1, '', 'f' || i || '.txt', 1, 1 FROM n"
+        )
+        .bind(MAX_ARCHIVE_FILES as i64)
+        .execute(&pool).await.unwrap();
+
+        let request: ArchiveRequest = serde_json::from_value(serde_json::json!({ "query": { "library_id": 1 } })).unwrap();
+        let err = download_archive(State(pool.clone()), Json(request)).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert!(err.1.error.contains(&MAX_ARCHIVE_FILES.to_string()));
+    }
+}
@@ -4,7 +4,7 @@
 
 use axum::{
     body::Body,
-    extract::State,
+    extract::{Path, Query, State},
     http::{header, Request, StatusCode},
     middleware::Next,
     response::{Json, Response},
@@ -13,7 +13,7 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 
-use crate::core::auth::{verify_password, create_jwt, decode_jwt, hash_password, Claims};
+use crate::core::auth::{verify_password, create_jwt, decode_content_token, decode_jwt, hash_password, Claims};
 
 /// 登录请求
 #[derive(Debug, Deserialize)]
@@ -105,40 +105,67 @@ pub async fn login(
 ///
 /// # 请求头
 /// 客户端需要在请求头中携带：
-/// ```
+/// ```text
 /// Authorization: Bearer <token>
 /// ```
 pub async fn auth_middleware(
     mut req: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    // 获取 Authorization 请求头
-    let auth_header = req
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
-
-    if let Some(auth_value) = auth_header {
-        // 检查是否为 Bearer 令牌
-        if auth_value.starts_with("Bearer ") {
-            let token = &auth_value[7..];
-
-            // 验证令牌
-            match decode_jwt(token) {
-                Ok(claims) => {
-                    // 令牌有效，将用户信息存储到请求扩展中
-                    req.extensions_mut().insert(claims);
-                    return Ok(next.run(req).await);
-                }
-                Err(_) => {
-                    return Err(StatusCode::UNAUTHORIZED);
-                }
-            }
+    match bearer_claims(&req) {
+        Some(claims) => {
+            // 令牌有效，将用户信息存储到请求扩展中
+            req.extensions_mut().insert(claims);
+            Ok(next.run(req).await)
         }
+        // 无令牌、令牌格式错误或验证失败
+        None => Err(StatusCode::UNAUTHORIZED),
     }
+}
 
-    // 无令牌或令牌格式错误
-    Err(StatusCode::UNAUTHORIZED)
+/// 文件内容令牌查询参数
+#[derive(Debug, Deserialize)]
+pub struct ContentTokenQuery {
+    pub token: Option<String>,
+}
+
+/// 文件内容路由的鉴权中间件
+///
+/// 除 `Authorization` 头外，也接受 `POST /api/v1/files/:id/content-token` 签发的
+/// `?token=` 文件内容令牌，令牌只对签发时的文件有效。需以 `route_layer` 挂在带 `:id` 参数的路由上。
+///
+/// # 使用方式
+/// ```ignore
+/// let content_routes = Router::new()
+///     .route("/api/v1/files/:id/content", get(api::file::get_file_content))
+///     .route_layer(middleware::from_fn(api::auth::content_auth_middleware));
+/// ```
+pub async fn content_auth_middleware(
+    Path(file_id): Path<i32>,
+    Query(query): Query<ContentTokenQuery>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let claims = bearer_claims(&req).or_else(|| {
+        let content = decode_content_token(query.token.as_deref()?).ok()?;
+        (content.file_id == file_id).then_some(Claims { sub: content.sub, exp: content.exp })
+    });
+    let Some(claims) = claims else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
+}
+
+/// 验证 `Authorization: Bearer <token>` 请求头，返回令牌中的用户信息
+fn bearer_claims(req: &Request<Body>) -> Option<Claims> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?;
+    decode_jwt(token).ok()
 }
 
 /// 从请求扩展中提取当前用户信息的辅助函数
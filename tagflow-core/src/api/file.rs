@@ -1,61 +1,529 @@
+use std::time::{Duration, UNIX_EPOCH};
+
 use axum::{
     body::Body,
     extract::{Path, Query, State},
-    http::{header, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     response::Response,
-    Json,
+    Extension, Json,
 };
-use sqlx::SqlitePool;
+use opendal::Operator;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sqlx::{QueryBuilder, SqlitePool};
+use tracing::{error, warn};
 use tokio::fs::File;
 use tokio_util::io::ReaderStream;
-use crate::models::dto::{FileQuery, FileResponse, FileItem};
-use crate::models::db::FileEntry;
+use crate::models::dto::{ContentTokenResponse, FileContentQuery, FileCursor, FileDetailResponse, FileQuery, FileSort, FileTagItem, FileTaskItem, SortOrder, FileResponse, FileItem, MissingFileItem, MissingFileQuery, MissingFileResponse};
+use crate::models::db::{FileEntry, Library};
+use crate::engine::tagger::file_type::{read_head, sniff_mime};
+use crate::infra::storage::StorageManager;
+use crate::api::auth::ErrorResponse;
+use crate::core::auth::{create_content_token, Claims, CONTENT_TOKEN_TTL_SECS};
+use crate::core::tag::query::{FileFilter, TagExpr};
+use crate::infra::thumbnail::{thumbnail_path, THUMBNAIL_CACHE_DIR};
 
+/// 列出文件
+///
+/// # 路由
+/// GET /api/v1/files?tag_id=3&recursive=true&q=Work AND NOT type:Image&status=online&limit=50&cursor=...
+///
+/// `q` 为标签查询表达式 (语法见 [`crate::core::tag::query`])，与 `tag_id` 同时指定时取交集。
+///
+/// # 排序与属性过滤
+/// - `sort`: `name` / `size` / `mtime` (默认) / `indexed_at` / `extension`，`order`: `asc` / `desc` (默认)
+/// - `library_id`、`ext=jpg,png`、`min_size` / `max_size`、`mtime_from` / `mtime_to` (闭区间)
+/// - `path_prefix=Work/Design`: 该目录及其子目录下的文件
+///
+/// # 成功响应 (200)
+/// ```json
+/// { "items": [...], "total": 1234, "next_cursor": "313730343036373230303a3432" }
+/// ```
+/// `total` 为满足条件的文件总数。按 mtime 排序时翻页可把 `next_cursor` 作为 `cursor` 传回，
+/// 深翻页不会退化为大 OFFSET 扫描；其他排序使用 `page` 参数，`next_cursor` 为空。
+///
+/// # 失败响应
+/// - 400: `q` 语法错误、游标无效或按非 mtime 排序时使用游标，响应体为 `{ "error": "..." }`
 pub async fn list_files(
     State(pool): State<SqlitePool>,
     Query(query): Query<FileQuery>,
-) -> Json<FileResponse> {
-    let limit = query.limit.unwrap_or(50);
-    let offset = (query.page.unwrap_or(1) - 1) * limit;
-
-    let items: Result<Vec<FileEntry>, _> = if let Some(tag_id) = query.tag_id {
-        if query.recursive.unwrap_or(true) {
-            // 使用递归 CTE 查找所有子孙标签的文件
-            sqlx::query_as::<_, FileEntry>(
-                r#"
-                WITH RECURSIVE sub_tags(id) AS (
-                    SELECT id FROM tags WHERE id = ?
-                    UNION ALL
-                    SELECT t.id FROM tags t JOIN sub_tags st ON t.parent_id = st.id
-                )
-                SELECT DISTINCT f.* FROM files f
-                JOIN file_tags ft ON f.id = ft.file_id
-                WHERE ft.tag_id IN (SELECT id FROM sub_tags)
-                ORDER BY f.mtime DESC LIMIT ? OFFSET ?
-                "#,
-            )
-            .bind(tag_id).bind(limit).bind(offset)
-            .fetch_all(&pool).await
-        } else {
-            // 仅查找直接关联该标签的文件
-            sqlx::query_as::<_, FileEntry>(
-                "SELECT f.* FROM files f JOIN file_tags ft ON f.id = ft.file_id WHERE ft.tag_id = ? ORDER BY f.mtime DESC LIMIT ? OFFSET ?"
-            )
-            .bind(tag_id).bind(limit).bind(offset)
-            .fetch_all(&pool).await
+) -> Result<Json<FileResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let internal_error = |e: sqlx::Error| {
+        error!("查询文件列表失败: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "查询文件列表失败".to_string() }))
+    };
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let expr = query
+        .q
+        .as_deref()
+        .map(TagExpr::parse)
+        .transpose()
+        .map_err(|e| bad_request(e.to_string()))?;
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|c| FileCursor::decode(c).ok_or_else(|| bad_request("无效的游标".to_string())))
+        .transpose()?;
+
+    if cursor.is_some() && query.sort != FileSort::Mtime {
+        return Err(bad_request("游标分页仅支持按 mtime 排序".to_string()));
+    }
+
+    let filter = FileFilter {
+        tag_id: query.tag_id,
+        recursive: query.recursive.unwrap_or(true),
+        expr,
+        library_id: query.library_id,
+        status: query.status,
+        extensions: query.extensions(),
+        min_size: query.min_size,
+        max_size: query.max_size,
+        mtime_from: query.mtime_from,
+        mtime_to: query.mtime_to,
+        path_prefix: query.path_prefix.clone(),
+    };
+
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM files f");
+    filter.push_where(&mut qb);
+    let total: i64 = qb.build_query_scalar().fetch_one(&pool).await.map_err(internal_error)?;
+
+    let mut qb = QueryBuilder::new("SELECT f.* FROM files f");
+    filter.push_where(&mut qb);
+    if let Some(cursor) = cursor {
+        let op = if query.order == SortOrder::Asc { ">" } else { "<" };
+        qb.push(format!(" AND (f.mtime, f.id) {op} ("))
+            .push_bind(cursor.mtime)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    // 排序字段与方向来自枚举，不拼接用户输入；以 id 作为次序键保证分页稳定
+    let order = query.order.sql();
+    qb.push(format!(" ORDER BY {} {order}, f.id {order} LIMIT ", query.sort.sql_column())).push_bind(limit);
+    if cursor.is_none() {
+        let offset = (query.page.unwrap_or(1).max(1) - 1) * limit;
+        qb.push(" OFFSET ").push_bind(offset);
+    }
+    let items: Vec<FileEntry> = qb.build_query_as().fetch_all(&pool).await.map_err(internal_error)?;
+
+    let next_cursor = match items.last() {
+        Some(last) if items.len() as i64 == limit && query.sort == FileSort::Mtime => {
+            Some(FileCursor { mtime: last.mtime, id: last.id }.encode())
+        }
+        _ => None,
+    };
+    let items: Vec<FileItem> = items.into_iter().map(|e| e.into()).collect();
+
+    Ok(Json(FileResponse { items, total, next_cursor }))
+}
+
+/// 列出丢失的文件
+///
+/// # 路由
+/// GET /api/v1/files/missing?library_id=1&page=1&limit=50
+///
+/// # 成功响应 (200)
+/// ```json
+/// {
+///   "items": [
+///     {
+///       "id": 42,
+///       "library_id": 1,
+///       "library_name": "我的照片",
+///       "path": "2024/trip/a.jpg",
+///       "size": 102400,
+///       "mtime": 1704067200,
+///       "lost_at": "2026-01-18T08:00:00Z"
+///     }
+///   ],
+///   "total": 1
+/// }
+/// ```
+/// 按丢失时间倒序排列。
+pub async fn list_missing_files(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<MissingFileQuery>,
+) -> Result<Json<MissingFileResponse>, StatusCode> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let offset = (query.page.unwrap_or(1).max(1) - 1) * limit;
+
+    let items = sqlx::query_as::<_, MissingFileItem>(
+        "SELECT f.id, f.library_id, l.name AS library_name, f.parent_path || f.filename AS path,
+                f.size, f.mtime, f.lost_at
+         FROM files f JOIN libraries l ON l.id = f.library_id
+         WHERE f.status = 0 AND (?1 IS NULL OR f.library_id = ?1)
+         ORDER BY f.lost_at DESC, f.id DESC LIMIT ?2 OFFSET ?3"
+    )
+    .bind(query.library_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM files WHERE status = 0 AND (?1 IS NULL OR library_id = ?1)"
+    )
+    .bind(query.library_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(MissingFileResponse { items, total }))
+}
+
+/// 获取文件详情
+///
+/// # 路由
+/// GET /api/v1/files/:id
+///
+/// # 成功响应 (200)
+/// ```json
+/// {
+///   "id": 42,
+///   "library_id": 1,
+///   "parent_path": "Work/Design/",
+///   "filename": "logo.png",
+///   "extension": "png",
+///   "size": 102400,
+///   "mtime": 1704067200,
+///   "hash": "af1349b9...",
+///   "status": 1,
+///   "indexed_at": "2026-01-28T08:00:00Z",
+///   "lost_at": null,
+///   "library_name": "我的照片",
+///   "path": "Work/Design/logo.png",
+///   "tags": [
+///     { "id": 3, "name": "Design", "category": "path", "path": ["Work", "Design"], "source": "path" },
+///     { "id": 9, "name": "Image", "category": "type", "path": ["Image"], "source": "type" }
+///   ],
+///   "has_thumbnail": true,
+///   "tasks": [
+///     { "id": 7, "task_type": "thumb", "status": 3, "error_msg": "ffmpeg 退出码 1", "created_at": "...", "started_at": "..." }
+///   ]
+/// }
+/// ```
+///
+/// # 失败响应
+/// - 404: 文件不存在
+pub async fn get_file(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i32>,
+) -> Result<Json<FileDetailResponse>, StatusCode> {
+    let internal_error = |e: sqlx::Error| {
+        error!("查询文件详情失败: {} - {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let file: FileEntry = sqlx::query_as("SELECT * FROM files WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let library_name: String = sqlx::query_scalar("SELECT name FROM libraries WHERE id = ?")
+        .bind(file.library_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(internal_error)?;
+    let tags = file_tags(&pool, id).await.map_err(internal_error)?;
+    let tasks: Vec<FileTaskItem> = sqlx::query_as(
+        "SELECT id, task_type, status, error_msg, created_at, started_at FROM tasks
+         WHERE file_id = ? AND status != 2 ORDER BY id DESC"
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await
+    .map_err(internal_error)?;
+    let has_thumbnail = tokio::fs::try_exists(thumbnail_path(THUMBNAIL_CACHE_DIR, id)).await.unwrap_or(false);
+
+    Ok(Json(FileDetailResponse {
+        path: format!("{}{}", file.parent_path, file.filename),
+        file,
+        library_name,
+        tags,
+        has_thumbnail,
+        tasks,
+    }))
+}
+
+/// 查询文件关联的标签及其完整层级路径
+async fn file_tags(pool: &SqlitePool, file_id: i32) -> Result<Vec<FileTagItem>, sqlx::Error> {
+    // 从每个关联标签向上回溯祖先，depth 越大越接近根
+    let rows: Vec<(i32, String, String, String, i32, String)> = sqlx::query_as(
+        "WITH RECURSIVE ancestry(tag_id, ancestor_id, parent_id, name, depth) AS (
+             SELECT t.id, t.id, t.parent_id, t.name, 0 FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+             WHERE ft.file_id = ?1
+             UNION ALL
+             SELECT a.tag_id, p.id, p.parent_id, p.name, a.depth + 1 FROM ancestry a JOIN tags p ON p.id = a.parent_id
+         )
+         SELECT a.tag_id, t.name, t.category, ft.source, a.depth, a.name
+         FROM ancestry a
+         JOIN tags t ON t.id = a.tag_id
+         JOIN file_tags ft ON ft.file_id = ?1 AND ft.tag_id = a.tag_id
+         ORDER BY a.tag_id, a.depth DESC"
+    )
+    .bind(file_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut tags: Vec<FileTagItem> = Vec::new();
+    for (tag_id, name, category, source, _depth, ancestor) in rows {
+        match tags.last_mut() {
+            Some(tag) if tag.id == tag_id => tag.path.push(ancestor),
+            _ => tags.push(FileTagItem { id: tag_id, name, category, path: vec![ancestor], source }),
+        }
+    }
+    tags.sort_by(|a, b| (&a.category, &a.path).cmp(&(&b.category, &b.path)));
+    Ok(tags)
+}
+
+/// 签发文件内容令牌
+///
+/// `<img>`/`<video>` 等元素无法携带 `Authorization` 头，客户端先获取令牌，
+/// 再以 `GET /api/v1/files/:id/content?token=<token>` 引用文件。令牌只对该文件有效，
+/// 过期后需重新获取。
+///
+/// # 路由
+/// POST /api/v1/files/:id/content-token
+///
+/// # 成功响应 (200)
+/// ```json
+/// { "token": "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...", "expires_in": 3600 }
+/// ```
+///
+/// # 失败响应
+/// - 404: 文件不存在或已丢失
+pub async fn create_file_content_token(
+    State(pool): State<SqlitePool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+) -> Result<Json<ContentTokenResponse>, StatusCode> {
+    let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM files WHERE id = ? AND status = 1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let token = create_content_token(&claims.sub, id).map_err(|e| {
+        error!("签发文件内容令牌失败: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(ContentTokenResponse { token, expires_in: CONTENT_TOKEN_TTL_SECS }))
+}
+
+/// 获取文件内容
+///
+/// 通过资源库的 OpenDAL 算子流式读取原文件，不经过本地缓存。
+///
+/// # 路由
+/// GET /api/v1/files/:id/content?download=true
+///
+/// 除 `Authorization` 头外也可用 `?token=` 携带文件内容令牌 (见 [`create_file_content_token`])。
+///
+/// # 请求头
+/// - `Range: bytes=0-1023` / `bytes=1024-` / `bytes=-500`: 只返回指定区间 (仅支持单个区间)
+/// - `If-Range`: 与当前 ETag 不一致时忽略 Range 返回完整内容
+/// - `If-None-Match` / `If-Modified-Since`: 未变化时返回 304
+///
+/// # 响应
+/// - 200: 完整内容
+/// - 206: 部分内容，带 `Content-Range`
+/// - 304: 未修改
+/// - 404: 文件不存在、已丢失或在存储中找不到
+/// - 416: 区间超出文件大小
+///
+/// `Content-Type` 按扩展名推断，无法推断时读取文件头识别；`ETag` 与 `Last-Modified`
+/// 由文件当前的大小与修改时间生成。
+///
+/// 资源库中的文件不可信，且与 API 同源：响应总是带 `X-Content-Type-Options: nosniff` 与
+/// `Content-Security-Policy: sandbox`，HTML、SVG、XML 等可执行脚本的类型总是以附件形式下载。
+pub async fn get_file_content(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i32>,
+    Query(query): Query<FileContentQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("读取文件内容失败: {} - {:#}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let file: FileEntry = sqlx::query_as("SELECT * FROM files WHERE id = ? AND status = 1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| internal_error(e.into()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = ?")
+        .bind(file.library_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| internal_error(e.into()))?;
+    let op = StorageManager::get_operator(&library).map_err(internal_error)?;
+    let path = format!("{}{}", file.parent_path, file.filename);
+
+    let meta = match op.stat(&path).await {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => {
+            warn!("文件在存储中不存在: {} ({})", path, library.name);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Err(e) => return Err(internal_error(e.into())),
+    };
+    let size = meta.content_length();
+    let mtime = meta.last_modified().map_or(file.mtime, |t| t.timestamp()).max(0) as u64;
+    let etag = format!("\"{:x}-{:x}\"", size, mtime);
+    let last_modified = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(mtime));
+
+    if not_modified(&headers, &etag, mtime) {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .header(header::LAST_MODIFIED, &last_modified)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let content_type = content_type(&op, &path, file.extension.as_deref(), size).await;
+    let disposition = content_disposition(&file.filename, query.download || is_active_content(&content_type));
+
+    // If-Range 不匹配时按完整内容响应
+    let if_range_matches = headers
+        .get(header::IF_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v == etag || v == last_modified);
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if if_range_matches => match parse_range(range, size) {
+            Ok(range) => range,
+            Err(()) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                    .body(Body::empty())
+                    .unwrap());
+            }
+        },
+        _ => None,
+    };
+
+    let (status, start, end) = match range {
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+        None => (StatusCode::OK, 0, size),
+    };
+    let stream = op
+        .reader(&path)
+        .await
+        .map_err(|e| internal_error(e.into()))?
+        .into_bytes_stream(start..end)
+        .await
+        .map_err(|e| internal_error(e.into()))?;
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, end - start)
+        .header(header::CONTENT_DISPOSITION, disposition)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, &last_modified)
+        .header(header::CACHE_CONTROL, "private, no-cache")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::CONTENT_SECURITY_POLICY, "sandbox");
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, size));
+    }
+    Ok(response.body(Body::from_stream(stream)).unwrap())
+}
+
+/// 条件请求是否命中缓存；有 If-None-Match 时忽略 If-Modified-Since
+fn not_modified(headers: &HeaderMap, etag: &str, mtime: u64) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return if_none_match.trim() == "*"
+            || if_none_match.split(',').any(|tag| tag.trim().trim_start_matches("W/") == etag);
+    }
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .is_some_and(|since| UNIX_EPOCH + Duration::from_secs(mtime) <= since)
+}
+
+/// 解析单个字节区间，返回半开区间 `[start, end)`
+///
+/// - `Ok(None)`: 语法无法识别或包含多个区间，按规范忽略 Range 返回完整内容
+/// - `Err(())`: 区间无法满足 (416)
+fn parse_range(header: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // bytes=-500: 最后 500 个字节
+        let Ok(suffix) = end.parse::<u64>() else { return Ok(None) };
+        if suffix == 0 {
+            return Err(());
         }
+        (size.saturating_sub(suffix), size)
     } else {
-        // 无过滤条件，返回所有
-        sqlx::query_as::<_, FileEntry>("SELECT * FROM files ORDER BY mtime DESC LIMIT ? OFFSET ?")
-            .bind(limit).bind(offset)
-            .fetch_all(&pool).await
+        let Ok(start) = start.parse::<u64>() else { return Ok(None) };
+        let end = if end.is_empty() {
+            size
+        } else {
+            let Ok(end) = end.parse::<u64>() else { return Ok(None) };
+            if end < start {
+                return Ok(None);
+            }
+            end.saturating_add(1).min(size)
+        };
+        (start, end)
     };
+    if range.0 >= size {
+        return Err(());
+    }
+    Ok(Some(range))
+}
 
-    let items = items.unwrap_or_default();
-    let items: Vec<FileItem> = items.into_iter().map(|e| e.into()).collect();
-    let total = items.len() as i64;
+/// 按扩展名推断 Content-Type，无法推断 (或只能推断为通用二进制) 时读取文件头识别
+async fn content_type(op: &Operator, path: &str, extension: Option<&str>, size: u64) -> String {
+    if let Some(mime) = extension.and_then(|ext| mime_guess::from_ext(ext).first())
+        && mime != mime_guess::mime::APPLICATION_OCTET_STREAM
+    {
+        return mime.to_string();
+    }
+    let head = read_head(op, path, size).await.unwrap_or_default();
+    sniff_mime(&head).unwrap_or("application/octet-stream").to_string()
+}
+
+/// 浏览器直接打开时可能执行脚本的类型
+fn is_active_content(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    matches!(
+        essence.as_str(),
+        "text/html" | "application/xhtml+xml" | "image/svg+xml" | "text/xml" | "application/xml" | "text/xsl"
+    ) || essence.ends_with("+xml")
+}
 
-    Json(FileResponse { items, total })
+/// RFC 6266 Content-Disposition，非 ASCII 文件名通过 `filename*` 传递
+pub(crate) fn content_disposition(filename: &str, download: bool) -> String {
+    let kind = if download { "attachment" } else { "inline" };
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let encoded = utf8_percent_encode(filename, NON_ALPHANUMERIC);
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", kind, fallback, encoded)
 }
 
 /// 获取文件缩略图
@@ -74,9 +542,7 @@ pub async fn list_files(
 pub async fn get_thumbnail(
     Path(id): Path<i32>,
 ) -> Result<Response, StatusCode> {
-    // 缩略图缓存目录
-    let cache_dir = "./cache";
-    let thumbnail_path = format!("{}/{}.webp", cache_dir, id);
+    let thumbnail_path = thumbnail_path(THUMBNAIL_CACHE_DIR, id);
 
     // 检查缩略图文件是否存在
     match File::open(&thumbnail_path).await {
@@ -99,3 +565,413 @@ pub async fn get_thumbnail(
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+    use crate::core::tag::TagManager;
+    use crate::models::db::TagCategory;
+    use crate::models::dto::FileStatusFilter;
+
+    async fn seed(pool: &SqlitePool) {
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime, status, lost_at) VALUES
+             (1, 1, '', 'online.txt', 1, 2, 1, NULL),
+             (2, 1, 'Old/', 'gone.txt', 1, 1, 0, '2026-01-18 08:00:00')"
+        )
+        .execute(pool).await.unwrap();
+    }
+
+    fn query(status: FileStatusFilter) -> FileQuery {
+        FileQuery {
+            tag_id: None,
+            recursive: None,
+            q: None,
+            page: None,
+            limit: None,
+            cursor: None,
+            status,
+            sort: FileSort::default(),
+            order: SortOrder::default(),
+            library_id: None,
+            ext: None,
+            min_size: None,
+            max_size: None,
+            mtime_from: None,
+            mtime_to: None,
+            path_prefix: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_files_status_filter() {
+        let pool = test_pool().await;
+        seed(&pool).await;
+
+        let ids = |res: FileResponse| res.items.iter().map(|i| i.id).collect::<Vec<_>>();
+        let Json(online) = list_files(State(pool.clone()), Query(query(FileStatusFilter::default()))).await.unwrap();
+        assert_eq!(ids(online), vec![1]);
+        let Json(lost) = list_files(State(pool.clone()), Query(query(FileStatusFilter::Lost))).await.unwrap();
+        assert_eq!(ids(lost), vec![2]);
+        let Json(all) = list_files(State(pool.clone()), Query(query(FileStatusFilter::All))).await.unwrap();
+        assert_eq!(ids(all), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_list_missing_files() {
+        let pool = test_pool().await;
+        seed(&pool).await;
+
+        let Json(res) = list_missing_files(
+            State(pool.clone()),
+            Query(MissingFileQuery { library_id: Some(1), page: None, limit: None }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.total, 1);
+        assert_eq!(res.items[0].path, "Old/gone.txt");
+        assert_eq!(res.items[0].library_name, "lib");
+        assert!(res.items[0].lost_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_list_files_tag_query() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime) VALUES
+             (1, 1, 'Work/Design/', 'logo.png', 1, 3),
+             (2, 1, 'Work/Design/', 'intro.mp4', 1, 2),
+             (3, 1, 'Work/Design/Archive/', 'old.png', 1, 1),
+             (4, 1, 'Work/', 'notes.txt', 1, 0)"
+        )
+        .execute(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let tags = [
+            (1, TagCategory::Path, vec!["Work", "Design"]),
+            (1, TagCategory::Type, vec!["Image", "PNG"]),
+            (2, TagCategory::Path, vec!["Work", "Design"]),
+            (2, TagCategory::Type, vec!["Video"]),
+            (3, TagCategory::Path, vec!["Work", "Design", "Archive"]),
+            (3, TagCategory::Type, vec!["Image", "PNG"]),
+            (4, TagCategory::Path, vec!["Work"]),
+            (4, TagCategory::Type, vec!["Text"]),
+        ];
+        for (file_id, category, path) in tags {
+            let parts: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+            let tag_id = TagManager::ensure_tags_on(&mut conn, category, &parts).await.unwrap();
+            TagManager::link_file_to_tag_on(&mut conn, file_id, tag_id, "path").await.unwrap();
+        }
+        drop(conn);
+
+        let search = |q: &str| {
+            let pool = pool.clone();
+            let q = q.to_string();
+            async move {
+                let query = FileQuery { q: Some(q), ..query(FileStatusFilter::Online) };
+                list_files(State(pool), Query(query)).await.map(|Json(res)| res.items.iter().map(|i| i.id).collect::<Vec<_>>())
+            }
+        };
+
+        assert_eq!(search("Work/Design AND (type:Image OR type:Video)").await.unwrap(), vec![1, 2, 3]);
+        // 子孙标签 Archive 也被 NOT 排除；路径可匹配任意层级，以 / 开头时只从根匹配
+        assert_eq!(search("Work/Design AND (type:Image OR type:Video) AND NOT Archive").await.unwrap(), vec![1, 2]);
+        assert_eq!(search("type:image/png OR type:Text").await.unwrap(), vec![1, 3, 4]);
+        assert_eq!(search("Design/Archive").await.unwrap(), vec![3]);
+        assert_eq!(search("/Design").await.unwrap(), Vec::<i32>::new());
+
+        // 上限内的查询可以执行，超出时返回 400 而不是生成超限的 SQL
+        let deep = vec!["Work"; 16].join("/");
+        assert!(search(&vec![deep.as_str(); 64].join(" OR ")).await.unwrap().is_empty());
+        let err = search(&vec!["Work"; 65].join(" OR ")).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        // 分类前缀区分同名标签
+        assert_eq!(search("path:Video").await.unwrap(), Vec::<i32>::new());
+
+        let err = search("Work AND (type:Image").await.unwrap_err();
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert!(err.1.error.contains("缺少对应的 ')'"));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_total_and_cursor() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        // 文件 2 与 3 的 mtime 相同，按 id 倒序
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime) VALUES
+             (1, 1, '', 'a', 1, 30), (2, 1, '', 'b', 1, 20), (3, 1, '', 'c', 1, 20), (4, 1, '', 'd', 1, 10), (5, 1, '', 'e', 1, 5)"
+        )
+        .execute(&pool).await.unwrap();
+
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = FileQuery { limit: Some(2), cursor: cursor.clone(), ..query(FileStatusFilter::Online) };
+            let Json(res) = list_files(State(pool.clone()), Query(page)).await.unwrap();
+            assert_eq!(res.total, 5);
+            ids.extend(res.items.iter().map(|i| i.id));
+            match res.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(ids, vec![1, 3, 2, 4, 5]);
+
+        // 与 page 分页结果一致
+        let page = FileQuery { limit: Some(2), page: Some(2), ..query(FileStatusFilter::Online) };
+        let Json(res) = list_files(State(pool.clone()), Query(page)).await.unwrap();
+        assert_eq!(res.items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![2, 4]);
+
+        let invalid = FileQuery { cursor: Some("zz".to_string()), ..query(FileStatusFilter::Online) };
+        assert_eq!(list_files(State(pool.clone()), Query(invalid)).await.unwrap_err().0, StatusCode::BAD_REQUEST);
+        assert_eq!(FileCursor::decode(&FileCursor { mtime: 1_700_000_000, id: 42 }.encode()), Some(FileCursor { mtime: 1_700_000_000, id: 42 }));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_sort_and_attribute_filters() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('a', 'local', '/a'), ('b', 'local', '/b')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, extension, size, mtime) VALUES
+             (1, 1, 'Work/', 'b.JPG', 'jpg', 300, 10),
+             (2, 1, 'Work/Design/', 'a.png', 'png', 100, 20),
+             (3, 1, 'Workshop/', 'c.jpg', 'jpg', 200, 30),
+             (4, 2, 'Work/', 'd.txt', 'txt', 50, 40),
+             (5, 1, 'W_rk/', 'e.png', 'png', 10, 50)"
+        )
+        .execute(&pool).await.unwrap();
+
+        let ids = |query: FileQuery| {
+            let pool = pool.clone();
+            async move {
+                let Json(res) = list_files(State(pool), Query(query)).await.unwrap();
+                res.items.iter().map(|i| i.id).collect::<Vec<_>>()
+            }
+        };
+        let base = || query(FileStatusFilter::Online);
+
+        assert_eq!(ids(FileQuery { sort: FileSort::Name, order: SortOrder::Asc, ..base() }).await, vec![2, 1, 3, 4, 5]);
+        assert_eq!(ids(FileQuery { sort: FileSort::Size, ..base() }).await, vec![1, 3, 2, 4, 5]);
+        assert_eq!(ids(FileQuery { sort: FileSort::Extension, order: SortOrder::Asc, ..base() }).await, vec![1, 3, 2, 5, 4]);
+
+        assert_eq!(ids(FileQuery { ext: Some(".JPG, png".to_string()), library_id: Some(1), ..base() }).await, vec![5, 3, 2, 1]);
+        assert_eq!(ids(FileQuery { min_size: Some(100), max_size: Some(200), ..base() }).await, vec![3, 2]);
+        assert_eq!(ids(FileQuery { mtime_from: Some(20), mtime_to: Some(40), ..base() }).await, vec![4, 3, 2]);
+        // 按目录边界匹配，`_` 不作为通配符
+        assert_eq!(ids(FileQuery { path_prefix: Some("/Work/".to_string()), ..base() }).await, vec![4, 2, 1]);
+        assert_eq!(ids(FileQuery { path_prefix: Some("W_rk".to_string()), ..base() }).await, vec![5]);
+
+        // 升序游标分页
+        let page = FileQuery { limit: Some(3), order: SortOrder::Asc, ..base() };
+        let Json(first) = list_files(State(pool.clone()), Query(page)).await.unwrap();
+        let page = FileQuery { limit: Some(3), order: SortOrder::Asc, cursor: first.next_cursor, ..base() };
+        assert_eq!(ids(page).await, vec![4, 5]);
+
+        let invalid = FileQuery { sort: FileSort::Size, cursor: Some(FileCursor { mtime: 1, id: 1 }.encode()), ..base() };
+        assert_eq!(list_files(State(pool.clone()), Query(invalid)).await.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_file_detail() {
+        let pool = test_pool().await;
+        seed(&pool).await;
+        let mut conn = pool.acquire().await.unwrap();
+        let old = TagManager::ensure_path_tags_on(&mut conn, &["Old".to_string()]).await.unwrap();
+        TagManager::link_file_to_tag_on(&mut conn, 2, old, "path").await.unwrap();
+        let parts = ["Text".to_string(), "Plain".to_string()];
+        let text = TagManager::ensure_tags_on(&mut conn, TagCategory::Type, &parts).await.unwrap();
+        TagManager::link_file_to_tag_on(&mut conn, 2, text, "type").await.unwrap();
+        drop(conn);
+        sqlx::query("INSERT INTO tasks (file_id, task_type, status) VALUES (2, 'thumb', 2), (2, 'thumb', 3)")
+            .execute(&pool).await.unwrap();
+
+        let Json(detail) = get_file(State(pool.clone()), Path(2)).await.unwrap();
+        assert_eq!(detail.path, "Old/gone.txt");
+        assert_eq!(detail.library_name, "lib");
+        assert_eq!(detail.file.status, 0);
+        assert_eq!(detail.tags, vec![
+            FileTagItem { id: old, name: "Old".to_string(), category: "path".to_string(), path: vec!["Old".to_string()], source: "path".to_string() },
+            FileTagItem { id: text, name: "Plain".to_string(), category: "type".to_string(), path: parts.to_vec(), source: "type".to_string() },
+        ]);
+        // 已完成的任务不返回
+        assert_eq!(detail.tasks.iter().map(|t| t.status).collect::<Vec<_>>(), vec![3]);
+
+        let Json(online) = get_file(State(pool.clone()), Path(1)).await.unwrap();
+        assert!(online.tags.is_empty());
+        assert_eq!(get_file(State(pool.clone()), Path(99)).await.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 100))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 1000))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 1000))));
+        // 结束位置超出文件大小时截断
+        assert_eq!(parse_range("bytes=990-2000", 1000), Ok(Some((990, 1000))));
+        assert_eq!(parse_range("bytes=-5000", 1000), Ok(Some((0, 1000))));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        // 无法识别或多个区间时忽略
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=9-1", 1000), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_get_file_content() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("视频")).unwrap();
+        std::fs::write(dir.path().join("视频/片段 1.bin"), b"%PDF-1.7 0123456789").unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, extension, size, mtime, status) VALUES
+             (1, 1, '视频/', '片段 1.bin', 'bin', 19, 1, 1),
+             (2, 1, '', 'gone.txt', 'txt', 1, 1, 0)"
+        )
+        .execute(&pool).await.unwrap();
+
+        let request = |headers: &[(header::HeaderName, &str)]| {
+            let pool = pool.clone();
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.insert(name.clone(), value.parse().unwrap());
+            }
+            async move { get_file_content(State(pool), Path(1), Query(FileContentQuery::default()), map).await.unwrap() }
+        };
+        let body = |res: Response| async move { axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap() };
+
+        let full = request(&[]).await;
+        assert_eq!(full.status(), StatusCode::OK);
+        // 扩展名无法推断时按文件头识别
+        assert_eq!(full.headers()[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(
+            full.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"__ 1.bin\"; filename*=UTF-8''%E7%89%87%E6%AE%B5%201%2Ebin"
+        );
+        let etag = full.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert_eq!(body(full).await.as_ref(), b"%PDF-1.7 0123456789");
+
+        let partial = request(&[(header::RANGE, "bytes=9-")]).await;
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.headers()[header::CONTENT_RANGE], "bytes 9-18/19");
+        assert_eq!(partial.headers()[header::CONTENT_LENGTH], "10");
+        assert_eq!(body(partial).await.as_ref(), b"0123456789");
+
+        // If-Range 不匹配时返回完整内容
+        let stale = request(&[(header::RANGE, "bytes=9-"), (header::IF_RANGE, "\"old\"")]).await;
+        assert_eq!(stale.status(), StatusCode::OK);
+
+        assert_eq!(request(&[(header::RANGE, "bytes=19-")]).await.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(request(&[(header::IF_NONE_MATCH, &etag)]).await.status(), StatusCode::NOT_MODIFIED);
+
+        let lost = get_file_content(State(pool.clone()), Path(2), Query(FileContentQuery::default()), HeaderMap::new()).await;
+        assert_eq!(lost.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_active_content_is_downloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.html"), b"<script>alert(1)</script>").unwrap();
+        std::fs::write(dir.path().join("logo.svg"), b"<svg onload=\"alert(1)\"/>").unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), b"\xFF\xD8\xFF").unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, extension, size, mtime, status) VALUES
+             (1, 1, '', 'page.html', 'html', 25, 1, 1),
+             (2, 1, '', 'logo.svg', 'svg', 25, 1, 1),
+             (3, 1, '', 'photo.jpg', 'jpg', 3, 1, 1)"
+        )
+        .execute(&pool).await.unwrap();
+
+        let fetch = |id: i32| {
+            let pool = pool.clone();
+            async move { get_file_content(State(pool), Path(id), Query(FileContentQuery::default()), HeaderMap::new()).await.unwrap() }
+        };
+        let html = fetch(1).await;
+        assert_eq!(html.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(html.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"page.html\"; filename*=UTF-8''page%2Ehtml");
+        assert_eq!(html.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(html.headers()[header::CONTENT_SECURITY_POLICY], "sandbox");
+        assert!(fetch(2).await.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("attachment;"));
+
+        // 图片等被动内容仍可在页面中直接显示
+        let photo = fetch(3).await;
+        assert!(photo.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("inline;"));
+        assert_eq!(photo.headers()[header::CONTENT_SECURITY_POLICY], "sandbox");
+    }
+
+    #[tokio::test]
+    async fn test_get_file_content_with_token() {
+        use crate::api::auth::content_auth_middleware;
+        use crate::core::auth::create_jwt;
+        use axum::{middleware, routing::get, Router};
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("clip.mp4"), b"video").unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, extension, size, mtime, status) VALUES
+             (1, 1, '', 'clip.mp4', 'mp4', 5, 1, 1)"
+        )
+        .execute(&pool).await.unwrap();
+        let app = Router::new()
+            .route("/api/v1/files/:id/content", get(get_file_content))
+            .route_layer(middleware::from_fn(content_auth_middleware))
+            .with_state(pool.clone());
+        let fetch = |uri: String, bearer: Option<String>| {
+            let app = app.clone();
+            async move {
+                let mut req = axum::http::Request::get(uri);
+                if let Some(token) = bearer {
+                    req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+                }
+                app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap()
+            }
+        };
+
+        // <video src> 无法携带 Authorization 头，凭 ?token= 读取
+        let Json(issued) = create_file_content_token(
+            State(pool.clone()),
+            Extension(Claims { sub: "admin".to_string(), exp: usize::MAX }),
+            Path(1),
+        )
+        .await
+        .unwrap();
+        let res = fetch(format!("/api/v1/files/1/content?token={}", issued.token), None).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap().as_ref(), b"video");
+
+        // 令牌只对签发时的文件有效，登录令牌不能放在 URL 中
+        let other = create_content_token("admin", 2).unwrap();
+        assert_eq!(fetch(format!("/api/v1/files/1/content?token={}", other), None).await.status(), StatusCode::UNAUTHORIZED);
+        let login = create_jwt("admin").unwrap();
+        assert_eq!(fetch(format!("/api/v1/files/1/content?token={}", login), None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(fetch("/api/v1/files/1/content".to_string(), None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(fetch("/api/v1/files/1/content".to_string(), Some(login)).await.status(), StatusCode::OK);
+
+        let missing = create_file_content_token(
+            State(pool.clone()),
+            Extension(Claims { sub: "admin".to_string(), exp: usize::MAX }),
+            Path(9),
+        )
+        .await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
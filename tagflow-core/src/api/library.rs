@@ -3,14 +3,30 @@
 //! 提供资源库的 CRUD 操作、连接测试和扫描触发功能。
 
 use axum::{
-    extract::{Path as AxumPath, State},
+    extract::{Path as AxumPath, Query, State},
     http::StatusCode,
     Json,
 };
+use chrono::Utc;
+use serde::Deserialize;
 use sqlx::SqlitePool;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::models::dto::{CreateLibraryRequest, LibraryResponse, TestConnectionResponse};
+use crate::engine::purge::purge_lost_files;
+use crate::engine::retag_job::{RetagJobError, RetagJobManager};
+use crate::engine::scan_job::{ScanJobError, ScanJobManager};
+use crate::engine::scanner::filter::ScanFilter;
+use crate::engine::scheduler::ScanSchedule;
+use crate::engine::tagger::pipeline::BUILTIN_TAGGERS;
+use crate::engine::tagger::TaggingPipeline;
+use crate::engine::watcher::WatchManager;
+use crate::infra::storage::StorageManager;
+use crate::infra::thumbnail::THUMBNAIL_CACHE_DIR;
+use crate::models::db::{Library, LibraryConfig, RetagRun, ScanRun};
+use crate::models::dto::{
+    CreateLibraryRequest, LibraryResponse, PurgeResponse, RetagJobResponse, ScanJobResponse, ScanProgressResponse,
+    TestConnectionResponse, UpdateScheduleRequest,
+};
 
 /// 验证路径安全性（防止路径遍历攻击）
 ///
@@ -96,10 +112,26 @@ pub async fn list_libraries(
 /// }
 /// ```
 ///
+/// WebDAV 资源库的 `base_path` 为服务端上的目录，连接信息放在 `config_json` 中：
+/// ```json
+/// {
+///   "name": "NAS",
+///   "protocol": "webdav",
+///   "base_path": "/photos",
+///   "config_json": "{\"endpoint\":\"https://nas.local/dav\",\"username\":\"alice\",\"password\":\"secret\"}"
+/// }
+/// ```
+///
+/// 可选的 `scan_interval_secs` (秒) 或 `scan_cron` (cron 表达式，二选一) 用于开启定时扫描。
+///
 /// # 成功响应 (201)
 /// 无响应体
+///
+/// # 失败响应
+/// - 400: 协议无效、路径不安全、`config_json` 或扫描计划不合法
 pub async fn create_library(
     State(pool): State<SqlitePool>,
+    State(watchers): State<WatchManager>,
     Json(payload): Json<CreateLibraryRequest>,
 ) -> Result<StatusCode, StatusCode> {
     info!("创建资源库: name={}, protocol={}, path={}", payload.name, payload.protocol, payload.base_path);
@@ -116,22 +148,111 @@ pub async fn create_library(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    sqlx::query(
-        "INSERT INTO libraries (name, protocol, base_path, config_json)
-         VALUES (?, ?, ?, ?)"
+    // 扩展配置验证
+    let config = match LibraryConfig::parse(payload.config_json.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("资源库配置无效: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    if payload.protocol == "webdav" && config.endpoint.as_deref().is_none_or(str::is_empty) {
+        warn!("WebDAV 资源库缺少 endpoint 配置");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Err(e) = ScanFilter::from_config(&config) {
+        warn!("排除规则无效: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Err(e) = TaggingPipeline::for_library(&config) {
+        warn!("标签配置无效: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let schedule = match ScanSchedule::parse(payload.scan_interval_secs, payload.scan_cron.as_deref()) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            warn!("扫描计划无效: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let next_scan_at = schedule.and_then(|s| s.next_after(Utc::now()));
+
+    let library: Library = sqlx::query_as(
+        "INSERT INTO libraries (name, protocol, base_path, config_json, scan_interval_secs, scan_cron, next_scan_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING *"
     )
     .bind(&payload.name)
     .bind(&payload.protocol)
     .bind(&payload.base_path)
     .bind(&payload.config_json)
-    .execute(&pool)
+    .bind(payload.scan_interval_secs)
+    .bind(payload.scan_cron.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+    .bind(next_scan_at)
+    .fetch_one(&pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     info!("资源库创建成功: {}", payload.name);
+
+    // 启用监听的本地资源库立即开始监听；失败不影响创建结果
+    if let Err(e) = watchers.start(library) {
+        warn!("资源库 {} 启动监听失败: {}", payload.name, e);
+    }
     Ok(StatusCode::CREATED)
 }
 
+/// 更新资源库定时扫描计划
+///
+/// # 路由
+/// PUT /api/v1/libraries/:id/schedule
+///
+/// # 请求体
+/// ```json
+/// { "scan_interval_secs": null, "scan_cron": "0 3 * * *" }
+/// ```
+/// 两项至多设置其一，均为空表示关闭定时扫描。
+///
+/// # 成功响应 (200)
+/// 更新后的资源库，`next_scan_at` 为重新计算的下次扫描时间
+///
+/// # 失败响应
+/// - 400: 扫描计划不合法
+/// - 404: 资源库不存在
+pub async fn update_schedule(
+    State(pool): State<SqlitePool>,
+    AxumPath(id): AxumPath<i32>,
+    Json(payload): Json<UpdateScheduleRequest>,
+) -> Result<Json<LibraryResponse>, StatusCode> {
+    let schedule = match ScanSchedule::parse(payload.scan_interval_secs, payload.scan_cron.as_deref()) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            warn!("扫描计划无效: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let next_scan_at = schedule.and_then(|s| s.next_after(Utc::now()));
+
+    let library: Library = sqlx::query_as(
+        "UPDATE libraries SET scan_interval_secs = ?, scan_cron = ?, next_scan_at = ?
+         WHERE id = ? RETURNING *"
+    )
+    .bind(payload.scan_interval_secs)
+    .bind(payload.scan_cron.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+    .bind(next_scan_at)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        error!("更新扫描计划失败: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!("资源库 {} 扫描计划已更新，下次扫描: {:?}", id, library.next_scan_at);
+    Ok(Json(library.into()))
+}
+
 /// 删除资源库
 ///
 /// # 路由
@@ -145,10 +266,13 @@ pub async fn create_library(
 /// - 500: 服务器错误
 pub async fn delete_library(
     State(pool): State<SqlitePool>,
+    State(watchers): State<WatchManager>,
     AxumPath(id): AxumPath<i32>,
 ) -> StatusCode {
     info!("删除资源库: id={}", id);
 
+    watchers.stop(id);
+
     let result = sqlx::query("DELETE FROM libraries WHERE id = ?")
         .bind(id)
         .execute(&pool)
@@ -231,12 +355,7 @@ pub async fn test_library_connection(
 
         Json(TestConnectionResponse { reachable, message })
     } else if payload.protocol == "webdav" {
-        warn!("WebDAV 协议暂未实现");
-        // WebDAV 暂不支持
-        Json(TestConnectionResponse {
-            reachable: false,
-            message: "WebDAV 协议暂未实现".to_string(),
-        })
+        Json(test_webdav_connection(&payload).await)
     } else {
         warn!("不支持的协议类型: {}", payload.protocol);
         Json(TestConnectionResponse {
@@ -246,6 +365,40 @@ pub async fn test_library_connection(
     }
 }
 
+/// 测试 WebDAV 连接：构建算子并列举根目录
+async fn test_webdav_connection(payload: &CreateLibraryRequest) -> TestConnectionResponse {
+    let op = LibraryConfig::parse(payload.config_json.as_deref())
+        .and_then(|config| StorageManager::build_operator("webdav", &payload.base_path, &config));
+
+    let op = match op {
+        Ok(op) => op,
+        Err(e) => {
+            warn!("WebDAV 配置无效: {}", e);
+            return TestConnectionResponse {
+                reachable: false,
+                message: e.to_string(),
+            };
+        }
+    };
+
+    match op.check().await {
+        Ok(_) => {
+            info!("WebDAV 连接测试成功: {}", payload.base_path);
+            TestConnectionResponse {
+                reachable: true,
+                message: "WebDAV 服务可访问".to_string(),
+            }
+        }
+        Err(e) => {
+            warn!("WebDAV 连接失败: {} - {}", payload.base_path, e);
+            TestConnectionResponse {
+                reachable: false,
+                message: format!("WebDAV 连接失败: {}", e),
+            }
+        }
+    }
+}
+
 /// 手动触发资源库扫描
 ///
 /// # 路由
@@ -253,16 +406,23 @@ pub async fn test_library_connection(
 ///
 /// # 成功响应 (202)
 /// 扫描任务已接受，将在后台异步执行
+/// ```json
+/// {
+///   "job_id": 1,
+///   "library_id": 1
+/// }
+/// ```
 ///
 /// # 失败响应
 /// - 404: 资源库不存在
-/// - 501: 扫描功能未实现
+/// - 409: 该资源库已有扫描正在进行
 pub async fn trigger_scan(
     State(pool): State<SqlitePool>,
+    State(scans): State<ScanJobManager>,
     AxumPath(id): AxumPath<i32>,
-) -> StatusCode {
+) -> Result<(StatusCode, Json<ScanJobResponse>), StatusCode> {
     // 获取资源库配置
-    let _library = match sqlx::query_as::<_, crate::models::db::Library>(
+    let library = match sqlx::query_as::<_, crate::models::db::Library>(
         "SELECT * FROM libraries WHERE id = ?"
     )
     .bind(id)
@@ -270,11 +430,283 @@ pub async fn trigger_scan(
     .await
     {
         Ok(Some(lib)) => lib,
-        Ok(None) => return StatusCode::NOT_FOUND,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
 
-    // TODO: 实现扫描功能
-    // 当前扫描功能尚未完全实现，返回 NOT_IMPLEMENTED
-    StatusCode::NOT_IMPLEMENTED
+    match scans.enqueue(library).await {
+        Ok(job_id) => {
+            info!("扫描任务已提交: library={}, job={}", id, job_id);
+            Ok((StatusCode::ACCEPTED, Json(ScanJobResponse { job_id, library_id: id })))
+        }
+        Err(e @ ScanJobError::AlreadyRunning { .. }) => {
+            warn!("{}", e);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            error!("{}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 扫描历史查询参数
+#[derive(Debug, Deserialize)]
+pub struct ScanRunQuery {
+    pub limit: Option<i64>,
+}
+
+/// 获取资源库的扫描历史（按时间倒序）
+///
+/// # 路由
+/// GET /api/v1/libraries/:id/scans?limit=20
+///
+/// # 成功响应 (200)
+/// ```json
+/// [
+///   {
+///     "id": 3,
+///     "library_id": 1,
+///     "status": 2,
+///     "started_at": "2026-01-05T08:00:00Z",
+///     "finished_at": "2026-01-05T08:03:12Z",
+///     "files_seen": 12000,
+///     "files_added": 35,
+///     "files_modified": 4,
+///     "files_lost": 1,
+///     "errors": 0,
+///     "error_msg": null,
+///     "files_moved": 2,
+///     "files_restored": 0
+///   }
+/// ]
+/// ```
+/// `status`: 1=进行中, 2=已完成, 3=失败
+pub async fn list_scan_runs(
+    State(pool): State<SqlitePool>,
+    AxumPath(id): AxumPath<i32>,
+    Query(query): Query<ScanRunQuery>,
+) -> Result<Json<Vec<ScanRun>>, StatusCode> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 200);
+
+    let runs = sqlx::query_as::<_, ScanRun>(
+        "SELECT * FROM scan_runs WHERE library_id = ? ORDER BY id DESC LIMIT ?"
+    )
+    .bind(id)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(runs))
+}
+
+/// 获取资源库当前扫描的实时进度
+///
+/// # 路由
+/// GET /api/v1/libraries/:id/scans/current
+///
+/// # 成功响应 (200)
+/// ```json
+/// {
+///   "job_id": 4,
+///   "library_id": 1,
+///   "started_at": "2026-01-05T09:00:00Z",
+///   "expected_total": 12000,
+///   "files_seen": 5230,
+///   "files_added": 12,
+///   "files_modified": 0,
+///   "files_moved": 0,
+///   "files_restored": 0,
+///   "files_lost": 0,
+///   "errors": 0
+/// }
+/// ```
+///
+/// # 失败响应
+/// - 404: 当前没有正在进行的扫描
+pub async fn get_scan_progress(
+    State(scans): State<ScanJobManager>,
+    AxumPath(id): AxumPath<i32>,
+) -> Result<Json<ScanProgressResponse>, StatusCode> {
+    let scan = scans.running_scan(id).await.ok_or(StatusCode::NOT_FOUND)?;
+    let stats = scan.progress.stats();
+
+    Ok(Json(ScanProgressResponse {
+        job_id: scan.job_id,
+        library_id: id,
+        started_at: scan.started_at,
+        expected_total: scan.progress.expected_total(),
+        files_seen: stats.files_seen,
+        files_added: stats.files_added,
+        files_modified: stats.files_modified,
+        files_moved: stats.files_moved,
+        files_restored: stats.files_restored,
+        files_lost: stats.files_lost,
+        errors: stats.errors,
+    }))
+}
+
+/// 清理丢失文件的查询参数
+#[derive(Debug, Deserialize)]
+pub struct PurgeQuery {
+    /// 只清理丢失超过该天数的文件；缺省时使用资源库的 `lost_retention_days`，两者都未给出时拒绝请求
+    ///
+    /// 暂时卸载的磁盘上的文件也会被标记为丢失，因此不提供隐式的"清理全部"，需显式传 `older_than_days=0`
+    pub older_than_days: Option<u32>,
+}
+
+/// 清理资源库中的丢失文件
+///
+/// 删除文件记录及其标签关联与缓存的缩略图，操作不可撤销。
+///
+/// # 路由
+/// POST /api/v1/libraries/:id/purge-lost?older_than_days=30
+///
+/// # 成功响应 (200)
+/// ```json
+/// { "files_purged": 12, "thumbnails_removed": 8 }
+/// ```
+///
+/// # 失败响应
+/// - 400: 未指定 `older_than_days` 且资源库未配置 `lost_retention_days`
+/// - 404: 资源库不存在
+/// - 409: 该资源库正在扫描
+pub async fn purge_lost(
+    State(pool): State<SqlitePool>,
+    State(scans): State<ScanJobManager>,
+    AxumPath(id): AxumPath<i32>,
+    Query(query): Query<PurgeQuery>,
+) -> Result<Json<PurgeResponse>, StatusCode> {
+    let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let Some(older_than_days) = query
+        .older_than_days
+        .or_else(|| library.config().ok().and_then(|c| c.lost_retention_days))
+    else {
+        warn!("资源库 {} 未配置丢失文件保留天数，拒绝清理", id);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // 与扫描互斥，避免删除正在被移动检测认领的记录
+    let lock = scans.library_lock(id);
+    let Ok(_guard) = lock.try_lock() else {
+        warn!("资源库 {} 正在扫描，无法清理丢失文件", id);
+        return Err(StatusCode::CONFLICT);
+    };
+
+    let stats = purge_lost_files(&pool, id, older_than_days, THUMBNAIL_CACHE_DIR)
+        .await
+        .map_err(|e| {
+            error!("清理丢失文件失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(PurgeResponse {
+        files_purged: stats.files_purged,
+        thumbnails_removed: stats.thumbnails_removed,
+    }))
+}
+
+/// 重新打标签的查询参数
+#[derive(Debug, Deserialize)]
+pub struct RetagQuery {
+    /// 只替换该标签器 (`path`、`type`、`time`、`rule`) 的输出；缺省时替换全部
+    pub tagger: Option<String>,
+}
+
+/// 对资源库中的在线文件重新运行标签管道
+///
+/// 只替换标签器自己生成的标签，手动标签不受影响；指定的标签器已被禁用时删除它此前生成的标签。
+/// 需要读取文件头，大型远程资源库可能耗时较长，因此在后台执行；资源库正在扫描时等待扫描结束。
+///
+/// # 路由
+/// POST /api/v1/libraries/:id/retag?tagger=time
+///
+/// # 成功响应 (202)
+/// ```json
+/// { "job_id": 5, "library_id": 1 }
+/// ```
+/// 通过 `GET /api/v1/retag-jobs/:job_id` 查询任务状态。
+///
+/// # 失败响应
+/// - 400: 未知的标签器
+/// - 404: 资源库不存在
+/// - 409: 该资源库已有重新打标签任务在进行
+pub async fn retag(
+    State(pool): State<SqlitePool>,
+    State(retags): State<RetagJobManager>,
+    AxumPath(id): AxumPath<i32>,
+    Query(query): Query<RetagQuery>,
+) -> Result<(StatusCode, Json<RetagJobResponse>), StatusCode> {
+    if let Some(tagger) = query.tagger.as_deref()
+        && !BUILTIN_TAGGERS.contains(&tagger)
+    {
+        warn!("未知的标签器: {}", tagger);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM libraries WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match retags.enqueue(Some(id), query.tagger.as_deref()).await {
+        Ok(job_id) => {
+            info!("重新打标签任务已提交: library={}, job={}", id, job_id);
+            Ok((StatusCode::ACCEPTED, Json(RetagJobResponse { job_id, library_id: Some(id) })))
+        }
+        Err(e @ RetagJobError::AlreadyRunning { .. }) => {
+            warn!("{}", e);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            error!("{}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 查询重新打标签任务
+///
+/// # 路由
+/// GET /api/v1/retag-jobs/:id
+///
+/// # 成功响应 (200)
+/// ```json
+/// {
+///   "id": 5,
+///   "library_id": 1,
+///   "tagger": "time",
+///   "status": 2,
+///   "started_at": "2026-02-01T08:00:00Z",
+///   "finished_at": "2026-02-01T08:12:40Z",
+///   "files_retagged": 1024,
+///   "error_msg": null
+/// }
+/// ```
+/// `status`: 1=进行中, 2=已完成, 3=失败；进行中时 `files_retagged` 为已完成的资源库累计处理的文件数
+///
+/// # 失败响应
+/// - 404: 任务不存在
+pub async fn get_retag_job(
+    State(pool): State<SqlitePool>,
+    AxumPath(id): AxumPath<i64>,
+) -> Result<Json<RetagRun>, StatusCode> {
+    sqlx::query_as("SELECT * FROM retag_runs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
 }
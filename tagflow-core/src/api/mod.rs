@@ -2,3 +2,57 @@ pub mod tag;
 pub mod file;
 pub mod auth;
 pub mod library;
+pub mod rule;
+pub mod search;
+pub mod archive;
+
+use axum::extract::FromRef;
+use sqlx::SqlitePool;
+
+use crate::engine::retag_job::RetagJobManager;
+use crate::engine::scan_job::ScanJobManager;
+use crate::engine::watcher::WatchManager;
+
+/// API 共享状态
+///
+/// 通过 `FromRef` 让处理函数按需提取 `State<SqlitePool>`、`State<ScanJobManager>` 等。
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub scans: ScanJobManager,
+    pub watchers: WatchManager,
+    pub retags: RetagJobManager,
+}
+
+impl AppState {
+    pub fn new(pool: SqlitePool) -> Self {
+        let scans = ScanJobManager::new(pool.clone());
+        let watchers = WatchManager::new(pool.clone(), scans.clone());
+        let retags = RetagJobManager::new(pool.clone(), scans.clone());
+        Self { pool, scans, watchers, retags }
+    }
+}
+
+impl FromRef<AppState> for SqlitePool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for ScanJobManager {
+    fn from_ref(state: &AppState) -> Self {
+        state.scans.clone()
+    }
+}
+
+impl FromRef<AppState> for WatchManager {
+    fn from_ref(state: &AppState) -> Self {
+        state.watchers.clone()
+    }
+}
+
+impl FromRef<AppState> for RetagJobManager {
+    fn from_ref(state: &AppState) -> Self {
+        state.retags.clone()
+    }
+}
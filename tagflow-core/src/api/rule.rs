@@ -0,0 +1,381 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::engine::retag_job::{RetagJobError, RetagJobManager};
+use crate::engine::tagger::rule::CompiledRule;
+use crate::models::db::TagRule;
+use crate::models::dto::{RetagJobResponse, TagRuleQuery, TagRuleRequest, TagRuleResponse};
+
+/// 列出标签规则
+///
+/// # 路由
+/// GET /api/v1/rules?library_id=1
+///
+/// # 成功响应 (200)
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "library_id": null,
+///     "name": "发票",
+///     "conditions": { "filename_regex": null, "path_glob": "**/invoices/**", "extensions": ["pdf"] },
+///     "tag_path": "Finance/Invoices",
+///     "enabled": true,
+///     "created_at": "2026-01-22T08:00:00Z"
+///   }
+/// ]
+/// ```
+/// 指定 `library_id` 时返回该资源库的规则与全局规则。
+pub async fn list_rules(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<TagRuleQuery>,
+) -> Result<Json<Vec<TagRuleResponse>>, StatusCode> {
+    let rules: Vec<TagRule> = sqlx::query_as(
+        "SELECT * FROM tag_rules WHERE ?1 IS NULL OR library_id IS NULL OR library_id = ?1 ORDER BY id"
+    )
+    .bind(query.library_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rules.into_iter().map(Into::into).collect()))
+}
+
+/// 创建标签规则
+///
+/// 新规则在下次扫描时对新增与修改的文件生效；已有文件需调用
+/// `POST /api/v1/rules/apply` 重新应用。
+///
+/// # 路由
+/// POST /api/v1/rules
+///
+/// # 请求体
+/// ```json
+/// {
+///   "name": "发票",
+///   "library_id": 1,
+///   "conditions": { "path_glob": "**/invoices/**", "extensions": ["pdf"] },
+///   "tag_path": "Finance/Invoices"
+/// }
+/// ```
+///
+/// # 响应
+/// - 201: 创建成功，返回规则
+/// - 400: 条件无效 (正则/通配语法错误、没有条件、标签路径为空) 或资源库不存在
+pub async fn create_rule(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<TagRuleRequest>,
+) -> Result<(StatusCode, Json<TagRuleResponse>), StatusCode> {
+    let (conditions, tag_path) = validate_rule(&pool, &payload).await?;
+
+    let rule: TagRule = sqlx::query_as(
+        "INSERT INTO tag_rules (library_id, name, conditions, tag_path, enabled) VALUES (?, ?, ?, ?, ?) RETURNING *"
+    )
+    .bind(payload.library_id)
+    .bind(payload.name.trim())
+    .bind(conditions)
+    .bind(tag_path)
+    .bind(payload.enabled)
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| {
+        error!("创建标签规则失败: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("标签规则 #{} ({}) 已创建", rule.id, rule.name);
+    Ok((StatusCode::CREATED, Json(rule.into())))
+}
+
+/// 更新标签规则 (整体替换)
+///
+/// # 路由
+/// PUT /api/v1/rules/:id
+///
+/// # 响应
+/// - 200: 返回更新后的规则
+/// - 400: 条件无效
+/// - 404: 规则不存在
+pub async fn update_rule(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i32>,
+    Json(payload): Json<TagRuleRequest>,
+) -> Result<Json<TagRuleResponse>, StatusCode> {
+    let (conditions, tag_path) = validate_rule(&pool, &payload).await?;
+
+    let rule: TagRule = sqlx::query_as(
+        "UPDATE tag_rules SET library_id = ?, name = ?, conditions = ?, tag_path = ?, enabled = ?
+         WHERE id = ? RETURNING *"
+    )
+    .bind(payload.library_id)
+    .bind(payload.name.trim())
+    .bind(conditions)
+    .bind(tag_path)
+    .bind(payload.enabled)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        error!("更新标签规则失败: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(rule.into()))
+}
+
+/// 删除标签规则
+///
+/// 已由该规则生成的标签在重新应用规则后移除。
+///
+/// # 路由
+/// DELETE /api/v1/rules/:id
+///
+/// # 响应
+/// - 204: 删除成功
+/// - 404: 规则不存在
+pub async fn delete_rule(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i32>,
+) -> StatusCode {
+    match sqlx::query("DELETE FROM tag_rules WHERE id = ?").bind(id).execute(&pool).await {
+        Ok(res) if res.rows_affected() > 0 => StatusCode::NO_CONTENT,
+        Ok(_) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// 对资源库中的已有文件重新应用规则
+///
+/// 只运行规则标签器，不读取文件内容，也只替换来源为 `rule` 的标签，其他标签不受影响。在后台逐个资源库执行，
+/// 正在扫描的资源库等待扫描结束后再处理。
+///
+/// # 路由
+/// POST /api/v1/rules/apply?library_id=1
+///
+/// # 成功响应 (202)
+/// ```json
+/// { "job_id": 6, "library_id": 1 }
+/// ```
+/// 缺省 `library_id` 时应用到所有资源库。通过 `GET /api/v1/retag-jobs/:job_id` 查询任务状态。
+///
+/// # 失败响应
+/// - 404: 资源库不存在
+/// - 409: 相同范围的任务正在进行
+pub async fn apply_rules(
+    State(pool): State<SqlitePool>,
+    State(retags): State<RetagJobManager>,
+    Query(query): Query<TagRuleQuery>,
+) -> Result<(StatusCode, Json<RetagJobResponse>), StatusCode> {
+    if let Some(library_id) = query.library_id {
+        let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM libraries WHERE id = ?")
+            .bind(library_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if exists.is_none() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    match retags.enqueue(query.library_id, Some("rule")).await {
+        Ok(job_id) => {
+            info!("重新应用规则任务已提交: library={:?}, job={}", query.library_id, job_id);
+            Ok((StatusCode::ACCEPTED, Json(RetagJobResponse { job_id, library_id: query.library_id })))
+        }
+        Err(e @ RetagJobError::AlreadyRunning { .. }) => {
+            warn!("{}", e);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            error!("{}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 校验规则，返回序列化后的条件与规范化的标签路径
+async fn validate_rule(pool: &SqlitePool, payload: &TagRuleRequest) -> Result<(String, String), StatusCode> {
+    if payload.name.trim().is_empty() {
+        warn!("标签规则名称不能为空");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Err(e) = CompiledRule::new(&payload.conditions, &payload.tag_path) {
+        warn!("标签规则无效: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(library_id) = payload.library_id {
+        let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM libraries WHERE id = ?")
+            .bind(library_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if exists.is_none() {
+            warn!("标签规则引用的资源库不存在: {}", library_id);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let conditions = serde_json::to_string(&payload.conditions).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let tag_path = payload
+        .tag_path
+        .split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok((conditions, tag_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::scan_job::{ScanJobManager, ScanRunStatus};
+    use crate::engine::retag::retag_library;
+    use crate::engine::scanner::Scanner;
+    use crate::engine::tagger::TaggingPipeline;
+    use crate::infra::db::test_pool;
+    use crate::models::db::{Library, RetagRun, RuleConditions};
+
+    fn request(conditions: RuleConditions, tag_path: &str) -> TagRuleRequest {
+        TagRuleRequest {
+            name: "发票".to_string(),
+            library_id: None,
+            conditions,
+            tag_path: tag_path.to_string(),
+            enabled: true,
+        }
+    }
+
+    async fn rule_tags(pool: &SqlitePool) -> Vec<(String, String)> {
+        sqlx::query_as(
+            "SELECT f.filename, t.name FROM file_tags ft
+             JOIN files f ON f.id = ft.file_id JOIN tags t ON t.id = ft.tag_id
+             WHERE ft.source = 'rule' ORDER BY f.filename"
+        )
+        .fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rules_apply_at_scan_and_reapply() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("2024/invoices")).unwrap();
+        std::fs::write(dir.path().join("2024/invoices/march.pdf"), b"%PDF").unwrap();
+        std::fs::write(dir.path().join("2024/invoices/notes.txt"), b"notes").unwrap();
+        std::fs::write(dir.path().join("IMG_0001.jpg"), b"jpg").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = 1").fetch_one(&pool).await.unwrap();
+
+        let invoices = RuleConditions {
+            path_glob: Some("**/invoices/**".to_string()),
+            extensions: vec!["pdf".to_string()],
+            ..Default::default()
+        };
+        let (status, Json(rule)) = create_rule(State(pool.clone()), Json(request(invoices, " Finance / Invoices ")))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(rule.tag_path, "Finance/Invoices");
+
+        // 扫描时由标签管道应用规则
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+        assert_eq!(rule_tags(&pool).await, vec![("march.pdf".to_string(), "Invoices".to_string())]);
+        let category: String = sqlx::query_scalar("SELECT category FROM tags WHERE name = 'Invoices'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(category, "user");
+
+        // 修改规则后重新应用，只替换规则来源的标签
+        let camera = RuleConditions { filename_regex: Some(r"^IMG_\d+".to_string()), ..Default::default() };
+        let Json(updated) = update_rule(State(pool.clone()), Path(rule.id), Json(request(camera, "Camera"))).await.unwrap();
+        assert_eq!(updated.tag_path, "Camera");
+        let retags = RetagJobManager::new(pool.clone(), ScanJobManager::new(pool.clone()));
+        let (status, Json(job)) = apply_rules(
+            State(pool.clone()),
+            State(retags.clone()),
+            Query(TagRuleQuery { library_id: Some(1) }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        for _ in 0..100 {
+            if retags.running_job(Some(1)).await.is_none() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let run: RetagRun = sqlx::query_as("SELECT * FROM retag_runs WHERE id = ?")
+            .bind(job.job_id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!((run.status, run.files_retagged), (ScanRunStatus::Completed as i32, 3));
+        assert_eq!(rule_tags(&pool).await, vec![("IMG_0001.jpg".to_string(), "Camera".to_string())]);
+        let path_links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_tags WHERE source = 'path'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(path_links, 2);
+
+        assert_eq!(delete_rule(State(pool.clone()), Path(rule.id)).await, StatusCode::NO_CONTENT);
+        assert_eq!(delete_rule(State(pool.clone()), Path(rule.id)).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_reapply_leaves_type_and_time_tags_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cover.psd"), b"8BPS").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = 1").fetch_one(&pool).await.unwrap();
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let auto_tags = || sqlx::query_as::<_, (String, String)>(
+            "SELECT ft.source, t.name FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+             WHERE ft.source IN ('type', 'time') ORDER BY ft.source, t.name"
+        )
+        .fetch_all(&pool);
+        let before = auto_tags().await.unwrap();
+        assert!(before.iter().any(|(source, name)| source == "type" && name == "Other"));
+        assert!(before.iter().any(|(source, _)| source == "time"));
+
+        // 类型映射变化且文件内容不可读：若重新应用规则时运行了类型/时间标签器，其结果会随之改变
+        sqlx::query(r#"UPDATE libraries SET config_json = '{"type_mapping":{"psd":"Image/Design"}}' WHERE id = 1"#)
+            .execute(&pool).await.unwrap();
+        std::fs::remove_file(dir.path().join("cover.psd")).unwrap();
+
+        let psd = RuleConditions { extensions: vec!["psd".to_string()], ..Default::default() };
+        let (status, _) = create_rule(State(pool.clone()), Json(request(psd, "Design"))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = 1").fetch_one(&pool).await.unwrap();
+        let pipeline = TaggingPipeline::load_for_source(&pool, &library, "rule").await.unwrap();
+        assert_eq!(pipeline.sources(), vec!["rule"]);
+        retag_library(&pool, &library, Some("rule")).await.unwrap();
+
+        assert_eq!(rule_tags(&pool).await, vec![("cover.psd".to_string(), "Design".to_string())]);
+        assert_eq!(auto_tags().await.unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_rule_is_rejected() {
+        let pool = test_pool().await;
+        let bad_regex = RuleConditions { filename_regex: Some("(".to_string()), ..Default::default() };
+        let res = create_rule(State(pool.clone()), Json(request(bad_regex, "A"))).await;
+        assert_eq!(res.unwrap_err(), StatusCode::BAD_REQUEST);
+
+        let res = create_rule(State(pool.clone()), Json(request(RuleConditions::default(), "A"))).await;
+        assert_eq!(res.unwrap_err(), StatusCode::BAD_REQUEST);
+
+        let mut missing_library = request(RuleConditions { extensions: vec!["pdf".to_string()], ..Default::default() }, "A");
+        missing_library.library_id = Some(42);
+        let res = create_rule(State(pool.clone()), Json(missing_library)).await;
+        assert_eq!(res.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+}
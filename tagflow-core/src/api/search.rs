@@ -0,0 +1,146 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::{QueryBuilder, SqlitePool};
+use tracing::error;
+
+use crate::api::auth::ErrorResponse;
+use crate::core::search::SearchTerms;
+use crate::core::tag::query::{FileFilter, TagExpr};
+use crate::models::db::FileEntry;
+use crate::models::dto::{SearchQuery, SearchResponse};
+
+/// 按文件名与路径搜索
+///
+/// # 路由
+/// GET /api/v1/search?q=年度 报告&tags=type:Document&library_id=1&page=1&limit=50
+///
+/// # 查询参数
+/// - `q`: 搜索词，以空白分隔，全部命中才算匹配；每个词可匹配文件名或路径的任意位置
+/// - `tag_id` / `recursive` / `tags` / `library_id` / `status`: 过滤条件，与
+///   `GET /api/v1/files` 的 `tag_id` / `recursive` / `q` / `status` 含义相同
+///
+/// # 成功响应 (200)
+/// ```json
+/// { "items": [...], "total": 12 }
+/// ```
+/// 按相关度排序 (文件名命中优先)，相关度相同时按修改时间倒序。
+///
+/// # 失败响应
+/// - 400: 没有有效的搜索词，或 `tags` 语法错误
+pub async fn search_files(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let internal_error = |e: sqlx::Error| {
+        error!("搜索文件失败: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "搜索文件失败".to_string() }))
+    };
+
+    let terms = SearchTerms::parse(&query.q).ok_or_else(|| bad_request("搜索词不能为空".to_string()))?;
+    let expr = query
+        .tags
+        .as_deref()
+        .map(TagExpr::parse)
+        .transpose()
+        .map_err(|e| bad_request(e.to_string()))?;
+    let filter = FileFilter {
+        tag_id: query.tag_id,
+        recursive: query.recursive.unwrap_or(true),
+        expr,
+        library_id: query.library_id,
+        status: query.status,
+        ..Default::default()
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let offset = (query.page.unwrap_or(1).max(1) - 1) * limit;
+
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM files f");
+    terms.push_join(&mut qb);
+    filter.push_where(&mut qb);
+    terms.push_conditions(&mut qb);
+    let total: i64 = qb.build_query_scalar().fetch_one(&pool).await.map_err(internal_error)?;
+
+    let mut qb = QueryBuilder::new("SELECT f.* FROM files f");
+    terms.push_join(&mut qb);
+    filter.push_where(&mut qb);
+    terms.push_conditions(&mut qb);
+    terms.push_order(&mut qb);
+    qb.push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    let items: Vec<FileEntry> = qb.build_query_as().fetch_all(&pool).await.map_err(internal_error)?;
+
+    Ok(Json(SearchResponse { items: items.into_iter().map(Into::into).collect(), total }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tag::TagManager;
+    use crate::infra::db::test_pool;
+    use crate::models::db::TagCategory;
+    use crate::models::dto::FileStatusFilter;
+
+    fn query(q: &str) -> SearchQuery {
+        SearchQuery {
+            q: q.to_string(),
+            tag_id: None,
+            recursive: None,
+            tags: None,
+            library_id: None,
+            status: FileStatusFilter::Online,
+            page: None,
+            limit: None,
+        }
+    }
+
+    async fn ids(pool: &SqlitePool, query: SearchQuery) -> Vec<i32> {
+        let Json(res) = search_files(State(pool.clone()), Query(query)).await.unwrap();
+        assert_eq!(res.total, res.items.len() as i64);
+        res.items.iter().map(|i| i.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_files() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime) VALUES
+             (1, 1, '财务/2024年度报告/', 'summary.pdf', 1, 4),
+             (2, 1, '财务/', '2024年度报告终稿.docx', 1, 3),
+             (3, 1, '合同/', '采购合同.pdf', 1, 2),
+             (4, 1, 'Work/', 'Report-Q1.xlsx', 1, 1)"
+        )
+        .execute(&pool).await.unwrap();
+
+        // 文件名命中排在路径命中之前
+        assert_eq!(ids(&pool, query("年度报告")).await, vec![2, 1]);
+        // 前缀与大小写不敏感
+        assert_eq!(ids(&pool, query("repo*")).await, vec![4]);
+        // 两个字的中文词走 LIKE，可与索引词组合
+        assert_eq!(ids(&pool, query("合同")).await, vec![3]);
+        assert_eq!(ids(&pool, query("合同 .pdf")).await, vec![3]);
+        assert!(ids(&pool, query("不存在的文件")).await.is_empty());
+
+        // 索引随文件改名与删除同步
+        sqlx::query("UPDATE files SET filename = '销售合同.pdf' WHERE id = 3").execute(&pool).await.unwrap();
+        assert!(ids(&pool, query("采购合同")).await.is_empty());
+        assert_eq!(ids(&pool, query("销售合同")).await, vec![3]);
+        sqlx::query("DELETE FROM files WHERE id = 2").execute(&pool).await.unwrap();
+        assert_eq!(ids(&pool, query("年度报告")).await, vec![1]);
+
+        // 与标签过滤组合
+        let mut conn = pool.acquire().await.unwrap();
+        let pdf = TagManager::ensure_tags_on(&mut conn, TagCategory::Type, &["Document".to_string(), "PDF".to_string()]).await.unwrap();
+        TagManager::link_file_to_tag_on(&mut conn, 1, pdf, "type").await.unwrap();
+        drop(conn);
+        assert_eq!(ids(&pool, SearchQuery { tags: Some("type:Document".to_string()), ..query("pdf") }).await, vec![1]);
+        assert_eq!(ids(&pool, SearchQuery { tags: Some("NOT type:Document".to_string()), ..query("pdf") }).await, vec![3]);
+
+        let err = search_files(State(pool.clone()), Query(query(" * "))).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+    }
+}
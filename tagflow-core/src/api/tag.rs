@@ -1,6 +1,16 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
 use sqlx::SqlitePool;
-use crate::models::dto::TagNode;
+use tracing::{error, info, warn};
+
+use crate::core::tag::query::{FileFilter, TagExpr};
+use crate::core::tag::{BulkAction, FileSelection, TagError, TagManager};
+use crate::models::dto::{
+    AttachTagRequest, BulkFileQuery, BulkTagAction, BulkTagRequest, BulkTagResponse, CreateTagRequest, TagNode, UpdateTagRequest,
+};
 use crate::models::db::Tag;
 
 pub async fn get_tag_tree(State(pool): State<SqlitePool>) -> Json<Vec<TagNode>> {
@@ -26,3 +36,203 @@ fn build_tree(tags: &[Tag], parent_id: Option<i32>) -> Vec<TagNode> {
         })
         .collect()
 }
+
+/// 将标签操作错误映射为状态码
+fn tag_error_status(e: TagError) -> StatusCode {
+    match e {
+        TagError::NotFound(_) => StatusCode::NOT_FOUND,
+        TagError::Duplicate(_) => {
+            warn!("{}", e);
+            StatusCode::CONFLICT
+        }
+        TagError::NotUserTag(_) | TagError::Cycle(_) | TagError::InvalidName(_) | TagError::InvalidSelection(_) => {
+            warn!("{}", e);
+            StatusCode::BAD_REQUEST
+        }
+        TagError::Database(e) => {
+            error!("标签操作失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// 创建用户标签
+///
+/// # 路由
+/// POST /api/v1/tags
+///
+/// # 请求体
+/// ```json
+/// { "name": "Invoices", "parent_id": 12 }
+/// ```
+///
+/// # 响应
+/// - 201: 创建成功，返回标签
+/// - 400: 名称为空或包含 `/`，或父标签不是用户标签
+/// - 404: 父标签不存在
+/// - 409: 同一父标签下已存在同名标签
+pub async fn create_tag(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateTagRequest>,
+) -> Result<(StatusCode, Json<Tag>), StatusCode> {
+    let tag = TagManager::new(pool)
+        .create_user_tag(&payload.name, payload.parent_id)
+        .await
+        .map_err(tag_error_status)?;
+    Ok((StatusCode::CREATED, Json(tag)))
+}
+
+/// 重命名或移动用户标签
+///
+/// # 路由
+/// PATCH /api/v1/tags/:id
+///
+/// # 请求体
+/// ```json
+/// { "name": "Receipts", "parent_id": null }
+/// ```
+/// 省略 `parent_id` 表示不移动，`null` 表示移动到根级。
+///
+/// # 响应
+/// - 200: 返回修改后的标签
+/// - 400: 名称无效、非用户标签，或新的父标签是自身或其子标签
+/// - 404: 标签或父标签不存在
+/// - 409: 目标位置已存在同名标签
+pub async fn update_tag(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i32>,
+    Json(payload): Json<UpdateTagRequest>,
+) -> Result<Json<Tag>, StatusCode> {
+    let tag = TagManager::new(pool)
+        .update_user_tag(id, payload.name.as_deref(), payload.parent_id)
+        .await
+        .map_err(tag_error_status)?;
+    Ok(Json(tag))
+}
+
+/// 删除用户标签 (连同子标签与文件关联)
+///
+/// # 路由
+/// DELETE /api/v1/tags/:id
+pub async fn delete_tag(State(pool): State<SqlitePool>, Path(id): Path<i32>) -> StatusCode {
+    match TagManager::new(pool).delete_user_tag(id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(e) => tag_error_status(e),
+    }
+}
+
+/// 为文件手动关联用户标签
+///
+/// # 路由
+/// POST /api/v1/files/:id/tags
+///
+/// # 请求体
+/// ```json
+/// { "tag_id": 12 }
+/// ```
+///
+/// # 响应
+/// - 204: 已关联
+/// - 400: 不是用户标签
+/// - 404: 文件或标签不存在
+pub async fn attach_tag(
+    State(pool): State<SqlitePool>,
+    Path(file_id): Path<i32>,
+    Json(payload): Json<AttachTagRequest>,
+) -> StatusCode {
+    match TagManager::new(pool).attach_manual(file_id, payload.tag_id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(e) => tag_error_status(e),
+    }
+}
+
+/// 解除文件与标签的关联
+///
+/// # 路由
+/// DELETE /api/v1/files/:id/tags/:tag_id
+///
+/// 自动标签被解除后，下次重新打标签时可能再次生成。
+pub async fn detach_tag(
+    State(pool): State<SqlitePool>,
+    Path((file_id, tag_id)): Path<(i32, i32)>,
+) -> StatusCode {
+    match TagManager::new(pool).detach(file_id, tag_id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(e) => tag_error_status(e),
+    }
+}
+
+/// 批量添加或移除用户标签
+///
+/// # 路由
+/// POST /api/v1/tags/bulk
+///
+/// # 请求体
+/// ```json
+/// { "action": "apply", "tag_ids": [12, 13], "file_ids": [1, 2, 3] }
+/// ```
+/// 或按条件选择文件 (字段含义与 `GET /api/v1/files` 相同):
+/// ```json
+/// {
+///   "action": "remove",
+///   "tag_ids": [12],
+///   "query": { "tag_id": 5, "q": "NOT type:Image", "library_id": 1, "ext": "pdf,docx", "path_prefix": "Work" }
+/// }
+/// ```
+///
+/// # 成功响应 (200)
+/// ```json
+/// { "files_matched": 3, "links_changed": 5 }
+/// ```
+/// 添加时，已由自动标签器关联的标签改记为手动，之后重新打标签不会移除。
+/// 所有修改在一个事务中完成，任一标签无效时不做任何修改。
+///
+/// # 失败响应
+/// - 400: 未指定标签、`file_ids` 与 `query` 未指定或同时指定、查询语法错误，或包含非用户标签
+/// - 404: 标签不存在
+pub async fn bulk_tags(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<BulkTagRequest>,
+) -> Result<Json<BulkTagResponse>, StatusCode> {
+    let selection = file_selection(payload.file_ids, payload.query)?;
+    let action = match payload.action {
+        BulkTagAction::Apply => BulkAction::Apply,
+        BulkTagAction::Remove => BulkAction::Remove,
+    };
+
+    let result = TagManager::new(pool)
+        .bulk_update(&selection, &payload.tag_ids, action)
+        .await
+        .map_err(tag_error_status)?;
+    info!("批量{}标签: 匹配 {} 个文件，变更 {} 条关联",
+        if action == BulkAction::Apply { "添加" } else { "移除" }, result.files_matched, result.links_changed);
+
+    Ok(Json(BulkTagResponse { files_matched: result.files_matched, links_changed: result.links_changed }))
+}
+
+/// 由请求中的 `file_ids` 或 `query` (二选一) 构建目标文件选择，批量标签与打包下载共用
+pub(crate) fn file_selection(file_ids: Option<Vec<i32>>, query: Option<BulkFileQuery>) -> Result<FileSelection, StatusCode> {
+    match (file_ids, query) {
+        (Some(ids), None) => Ok(FileSelection::Ids(ids)),
+        (None, Some(query)) => {
+            let expr = query.q.as_deref().map(TagExpr::parse).transpose().map_err(|e| {
+                warn!("{}", e);
+                StatusCode::BAD_REQUEST
+            })?;
+            Ok(FileSelection::Query(FileFilter {
+                tag_id: query.tag_id,
+                recursive: query.recursive.unwrap_or(true),
+                expr,
+                library_id: query.library_id,
+                status: query.status,
+                extensions: query.extensions(),
+                min_size: query.min_size,
+                max_size: query.max_size,
+                mtime_from: query.mtime_from,
+                mtime_to: query.mtime_to,
+                path_prefix: query.path_prefix,
+            }))
+        }
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
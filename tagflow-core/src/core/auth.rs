@@ -14,6 +14,9 @@ use serde::{Deserialize, Serialize};
 /// JWT 密钥（生产环境应从环境变量读取）
 const JWT_SECRET: &[u8] = b"your_ultra_secret_key_change_in_production";
 
+/// 文件内容令牌有效期（秒）
+pub const CONTENT_TOKEN_TTL_SECS: i64 = 3600;
+
 /// JWT 令牌声明
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -23,6 +26,20 @@ pub struct Claims {
     pub exp: usize,
 }
 
+/// 文件内容令牌声明
+///
+/// 只授权读取单个文件的内容，以 `?token=` 附在内容 URL 上，供无法携带
+/// `Authorization` 头的 `<img>`/`<video>` 使用。使用独立的签名密钥，不能当作登录令牌。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentClaims {
+    /// 签发令牌的用户名
+    pub sub: String,
+    /// 授权读取的文件
+    pub file_id: i32,
+    /// 过期时间（Unix 时间戳）
+    pub exp: usize,
+}
+
 /// 密码加密：将明文密码转换为 Argon2 哈希
 ///
 /// # 参数
@@ -139,6 +156,30 @@ pub fn decode_jwt(token: &str) -> Result<Claims> {
     .map_err(|e| anyhow!("Failed to decode JWT: {}", e))
 }
 
+/// 创建文件内容令牌，有效期 [`CONTENT_TOKEN_TTL_SECS`]
+pub fn create_content_token(username: &str, file_id: i32) -> Result<String> {
+    let claims = ContentClaims {
+        sub: username.to_owned(),
+        file_id,
+        exp: (Utc::now().timestamp() + CONTENT_TOKEN_TTL_SECS) as usize,
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(&content_secret()))
+        .map_err(|e| anyhow!("Failed to create content token: {}", e))
+}
+
+/// 验证并解码文件内容令牌
+pub fn decode_content_token(token: &str) -> Result<ContentClaims> {
+    decode::<ContentClaims>(token, &DecodingKey::from_secret(&content_secret()), &Validation::default())
+        .map(|data| data.claims)
+        .map_err(|e| anyhow!("Failed to decode content token: {}", e))
+}
+
+/// 文件内容令牌的签名密钥，与登录令牌不同，两种令牌不能互换
+fn content_secret() -> Vec<u8> {
+    [JWT_SECRET, b":content"].concat()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,4 +199,14 @@ mod tests {
         let claims = decode_jwt(&token).unwrap();
         assert_eq!(claims.sub, username);
     }
+
+    #[test]
+    fn test_content_token_is_not_a_login_token() {
+        let token = create_content_token("alice", 42).unwrap();
+        let claims = decode_content_token(&token).unwrap();
+        assert_eq!((claims.sub.as_str(), claims.file_id), ("alice", 42));
+
+        assert!(decode_jwt(&token).is_err());
+        assert!(decode_content_token(&create_jwt("alice").unwrap()).is_err());
+    }
 }
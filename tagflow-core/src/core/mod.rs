@@ -1,2 +1,3 @@
 pub mod tag;
+pub mod search;
 pub mod auth;
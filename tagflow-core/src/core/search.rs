@@ -0,0 +1,112 @@
+//! 文件名与路径搜索
+//!
+//! 基于 `files_fts` (FTS5 trigram 分词) 全文索引。查询按空白拆分为多个词，全部命中才算匹配；
+//! 每个词可出现在文件名或路径的任意位置，因此天然支持前缀匹配与不分词的中文。
+//!
+//! trigram 索引只能匹配不少于 3 个字符的词，更短的词 (例如 "合同") 改用 `LIKE` 匹配，
+//! 结果按 bm25 相关度排序，文件名命中的权重高于路径。
+
+use sqlx::{QueryBuilder, Sqlite};
+
+/// trigram 分词可索引的最短词长 (字符数)
+const MIN_INDEXED_CHARS: usize = 3;
+
+/// bm25 列权重：文件名, 路径
+const FILENAME_WEIGHT: f64 = 10.0;
+const PATH_WEIGHT: f64 = 1.0;
+
+/// 解析后的搜索词
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchTerms {
+    /// 走全文索引的词
+    indexed: Vec<String>,
+    /// 过短而改用 LIKE 匹配的词
+    short: Vec<String>,
+}
+
+impl SearchTerms {
+    /// 拆分查询字符串；去掉引号与末尾的 `*` (子串匹配已包含前缀匹配)，没有有效词时返回 `None`
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut terms = Self { indexed: Vec::new(), short: Vec::new() };
+        for word in query.split_whitespace() {
+            let word = word.trim_matches('"').trim_end_matches('*').to_string();
+            if word.is_empty() || terms.indexed.contains(&word) || terms.short.contains(&word) {
+                continue;
+            }
+            if word.chars().count() >= MIN_INDEXED_CHARS {
+                terms.indexed.push(word);
+            } else {
+                terms.short.push(word);
+            }
+        }
+        (!terms.indexed.is_empty() || !terms.short.is_empty()).then_some(terms)
+    }
+
+    /// FTS5 MATCH 表达式：每个词作为短语 (引号转义)，以 AND 连接
+    fn match_expr(&self) -> String {
+        self.indexed
+            .iter()
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+
+    /// 追加与 `files f` 的连接，需在 `FROM files f` 之后、`WHERE` 之前调用
+    pub fn push_join(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        if !self.indexed.is_empty() {
+            qb.push(" JOIN files_fts ON files_fts.rowid = f.id");
+        }
+    }
+
+    /// 追加 `AND ...` 匹配条件，需在 `WHERE` 子句之后调用
+    pub fn push_conditions(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        if !self.indexed.is_empty() {
+            qb.push(" AND files_fts MATCH ").push_bind(self.match_expr());
+        }
+        for term in &self.short {
+            let pattern = format!("%{}%", escape_like(term));
+            qb.push(" AND (f.filename LIKE ")
+                .push_bind(pattern.clone())
+                .push(" ESCAPE '\\' OR f.parent_path LIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\')");
+        }
+    }
+
+    /// 追加排序：相关度优先，其次最近修改
+    pub fn push_order(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        qb.push(" ORDER BY ");
+        if !self.indexed.is_empty() {
+            qb.push(format!("bm25(files_fts, {FILENAME_WEIGHT:.1}, {PATH_WEIGHT:.1}), "));
+        }
+        qb.push("f.mtime DESC, f.id DESC");
+    }
+}
+
+/// 转义 LIKE 通配符 (配合 `ESCAPE '\'` 使用)
+pub(crate) fn escape_like(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_terms() {
+        let terms = SearchTerms::parse(r#" 合同 report* "年度总结" 合同 a"b "#).unwrap();
+        assert_eq!(terms.indexed, vec!["report", "年度总结", "a\"b"]);
+        assert_eq!(terms.short, vec!["合同"]);
+        assert_eq!(terms.match_expr(), r#""report" AND "年度总结" AND "a""b""#);
+
+        assert!(SearchTerms::parse("  * \"\" ").is_none());
+        assert_eq!(escape_like("50%_a\\b"), "50\\%\\_a\\\\b");
+    }
+}
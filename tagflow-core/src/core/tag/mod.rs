@@ -1,4 +1,73 @@
-use sqlx::SqlitePool;
+pub mod query;
+
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use thiserror::Error;
+
+use crate::models::db::{Tag, TagCategory};
+use self::query::FileFilter;
+
+/// 用户标签操作错误
+#[derive(Debug, Error)]
+pub enum TagError {
+    /// 标签或文件不存在
+    #[error("{0} 不存在")]
+    NotFound(&'static str),
+    /// 只能修改用户分类的标签
+    #[error("标签 {0} 不是用户标签，不能手动修改")]
+    NotUserTag(i32),
+    /// 同一父标签下已有同名标签
+    #[error("同一父标签下已存在名为 '{0}' 的标签")]
+    Duplicate(String),
+    /// 新的父标签是自身或其子孙
+    #[error("不能将标签 {0} 移动到自身或其子标签下")]
+    Cycle(i32),
+    /// 名称为空或包含 `/`
+    #[error("无效的标签名称: '{0}'")]
+    InvalidName(String),
+    /// 批量操作参数无效
+    #[error("{0}")]
+    InvalidSelection(&'static str),
+    #[error("数据库错误: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// 批量操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkAction {
+    Apply,
+    Remove,
+}
+
+/// 批量操作的目标文件
+#[derive(Debug, Clone)]
+pub enum FileSelection {
+    /// 指定的文件 ID，不存在的 ID 被忽略
+    Ids(Vec<i32>),
+    /// 满足条件的全部文件
+    Query(FileFilter),
+}
+
+impl FileSelection {
+    /// 追加 `SELECT f.id FROM files f WHERE ...`，可作为子查询嵌入批量语句
+    pub(crate) fn push_select(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        qb.push("SELECT f.id FROM files f");
+        match self {
+            FileSelection::Ids(ids) => {
+                let ids = serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string());
+                qb.push(" WHERE f.id IN (SELECT value FROM json_each(").push_bind(ids).push("))");
+            }
+            FileSelection::Query(filter) => filter.push_where(qb),
+        }
+    }
+}
+
+/// 批量操作结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkResult {
+    pub files_matched: u64,
+    /// 新增、移除或由自动来源改记为手动的文件标签关联数
+    pub links_changed: u64,
+}
 
 pub struct TagManager {
     db: SqlitePool,
@@ -12,6 +81,24 @@ impl TagManager {
     /// 确保一个层级标签路径存在。例如输入 ["Work", "Design", "2025"]
     /// 返回最后一个标签 ("2025") 的 ID
     pub async fn ensure_path_tags(&self, parts: Vec<String>) -> anyhow::Result<i32> {
+        let mut conn = self.db.acquire().await?;
+        Self::ensure_path_tags_on(&mut conn, &parts).await
+    }
+
+    /// 与 [`ensure_path_tags`](Self::ensure_path_tags) 相同，但在给定连接 (或事务) 上执行
+    pub async fn ensure_path_tags_on(conn: &mut SqliteConnection, parts: &[String]) -> anyhow::Result<i32> {
+        Self::ensure_tags_on(conn, TagCategory::Path, parts).await
+    }
+
+    /// 在指定分类下确保层级标签存在，返回叶子标签 ID
+    ///
+    /// 不同分类的同名标签互不影响，例如目录 `Image/` 与类型 `Image` 是两个标签。
+    pub async fn ensure_tags_on(
+        conn: &mut SqliteConnection,
+        category: TagCategory,
+        parts: &[String],
+    ) -> anyhow::Result<i32> {
+        let category = category.to_string();
         let mut last_parent_id: Option<i32> = None;
 
         for part in parts {
@@ -19,23 +106,25 @@ impl TagManager {
 
             // 尝试查找或插入当前层级的标签
             let row: Option<(i32,)> = sqlx::query_as(
-                "SELECT id FROM tags WHERE name = ? AND (parent_id = ? OR (parent_id IS NULL AND ? IS NULL))"
+                "SELECT id FROM tags WHERE name = ? AND category = ? AND (parent_id = ? OR (parent_id IS NULL AND ? IS NULL))"
             )
-            .bind(&part)
+            .bind(part)
+            .bind(&category)
             .bind(last_parent_id)
             .bind(last_parent_id)
-            .fetch_optional(&self.db)
+            .fetch_optional(&mut *conn)
             .await?;
 
             let id = if let Some((existing_id,)) = row {
                 existing_id
             } else {
                 let res = sqlx::query(
-                    "INSERT INTO tags (name, category, parent_id) VALUES (?, 'path', ?)"
+                    "INSERT INTO tags (name, category, parent_id) VALUES (?, ?, ?)"
                 )
-                .bind(&part)
+                .bind(part)
+                .bind(&category)
                 .bind(last_parent_id)
-                .execute(&self.db)
+                .execute(&mut *conn)
                 .await?;
                 res.last_insert_rowid() as i32
             };
@@ -46,16 +135,411 @@ impl TagManager {
         last_parent_id.ok_or_else(|| anyhow::anyhow!("路径为空，无法生成标签"))
     }
 
-    /// 建立文件与标签的关联
-    pub async fn link_file_to_tag(&self, file_id: i32, tag_id: i32, source: &str) -> anyhow::Result<()> {
-        sqlx::query(
+    /// 建立文件与标签的关联，返回是否新增了关联 (已存在时保持原来源不变)
+    pub async fn link_file_to_tag(&self, file_id: i32, tag_id: i32, source: &str) -> anyhow::Result<bool> {
+        let mut conn = self.db.acquire().await?;
+        Ok(Self::link_file_to_tag_on(&mut conn, file_id, tag_id, source).await?)
+    }
+
+    /// 与 [`link_file_to_tag`](Self::link_file_to_tag) 相同，但在给定连接 (或事务) 上执行
+    pub async fn link_file_to_tag_on(conn: &mut SqliteConnection, file_id: i32, tag_id: i32, source: &str) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query(
             "INSERT OR IGNORE INTO file_tags (file_id, tag_id, source) VALUES (?, ?, ?)"
         )
         .bind(file_id)
         .bind(tag_id)
         .bind(source)
+        .execute(conn)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// 在一个事务中为选中的文件批量添加或移除用户标签
+    ///
+    /// 添加的关联来源记为 `manual`；已存在的关联不计入变更数。文件条件作为子查询嵌入
+    /// 单条 `INSERT ... SELECT` / `DELETE` 语句，不把文件 ID 读入内存，写锁只持有几条语句的时间。
+    pub async fn bulk_update(
+        &self,
+        selection: &FileSelection,
+        tag_ids: &[i32],
+        action: BulkAction,
+    ) -> Result<BulkResult, TagError> {
+        if tag_ids.is_empty() {
+            return Err(TagError::InvalidSelection("至少需要指定一个标签"));
+        }
+        // 先校验标签再开启事务，避免事务占用连接期间再从连接池取连接
+        for &tag_id in tag_ids {
+            self.user_tag(tag_id).await?;
+        }
+
+        let tag_ids = serde_json::to_string(tag_ids).unwrap_or_else(|_| "[]".to_string());
+
+        let mut tx = self.db.begin().await?;
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM (");
+        selection.push_select(&mut count);
+        count.push(")");
+        let files_matched: i64 = count.build_query_scalar().fetch_one(&mut *tx).await?;
+
+        let mut qb = QueryBuilder::new("");
+        match action {
+            BulkAction::Apply => {
+                // 已由自动标签器关联的改记为手动 (与 attach_manual 一致)，也计入变更数；
+                // `WHERE true` 避免 SQLite 把 ON CONFLICT 解析为连接条件
+                qb.push(
+                    "INSERT INTO file_tags (file_id, tag_id, source) \
+                     SELECT s.id, t.value, 'manual' FROM ("
+                );
+                selection.push_select(&mut qb);
+                qb.push(") s, json_each(").push_bind(tag_ids).push(
+                    ") t WHERE true \
+                     ON CONFLICT(file_id, tag_id) DO UPDATE SET source = 'manual' WHERE source != 'manual'"
+                );
+            }
+            BulkAction::Remove => {
+                qb.push("DELETE FROM file_tags WHERE tag_id IN (SELECT value FROM json_each(")
+                    .push_bind(tag_ids)
+                    .push(")) AND file_id IN (");
+                selection.push_select(&mut qb);
+                qb.push(")");
+            }
+        }
+        let links_changed = qb.build().execute(&mut *tx).await?.rows_affected();
+        tx.commit().await?;
+
+        Ok(BulkResult { files_matched: files_matched as u64, links_changed })
+    }
+
+    /// 创建用户标签
+    pub async fn create_user_tag(&self, name: &str, parent_id: Option<i32>) -> Result<Tag, TagError> {
+        let name = validate_name(name)?;
+        // 根级标签不受 UNIQUE(name, parent_id) 约束，重名检查与插入须在同一写事务中完成
+        let mut tx = self.db.begin_with("BEGIN IMMEDIATE").await?;
+        if let Some(parent_id) = parent_id {
+            Self::user_tag_on(&mut tx, parent_id).await?;
+        }
+        Self::ensure_unique(&mut tx, &name, parent_id, None).await?;
+
+        let tag = sqlx::query_as("INSERT INTO tags (name, category, parent_id) VALUES (?, 'user', ?) RETURNING *")
+            .bind(&name)
+            .bind(parent_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| unique_violation(e, &name))?;
+        tx.commit().await?;
+        Ok(tag)
+    }
+
+    /// 重命名和/或移动用户标签；`parent_id` 为 `Some(None)` 表示移动到根级
+    pub async fn update_user_tag(
+        &self,
+        id: i32,
+        name: Option<&str>,
+        parent_id: Option<Option<i32>>,
+    ) -> Result<Tag, TagError> {
+        // 环检测与更新在同一写事务中完成：IMMEDIATE 事务一开始即持有写锁，
+        // 并发的反向移动 (A 移到 B 下、B 移到 A 下) 无法同时通过检测
+        let mut tx = self.db.begin_with("BEGIN IMMEDIATE").await?;
+        let tag = Self::user_tag_on(&mut tx, id).await?;
+        let name = match name {
+            Some(name) => validate_name(name)?,
+            None => tag.name,
+        };
+        let parent_id = parent_id.unwrap_or(tag.parent_id);
+
+        if let Some(parent_id) = parent_id {
+            Self::user_tag_on(&mut tx, parent_id).await?;
+            // 新父标签不能位于当前标签的子树中
+            let in_subtree: bool = sqlx::query_scalar(
+                "WITH RECURSIVE subtree(id) AS (
+                     SELECT ?1
+                     UNION ALL
+                     SELECT t.id FROM tags t JOIN subtree s ON t.parent_id = s.id
+                 )
+                 SELECT EXISTS(SELECT 1 FROM subtree WHERE id = ?2)"
+            )
+            .bind(id)
+            .bind(parent_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if in_subtree {
+                return Err(TagError::Cycle(id));
+            }
+        }
+        Self::ensure_unique(&mut tx, &name, parent_id, Some(id)).await?;
+
+        let tag = sqlx::query_as("UPDATE tags SET name = ?, parent_id = ? WHERE id = ? RETURNING *")
+            .bind(&name)
+            .bind(parent_id)
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| unique_violation(e, &name))?;
+        tx.commit().await?;
+        Ok(tag)
+    }
+
+    /// 删除用户标签，子标签与文件关联随之级联删除
+    pub async fn delete_user_tag(&self, id: i32) -> Result<(), TagError> {
+        self.user_tag(id).await?;
+        sqlx::query("DELETE FROM tags WHERE id = ?").bind(id).execute(&self.db).await?;
+        Ok(())
+    }
+
+    /// 手动为文件关联用户标签；已由自动标签器关联的，改记为手动以免重新打标签时被替换
+    pub async fn attach_manual(&self, file_id: i32, tag_id: i32) -> Result<(), TagError> {
+        self.ensure_file(file_id).await?;
+        self.user_tag(tag_id).await?;
+        sqlx::query(
+            "INSERT INTO file_tags (file_id, tag_id, source) VALUES (?, ?, 'manual')
+             ON CONFLICT(file_id, tag_id) DO UPDATE SET source = 'manual'"
+        )
+        .bind(file_id)
+        .bind(tag_id)
         .execute(&self.db)
         .await?;
         Ok(())
     }
+
+    /// 解除文件与标签的关联 (无论来源)
+    pub async fn detach(&self, file_id: i32, tag_id: i32) -> Result<(), TagError> {
+        let res = sqlx::query("DELETE FROM file_tags WHERE file_id = ? AND tag_id = ?")
+            .bind(file_id)
+            .bind(tag_id)
+            .execute(&self.db)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(TagError::NotFound("文件标签关联"));
+        }
+        Ok(())
+    }
+
+    /// 读取用户标签，非用户分类的标签返回 [`TagError::NotUserTag`]
+    async fn user_tag(&self, id: i32) -> Result<Tag, TagError> {
+        let mut conn = self.db.acquire().await?;
+        Self::user_tag_on(&mut conn, id).await
+    }
+
+    /// 与 [`user_tag`](Self::user_tag) 相同，但在给定连接 (或事务) 上执行
+    async fn user_tag_on(conn: &mut SqliteConnection, id: i32) -> Result<Tag, TagError> {
+        let tag: Tag = sqlx::query_as("SELECT * FROM tags WHERE id = ?")
+            .bind(id)
+            .fetch_optional(conn)
+            .await?
+            .ok_or(TagError::NotFound("标签"))?;
+        if tag.category != TagCategory::User.to_string() {
+            return Err(TagError::NotUserTag(id));
+        }
+        Ok(tag)
+    }
+
+    async fn ensure_file(&self, file_id: i32) -> Result<(), TagError> {
+        let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM files WHERE id = ?")
+            .bind(file_id)
+            .fetch_optional(&self.db)
+            .await?;
+        exists.map(|_| ()).ok_or(TagError::NotFound("文件"))
+    }
+
+    /// 检查同级重名。根级标签的 `parent_id` 为 NULL，不受 `UNIQUE(name, parent_id)` 约束，需显式检查
+    async fn ensure_unique(
+        conn: &mut SqliteConnection,
+        name: &str,
+        parent_id: Option<i32>,
+        exclude_id: Option<i32>,
+    ) -> Result<(), TagError> {
+        let existing: Option<i32> = sqlx::query_scalar(
+            "SELECT id FROM tags
+             WHERE name = ?1 AND parent_id IS ?2 AND (?2 IS NOT NULL OR category = 'user')
+               AND (?3 IS NULL OR id != ?3)"
+        )
+        .bind(name)
+        .bind(parent_id)
+        .bind(exclude_id)
+        .fetch_optional(conn)
+        .await?;
+        match existing {
+            Some(_) => Err(TagError::Duplicate(name.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 去除首尾空白；名称不能为空，也不能包含用作层级分隔符的 `/`
+fn validate_name(name: &str) -> Result<String, TagError> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() || trimmed.contains('/') {
+        return Err(TagError::InvalidName(name.to_string()));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// 并发创建时仍可能触发唯一约束，统一转换为重名错误
+fn unique_violation(e: sqlx::Error, name: &str) -> TagError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => TagError::Duplicate(name.to_string()),
+        e => TagError::Database(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+
+    #[tokio::test]
+    async fn test_user_tag_crud() {
+        let pool = test_pool().await;
+        let manager = TagManager::new(pool.clone());
+
+        let work = manager.create_user_tag(" Work ", None).await.unwrap();
+        assert_eq!(work.name, "Work");
+        let design = manager.create_user_tag("Design", Some(work.id)).await.unwrap();
+        let logo = manager.create_user_tag("Logo", Some(design.id)).await.unwrap();
+
+        // 根级与子级重名
+        assert!(matches!(manager.create_user_tag("Work", None).await, Err(TagError::Duplicate(_))));
+        assert!(matches!(manager.create_user_tag("Design", Some(work.id)).await, Err(TagError::Duplicate(_))));
+        // 与路径标签同名的根级用户标签允许存在
+        TagManager::ensure_path_tags_on(&mut pool.acquire().await.unwrap(), &["Photos".to_string()]).await.unwrap();
+        manager.create_user_tag("Photos", None).await.unwrap();
+        assert!(matches!(manager.create_user_tag("a/b", None).await, Err(TagError::InvalidName(_))));
+
+        // 环检测
+        assert!(matches!(manager.update_user_tag(work.id, None, Some(Some(logo.id))).await, Err(TagError::Cycle(_))));
+        assert!(matches!(manager.update_user_tag(work.id, None, Some(Some(work.id))).await, Err(TagError::Cycle(_))));
+
+        // 移动到根级并重命名
+        let moved = manager.update_user_tag(logo.id, Some("Brand"), Some(None)).await.unwrap();
+        assert_eq!((moved.name.as_str(), moved.parent_id), ("Brand", None));
+        assert!(matches!(manager.update_user_tag(moved.id, Some("Work"), None).await, Err(TagError::Duplicate(_))));
+        // 只改名保留原父标签
+        let renamed = manager.update_user_tag(design.id, Some("UI"), None).await.unwrap();
+        assert_eq!(renamed.parent_id, Some(work.id));
+
+        // 非用户标签不可修改
+        let path_tag: i32 = sqlx::query_scalar("SELECT id FROM tags WHERE category = 'path'").fetch_one(&pool).await.unwrap();
+        assert!(matches!(manager.delete_user_tag(path_tag).await, Err(TagError::NotUserTag(_))));
+
+        // 删除父标签级联删除子标签
+        manager.delete_user_tag(work.id).await.unwrap();
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tags WHERE id = ?").bind(design.id).fetch_one(&pool).await.unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_reparent_cannot_form_cycle() {
+        // 内存库的测试连接池只有一个连接，这里使用多连接的文件库以便两个移动真正并发
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("tags.db").display());
+        let pool = crate::infra::db::init_db(&url).await.unwrap();
+        let manager = TagManager::new(pool.clone());
+        let a = manager.create_user_tag("A", None).await.unwrap();
+        let b = manager.create_user_tag("B", None).await.unwrap();
+
+        for _ in 0..20 {
+            let (ab, ba) = tokio::join!(
+                manager.update_user_tag(a.id, None, Some(Some(b.id))),
+                manager.update_user_tag(b.id, None, Some(Some(a.id))),
+            );
+            // 恰好一个移动成功，另一个被判定为环
+            assert!(ab.is_ok() != ba.is_ok(), "{:?} / {:?}", ab, ba);
+            assert!(matches!(ab.err().or(ba.err()), Some(TagError::Cycle(_))));
+
+            manager.update_user_tag(a.id, None, Some(None)).await.unwrap();
+            manager.update_user_tag(b.id, None, Some(None)).await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_create_rejects_duplicate_root_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("tags.db").display());
+        let pool = crate::infra::db::init_db(&url).await.unwrap();
+        let manager = TagManager::new(pool.clone());
+
+        for i in 0..20 {
+            let name = format!("Inbox {}", i);
+            let (first, second) = tokio::join!(
+                manager.create_user_tag(&name, None),
+                manager.create_user_tag(&name, None),
+            );
+            assert!(first.is_ok() != second.is_ok(), "{:?} / {:?}", first, second);
+            assert!(matches!(first.err().or(second.err()), Some(TagError::Duplicate(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attach_and_detach() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO files (id, library_id, parent_path, filename, size, mtime) VALUES (1, 1, '', 'a.pdf', 1, 1)")
+            .execute(&pool).await.unwrap();
+        let manager = TagManager::new(pool.clone());
+        let tag = manager.create_user_tag("Invoices", None).await.unwrap();
+
+        // 规则生成的关联被手动确认后改记为 manual
+        manager.link_file_to_tag(1, tag.id, "rule").await.unwrap();
+        manager.attach_manual(1, tag.id).await.unwrap();
+        let source: String = sqlx::query_scalar("SELECT source FROM file_tags WHERE file_id = 1").fetch_one(&pool).await.unwrap();
+        assert_eq!(source, "manual");
+
+        assert!(matches!(manager.attach_manual(2, tag.id).await, Err(TagError::NotFound(_))));
+        manager.detach(1, tag.id).await.unwrap();
+        assert!(matches!(manager.detach(1, tag.id).await, Err(TagError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, extension, size, mtime, status) VALUES
+             (1, 1, 'Work/', 'a.pdf', 'pdf', 1, 1, 1),
+             (2, 1, 'Work/Design/', 'b.png', 'png', 1, 1, 1),
+             (3, 1, 'Home/', 'c.txt', 'txt', 1, 1, 1),
+             (4, 1, 'Work/', 'd.pdf', 'pdf', 1, 1, 0)"
+        )
+        .execute(&pool).await.unwrap();
+        let manager = TagManager::new(pool.clone());
+        let mut conn = pool.acquire().await.unwrap();
+        let work = TagManager::ensure_path_tags_on(&mut conn, &["Work".to_string()]).await.unwrap();
+        let design = TagManager::ensure_path_tags_on(&mut conn, &["Work".to_string(), "Design".to_string()]).await.unwrap();
+        drop(conn);
+        for (file_id, tag_id) in [(1, work), (2, design), (4, work)] {
+            manager.link_file_to_tag(file_id, tag_id, "path").await.unwrap();
+        }
+        let todo = manager.create_user_tag("Todo", None).await.unwrap();
+        let review = manager.create_user_tag("Review", None).await.unwrap();
+        manager.link_file_to_tag(1, todo.id, "rule").await.unwrap();
+
+        // 按标签递归查询，默认只包括在线文件；规则生成的关联改记为手动，同样计入变更
+        let query = FileSelection::Query(FileFilter { tag_id: Some(work), recursive: true, ..Default::default() });
+        let result = manager.bulk_update(&query, &[todo.id, review.id], BulkAction::Apply).await.unwrap();
+        assert_eq!(result, BulkResult { files_matched: 2, links_changed: 4 });
+        let source: String = sqlx::query_scalar("SELECT source FROM file_tags WHERE file_id = 1 AND tag_id = ?")
+            .bind(todo.id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(source, "manual");
+        // 再次添加不产生变更
+        let result = manager.bulk_update(&query, &[todo.id], BulkAction::Apply).await.unwrap();
+        assert_eq!(result.links_changed, 0);
+
+        // 按 ID 移除，不存在的 ID 被忽略
+        let ids = FileSelection::Ids(vec![2, 3, 99]);
+        let result = manager.bulk_update(&ids, &[todo.id], BulkAction::Remove).await.unwrap();
+        assert_eq!(result, BulkResult { files_matched: 2, links_changed: 1 });
+
+        // 按属性条件选择
+        let pdfs = FileSelection::Query(FileFilter { extensions: vec!["pdf".to_string()], ..Default::default() });
+        let result = manager.bulk_update(&pdfs, &[review.id], BulkAction::Remove).await.unwrap();
+        assert_eq!(result, BulkResult { files_matched: 1, links_changed: 1 });
+
+        // 非用户标签被拒绝，且不产生任何修改
+        assert!(matches!(manager.bulk_update(&ids, &[work], BulkAction::Remove).await, Err(TagError::NotUserTag(_))));
+        assert!(matches!(manager.bulk_update(&ids, &[], BulkAction::Apply).await, Err(TagError::InvalidSelection(_))));
+        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_tags WHERE source = 'manual'").fetch_one(&pool).await.unwrap();
+        assert_eq!(links, 2);
+    }
 }
@@ -0,0 +1,449 @@
+//! 标签查询语言
+//!
+//! 支持 `AND` / `OR` / `NOT` 与括号组合标签条件，例如
+//! `Work/Design AND (type:Image OR type:Video) AND NOT Archive`。
+//!
+//! - 标签写作路径 `A/B/C`，可匹配任意层级下的同名路径 (`Archive` 也匹配 `Work/Archive`)；
+//!   以 `/` 开头时只从根标签匹配，例如 `/Archive`。名称不区分大小写 (仅 ASCII)
+//! - 可用 `path:` / `type:` / `user:` / `time:` 前缀限定分类，不加前缀时匹配任意分类
+//! - 命中某个标签即包括其全部子孙标签，例如 `type:Image` 包含 `type:Image/JPEG`
+//! - 含空格或与运算符同名的标签用双引号括起，例如 `user:"My Docs"/2024`、`"AND"`
+//! - 运算符必须大写，优先级 `NOT` > `AND` > `OR`
+//! - 单个查询至多 64 个标签条件、每个路径至多 16 级，避免生成超出 SQLite 限制的 SQL
+//!
+//! 查询编译为作用于别名为 `f` 的 files 表的 SQL 条件，每个标签条件是一个基于
+//! `file_tags` 与递归 `sub_tags` CTE 的子查询，标签名全部以参数绑定。
+
+use sqlx::{QueryBuilder, Sqlite};
+use thiserror::Error;
+
+use crate::core::search::escape_like;
+use crate::models::db::TagCategory;
+use crate::models::dto::FileStatusFilter;
+
+/// 最大嵌套深度，防止恶意输入导致栈溢出
+const MAX_DEPTH: usize = 64;
+
+/// 最多标签条件数，过长的 `A OR B OR ...` 会超出 SQLite 表达式深度限制
+const MAX_TERMS: usize = 64;
+
+/// 标签路径最大层级，每一级对应一次 tags 表连接 (SQLite 单个查询至多连接 64 个表)
+const MAX_PATH_LEN: usize = 16;
+
+/// 查询解析错误
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("查询语法错误 (第 {position} 个字符): {message}")]
+pub struct QueryError {
+    /// 出错位置，从 1 开始按字符计数
+    pub position: usize,
+    pub message: String,
+}
+
+/// 查询表达式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    /// 某个标签 (含子孙标签)
+    Tag {
+        category: Option<TagCategory>,
+        path: Vec<String>,
+        /// 路径是否从根标签开始匹配
+        rooted: bool,
+    },
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    /// 解析查询字符串
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0, end: input.chars().count() + 1, terms: 0 };
+        if parser.tokens.is_empty() {
+            return Err(parser.error_at(1, "查询为空"));
+        }
+        let expr = parser.parse_or(0)?;
+        if let Some(token) = parser.peek() {
+            let message = match token.kind {
+                TokenKind::RParen => "多余的 ')'".to_string(),
+                _ => format!("期望 AND 或 OR，实际为 {}", token.describe()),
+            };
+            return Err(parser.error_at(token.position, message));
+        }
+        Ok(expr)
+    }
+
+    /// 追加等价的 SQL 条件 (作用于别名为 `f` 的 files 表)
+    pub fn push_sql(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        match self {
+            TagExpr::Tag { category, path, rooted } => push_tag_sql(qb, *category, path, *rooted),
+            TagExpr::Not(inner) => {
+                qb.push("NOT (");
+                inner.push_sql(qb);
+                qb.push(")");
+            }
+            TagExpr::And(lhs, rhs) | TagExpr::Or(lhs, rhs) => {
+                let op = if matches!(self, TagExpr::And(..)) { " AND " } else { " OR " };
+                qb.push("(");
+                lhs.push_sql(qb);
+                qb.push(op);
+                rhs.push_sql(qb);
+                qb.push(")");
+            }
+        }
+    }
+}
+
+/// 文件过滤条件，文件列表与批量标签操作共用
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    /// 关联了该标签 (递归时包括子孙标签) 的文件
+    pub tag_id: Option<i32>,
+    pub recursive: bool,
+    /// 标签查询表达式，与 `tag_id` 同时指定时取交集
+    pub expr: Option<TagExpr>,
+    pub library_id: Option<i32>,
+    pub status: FileStatusFilter,
+    /// 小写、不含前导点的扩展名，为空表示不限
+    pub extensions: Vec<String>,
+    /// 大小范围 (闭区间)
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    /// 修改时间范围 (闭区间)
+    pub mtime_from: Option<i64>,
+    pub mtime_to: Option<i64>,
+    /// 目录前缀，按目录边界匹配：`Work` 匹配 `Work/` 与 `Work/Design/`，不匹配 `Workshop/`
+    pub path_prefix: Option<String>,
+}
+
+impl FileFilter {
+    /// 追加 `WHERE ...` 子句 (作用于别名为 `f` 的 files 表)
+    pub fn push_where(&self, qb: &mut QueryBuilder<'_, Sqlite>) {
+        qb.push(" WHERE ").push(self.status.sql_condition());
+        if let Some(library_id) = self.library_id {
+            qb.push(" AND f.library_id = ").push_bind(library_id);
+        }
+        if !self.extensions.is_empty() {
+            qb.push(" AND f.extension IN (");
+            let mut list = qb.separated(", ");
+            for ext in &self.extensions {
+                list.push_bind(ext.clone());
+            }
+            qb.push(")");
+        }
+        if let Some(min_size) = self.min_size {
+            qb.push(" AND f.size >= ").push_bind(min_size);
+        }
+        if let Some(max_size) = self.max_size {
+            qb.push(" AND f.size <= ").push_bind(max_size);
+        }
+        if let Some(mtime_from) = self.mtime_from {
+            qb.push(" AND f.mtime >= ").push_bind(mtime_from);
+        }
+        if let Some(mtime_to) = self.mtime_to {
+            qb.push(" AND f.mtime <= ").push_bind(mtime_to);
+        }
+        let prefix = self.path_prefix.as_deref().map(|p| p.trim_matches('/')).unwrap_or_default();
+        if !prefix.is_empty() {
+            qb.push(" AND f.parent_path LIKE ")
+                .push_bind(format!("{}/%", escape_like(prefix)))
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(tag_id) = self.tag_id {
+            if self.recursive {
+                // 使用递归 CTE 查找所有子孙标签的文件
+                qb.push(" AND f.id IN (WITH RECURSIVE sub_tags(id) AS (SELECT id FROM tags WHERE id = ")
+                    .push_bind(tag_id)
+                    .push(
+                        " UNION ALL SELECT t.id FROM tags t JOIN sub_tags st ON t.parent_id = st.id) \
+                         SELECT ft.file_id FROM file_tags ft WHERE ft.tag_id IN (SELECT id FROM sub_tags))"
+                    );
+            } else {
+                qb.push(" AND f.id IN (SELECT file_id FROM file_tags WHERE tag_id = ").push_bind(tag_id).push(")");
+            }
+        }
+        if let Some(expr) = &self.expr {
+            qb.push(" AND ");
+            expr.push_sql(qb);
+        }
+    }
+}
+
+/// 标签路径逐级连接 tags 表定位锚点标签 (可能有多个)，再递归展开子孙标签
+fn push_tag_sql(qb: &mut QueryBuilder<'_, Sqlite>, category: Option<TagCategory>, path: &[String], rooted: bool) {
+    let depth = path.len();
+    qb.push(format!(
+        "f.id IN (WITH RECURSIVE sub_tags(id) AS (SELECT t{depth}.id FROM tags t1"
+    ));
+    for level in 2..=depth {
+        qb.push(format!(" JOIN tags t{level} ON t{level}.parent_id = t{}.id", level - 1));
+    }
+    qb.push(" WHERE ");
+    if rooted {
+        qb.push("t1.parent_id IS NULL AND ");
+    }
+    for (level, name) in path.iter().enumerate() {
+        if level > 0 {
+            qb.push(" AND ");
+        }
+        qb.push(format!("t{}.name = ", level + 1)).push_bind(name.clone()).push(" COLLATE NOCASE");
+    }
+    if let Some(category) = category {
+        qb.push(" AND t1.category = ").push_bind(category.to_string());
+    }
+    qb.push(
+        " UNION ALL SELECT t.id FROM tags t JOIN sub_tags st ON t.parent_id = st.id) \
+         SELECT ft.file_id FROM file_tags ft WHERE ft.tag_id IN (SELECT id FROM sub_tags))"
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// 起始位置，从 1 开始
+    position: usize,
+    /// 原始文本，用于错误信息
+    text: String,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self.kind {
+            TokenKind::Term(_) => format!("标签 '{}'", self.text),
+            _ => format!("'{}'", self.text),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let position = i + 1;
+        if c == '(' || c == ')' {
+            let kind = if c == '(' { TokenKind::LParen } else { TokenKind::RParen };
+            tokens.push(Token { kind, position, text: c.to_string() });
+            i += 1;
+            continue;
+        }
+
+        // 标签或运算符：读到空白或括号为止，引号内的内容原样保留
+        let mut value = String::new();
+        let mut quoted = false;
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '(' && chars[i] != ')' {
+            if chars[i] == '"' {
+                let open = i;
+                quoted = true;
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(QueryError { position: open + 1, message: "引号未闭合".to_string() });
+                }
+            } else {
+                value.push(chars[i]);
+            }
+            i += 1;
+        }
+        let text: String = chars[start..i].iter().collect();
+
+        let kind = match value.as_str() {
+            "AND" if !quoted => TokenKind::And,
+            "OR" if !quoted => TokenKind::Or,
+            "NOT" if !quoted => TokenKind::Not,
+            _ => TokenKind::Term(text.clone()),
+        };
+        tokens.push(Token { kind, position, text });
+    }
+    Ok(tokens)
+}
+
+/// 递归下降解析器
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// 输入末尾位置，用于 "意外结束" 类错误
+    end: usize,
+    /// 已解析的标签条件数
+    terms: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_if(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|t| &t.kind == kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error_at(&self, position: usize, message: impl Into<String>) -> QueryError {
+        QueryError { position, message: message.into() }
+    }
+
+    fn parse_or(&mut self, depth: usize) -> Result<TagExpr, QueryError> {
+        let mut expr = self.parse_and(depth)?;
+        while self.next_if(&TokenKind::Or) {
+            let rhs = self.parse_and(depth)?;
+            expr = TagExpr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<TagExpr, QueryError> {
+        let mut expr = self.parse_unary(depth)?;
+        while self.next_if(&TokenKind::And) {
+            let rhs = self.parse_unary(depth)?;
+            expr = TagExpr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<TagExpr, QueryError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error_at(self.end, "查询意外结束，缺少标签"));
+        };
+        if depth >= MAX_DEPTH {
+            return Err(self.error_at(token.position, format!("嵌套层数超过 {}", MAX_DEPTH)));
+        }
+        self.pos += 1;
+
+        match token.kind {
+            TokenKind::Not => Ok(TagExpr::Not(Box::new(self.parse_unary(depth + 1)?))),
+            TokenKind::LParen => {
+                let expr = self.parse_or(depth + 1)?;
+                if !self.next_if(&TokenKind::RParen) {
+                    let position = self.peek().map_or(self.end, |t| t.position);
+                    return Err(self.error_at(position, format!("第 {} 个字符处的 '(' 缺少对应的 ')'", token.position)));
+                }
+                Ok(expr)
+            }
+            TokenKind::Term(ref text) => {
+                self.terms += 1;
+                if self.terms > MAX_TERMS {
+                    return Err(self.error_at(token.position, format!("标签条件超过 {} 个", MAX_TERMS)));
+                }
+                parse_term(text).map_err(|message| self.error_at(token.position, message))
+            }
+            TokenKind::RParen | TokenKind::And | TokenKind::Or => {
+                Err(self.error_at(token.position, format!("期望标签、NOT 或 '('，实际为 {}", token.describe())))
+            }
+        }
+    }
+}
+
+/// 解析单个标签条件，例如 `type:Image/JPEG`、`user:"My Docs"/2024`
+fn parse_term(text: &str) -> Result<TagExpr, String> {
+    let (category, rest) = match text.split_once(':') {
+        Some((prefix, rest)) if !prefix.contains('"') => match prefix.to_ascii_lowercase().parse::<TagCategory>() {
+            Ok(category) => (Some(category), rest),
+            Err(_) => return Err(format!("未知的标签分类 '{}'，可用: path、type、user、time", prefix)),
+        },
+        _ => (None, text),
+    };
+
+    let rest = unquote(rest);
+    let rooted = rest.trim_start().starts_with('/');
+    let path: Vec<String> = rest
+        .split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    if path.is_empty() {
+        return Err(format!("标签 '{}' 的路径为空", text));
+    }
+    if path.len() > MAX_PATH_LEN {
+        return Err(format!("标签 '{}' 的路径超过 {} 级", text, MAX_PATH_LEN));
+    }
+    Ok(TagExpr::Tag { category, path, rooted })
+}
+
+/// 去掉引号 (引号内的 `/` 仍作为层级分隔符)
+fn unquote(text: &str) -> String {
+    text.chars().filter(|&c| c != '"').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(category: Option<TagCategory>, path: &[&str]) -> TagExpr {
+        TagExpr::Tag { category, path: path.iter().map(|s| s.to_string()).collect(), rooted: false }
+    }
+
+    #[test]
+    fn test_parse_precedence_and_quoting() {
+        let expr = TagExpr::parse("Work/Design AND (type:Image OR type:Video) AND NOT Archive").unwrap();
+        assert_eq!(
+            expr,
+            TagExpr::And(
+                Box::new(TagExpr::And(
+                    Box::new(tag(None, &["Work", "Design"])),
+                    Box::new(TagExpr::Or(
+                        Box::new(tag(Some(TagCategory::Type), &["Image"])),
+                        Box::new(tag(Some(TagCategory::Type), &["Video"])),
+                    )),
+                )),
+                Box::new(TagExpr::Not(Box::new(tag(None, &["Archive"])))),
+            )
+        );
+
+        // AND 优先于 OR
+        let expr = TagExpr::parse("A OR B AND C").unwrap();
+        assert!(matches!(expr, TagExpr::Or(_, ref rhs) if matches!(**rhs, TagExpr::And(..))));
+
+        // 引号保留空格与运算符同名的标签，小写的 and 是普通标签
+        assert_eq!(TagExpr::parse(r#"user:"My Docs"/2024"#).unwrap(), tag(Some(TagCategory::User), &["My Docs", "2024"]));
+        assert_eq!(TagExpr::parse(r#""AND""#).unwrap(), tag(None, &["AND"]));
+        assert_eq!(TagExpr::parse("and").unwrap(), tag(None, &["and"]));
+
+        // 以 / 开头的路径只从根匹配
+        let TagExpr::Tag { rooted, path, .. } = TagExpr::parse("path:/Work/Design").unwrap() else { panic!() };
+        assert!(rooted);
+        assert_eq!(path, vec!["Work", "Design"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |q: &str| TagExpr::parse(q).unwrap_err();
+
+        assert_eq!(error("").message, "查询为空");
+        assert_eq!(error("Work AND").position, 9);
+        assert_eq!(error("(Work OR Home").message, "第 1 个字符处的 '(' 缺少对应的 ')'");
+        assert_eq!(error("Work)").message, "多余的 ')'");
+        assert_eq!(error("Work Home").message, "期望 AND 或 OR，实际为 标签 'Home'");
+        assert_eq!(error("OR Work").position, 1);
+        assert_eq!(error("colour:Red").message, "未知的标签分类 'colour'，可用: path、type、user、time");
+        assert_eq!(error("type:").message, "标签 'type:' 的路径为空");
+        assert_eq!(error(r#"Work AND "Home"#).position, 10);
+        assert!(error(&"(".repeat(100)).message.contains("嵌套层数"));
+        assert_eq!(error(&vec!["A"; 65].join(" OR ")).message, "标签条件超过 64 个");
+        assert!(error(&vec!["A"; 17].join("/")).message.contains("超过 16 级"));
+    }
+}
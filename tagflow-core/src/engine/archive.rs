@@ -0,0 +1,242 @@
+//! 多文件打包下载
+//!
+//! 边读边写 ZIP：逐个通过资源库的 OpenDAL 算子流式读取文件并写入输出流，不使用临时文件，
+//! 内存占用与文件数量和大小无关。条目使用 Stored (不压缩) 方式写入，照片、视频等
+//! 已压缩格式再压缩收益很小；超过 4 GiB 时自动使用 ZIP64。
+
+use std::collections::{HashMap, HashSet};
+
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use chrono::DateTime;
+use futures_util::io::AsyncWriteExt;
+use futures_util::TryStreamExt;
+use opendal::Operator;
+use sqlx::SqlitePool;
+use tokio::io::AsyncWrite;
+use tracing::warn;
+
+use crate::models::db::FileEntry;
+
+/// 包内的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub file_id: i32,
+    pub library_id: i32,
+    /// 相对资源库根目录的存储路径
+    pub path: String,
+    /// 包内路径，已去重
+    pub name: String,
+    pub mtime: i64,
+}
+
+/// 打包结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub written: usize,
+    /// 存储中读取失败而跳过的文件
+    pub skipped: usize,
+}
+
+/// 计算每个文件的包内路径
+///
+/// `folders` 为文件所在的包内目录 (例如标签路径)，未指定的文件放在根目录。
+/// 同名文件 (不区分大小写) 依次重命名为 `name (1).ext`、`name (2).ext`。
+pub fn plan_entries(files: &[FileEntry], folders: &HashMap<i32, Vec<String>>) -> Vec<ArchiveEntry> {
+    let mut used: HashSet<String> = HashSet::new();
+    files
+        .iter()
+        .map(|file| {
+            let dir: String = folders
+                .get(&file.id)
+                .map(|parts| parts.iter().map(|p| format!("{}/", sanitize(p))).collect())
+                .unwrap_or_default();
+            ArchiveEntry {
+                file_id: file.id,
+                library_id: file.library_id,
+                path: format!("{}{}", file.parent_path, file.filename),
+                name: unique_name(&mut used, &dir, &sanitize(&file.filename)),
+                mtime: file.mtime,
+            }
+        })
+        .collect()
+}
+
+/// 查询文件在某个标签子树下的标签路径，用作包内目录
+///
+/// 路径从该标签本身开始；文件关联了子树中多个标签时取最深的一个 (深度相同取路径字典序最小)。
+pub async fn tag_folders(db: &SqlitePool, root_tag_id: i32, file_ids: &[i32]) -> anyhow::Result<HashMap<i32, Vec<String>>> {
+    let rows: Vec<(i32, String)> = sqlx::query_as(
+        "WITH RECURSIVE sub_tags(id, path, depth) AS (
+             SELECT id, name, 0 FROM tags WHERE id = ?1
+             UNION ALL
+             SELECT t.id, st.path || '/' || t.name, st.depth + 1 FROM tags t JOIN sub_tags st ON t.parent_id = st.id
+         )
+         SELECT ft.file_id, st.path FROM file_tags ft JOIN sub_tags st ON st.id = ft.tag_id
+         WHERE ft.file_id IN (SELECT value FROM json_each(?2))
+         ORDER BY ft.file_id, st.depth DESC, st.path"
+    )
+    .bind(root_tag_id)
+    .bind(serde_json::to_string(file_ids)?)
+    .fetch_all(db)
+    .await?;
+
+    let mut folders = HashMap::new();
+    for (file_id, path) in rows {
+        folders
+            .entry(file_id)
+            .or_insert_with(|| path.split('/').map(str::to_string).collect());
+    }
+    Ok(folders)
+}
+
+/// 将条目依次写入 ZIP 并关闭
+///
+/// 打开失败的文件 (例如已从存储中删除) 记录日志后跳过；写入过程中出错时返回错误，
+/// 此时输出的压缩包不完整。
+pub async fn write_zip<W: AsyncWrite + Unpin>(
+    ops: &HashMap<i32, Operator>,
+    entries: &[ArchiveEntry],
+    writer: W,
+) -> anyhow::Result<ArchiveStats> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut stats = ArchiveStats::default();
+
+    for entry in entries {
+        let Some(op) = ops.get(&entry.library_id) else {
+            anyhow::bail!("缺少资源库 {} 的存储算子", entry.library_id);
+        };
+        // 读取器是惰性的，先取第一块数据确认文件可读，再写条目头
+        let opened = async {
+            let mut stream = op.reader(&entry.path).await?.into_bytes_stream(..).await?;
+            let first = stream.try_next().await?;
+            Ok::<_, anyhow::Error>((stream, first))
+        };
+        let (mut stream, first) = match opened.await {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("打包时跳过无法读取的文件: {} - {:#}", entry.path, e);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let mut builder = ZipEntryBuilder::new(entry.name.clone().into(), Compression::Stored)
+            .unix_permissions(0o644);
+        if let Some(mtime) = DateTime::from_timestamp(entry.mtime, 0) {
+            builder = builder.last_modification_date(ZipDateTime::from_chrono(&mtime));
+        }
+        let mut entry_writer = zip.write_entry_stream(builder).await?;
+        if let Some(chunk) = first {
+            entry_writer.write_all(&chunk).await?;
+        }
+        while let Some(chunk) = stream.try_next().await? {
+            entry_writer.write_all(&chunk).await?;
+        }
+        entry_writer.close().await?;
+        stats.written += 1;
+    }
+
+    zip.close().await?;
+    Ok(stats)
+}
+
+/// 在已使用的包内路径中找一个不冲突的名字
+fn unique_name(used: &mut HashSet<String>, dir: &str, filename: &str) -> String {
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (filename, String::new()),
+    };
+    let mut name = format!("{}{}", dir, filename);
+    let mut n = 1;
+    while !used.insert(name.to_lowercase()) {
+        name = format!("{}{} ({}){}", dir, stem, n, ext);
+        n += 1;
+    }
+    name
+}
+
+/// 去掉路径分隔符与 `.`/`..`，防止条目逃逸出解压目录
+fn sanitize(segment: &str) -> String {
+    let cleaned: String = segment
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | '\0') { '_' } else { c })
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "_".to_string(),
+        _ => cleaned,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_zip::base::read::mem::ZipFileReader;
+    use chrono::Utc;
+    use futures_util::io::AsyncReadExt;
+
+    fn file(id: i32, parent_path: &str, filename: &str) -> FileEntry {
+        FileEntry {
+            id,
+            library_id: 1,
+            parent_path: parent_path.to_string(),
+            filename: filename.to_string(),
+            extension: None,
+            size: 0,
+            mtime: 1_700_000_000,
+            hash: None,
+            status: 1,
+            indexed_at: Utc::now(),
+            lost_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zip_round_trip_with_unique_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a")).unwrap();
+        std::fs::create_dir_all(dir.path().join("b")).unwrap();
+        std::fs::write(dir.path().join("a/报告.txt"), b"first").unwrap();
+        std::fs::write(dir.path().join("b/报告.txt"), b"second").unwrap();
+        std::fs::write(dir.path().join("b/README"), b"readme").unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+
+        let files = vec![
+            file(1, "a/", "报告.txt"),
+            file(2, "b/", "报告.TXT"),
+            file(3, "b/", "README"),
+            file(4, "c/", "README"),
+            file(5, "gone/", "missing.txt"),
+        ];
+        // 文件 4 在标签目录下，不与根目录的 README 冲突
+        let folders = HashMap::from([(4, vec!["..".to_string(), "Docs".to_string()])]);
+        let mut entries = plan_entries(&files, &folders);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["报告.txt", "报告 (1).TXT", "README", "_/Docs/README", "missing.txt"]);
+
+        // 指向同一文件以便读取，存储中不存在的文件被跳过
+        entries[1].path = "b/报告.txt".to_string();
+        entries[3].path = "b/README".to_string();
+        let mut buf = Vec::new();
+        let stats = write_zip(&HashMap::from([(1, op)]), &entries, &mut buf).await.unwrap();
+        assert_eq!(stats, ArchiveStats { written: 4, skipped: 1 });
+
+        let zip = ZipFileReader::new(buf).await.unwrap();
+        let mut contents = Vec::new();
+        for index in 0..zip.file().entries().len() {
+            let mut reader = zip.reader_with_entry(index).await.unwrap();
+            let name = reader.entry().filename().as_str().unwrap().to_string();
+            let mut data = String::new();
+            reader.read_to_string(&mut data).await.unwrap();
+            contents.push((name, data));
+        }
+        assert_eq!(contents, vec![
+            ("报告.txt".to_string(), "first".to_string()),
+            ("报告 (1).TXT".to_string(), "second".to_string()),
+            ("README".to_string(), "readme".to_string()),
+            ("_/Docs/README".to_string(), "readme".to_string()),
+        ]);
+    }
+}
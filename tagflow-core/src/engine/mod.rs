@@ -1,3 +1,10 @@
+pub mod archive;
+pub mod purge;
+pub mod retag;
+pub mod retag_job;
+pub mod scan_job;
 pub mod scanner;
+pub mod scheduler;
 pub mod tagger;
+pub mod watcher;
 pub mod worker;
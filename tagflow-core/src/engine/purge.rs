@@ -0,0 +1,165 @@
+//! 丢失文件清理
+//!
+//! 删除丢失超过保留期的文件记录，连同其标签关联、待处理任务与缓存的缩略图。
+//! 可由扫描结束后按资源库的 `lost_retention_days` 自动触发，也可通过 API 手动触发。
+
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+use crate::infra::thumbnail::thumbnail_path;
+
+/// 每个删除事务包含的文件数
+const PURGE_BATCH_SIZE: usize = 500;
+
+/// 清理结果
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PurgeStats {
+    pub files_purged: u64,
+    pub thumbnails_removed: u64,
+}
+
+/// 删除资源库中丢失超过 `older_than_days` 天的文件，`0` 表示删除全部丢失文件
+pub async fn purge_lost_files(
+    pool: &SqlitePool,
+    library_id: i32,
+    older_than_days: u32,
+    cache_dir: &str,
+) -> anyhow::Result<PurgeStats> {
+    let ids: Vec<i32> = sqlx::query_scalar(
+        "SELECT id FROM files
+         WHERE library_id = ? AND status = 0 AND (lost_at IS NULL OR lost_at <= datetime('now', ?))"
+    )
+    .bind(library_id)
+    .bind(format!("-{} days", older_than_days))
+    .fetch_all(pool)
+    .await?;
+
+    let mut stats = PurgeStats::default();
+    for chunk in ids.chunks(PURGE_BATCH_SIZE) {
+        purge_batch(pool, chunk, cache_dir, &mut stats).await?;
+    }
+
+    if stats.files_purged > 0 {
+        info!(
+            "资源库 {} 已清理 {} 个丢失文件，删除 {} 个缩略图",
+            library_id, stats.files_purged, stats.thumbnails_removed
+        );
+    }
+    Ok(stats)
+}
+
+/// 在一个事务中删除一批候选文件
+///
+/// 候选 ID 来自事务外的查询，期间文件可能已恢复在线，因此先带状态条件删除文件记录，
+/// 只对实际删除的文件清理标签关联、任务与缩略图。
+async fn purge_batch(
+    pool: &SqlitePool,
+    file_ids: &[i32],
+    cache_dir: &str,
+    stats: &mut PurgeStats,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut purged = Vec::with_capacity(file_ids.len());
+    for &file_id in file_ids {
+        let deleted: Option<i32> = sqlx::query_scalar("DELETE FROM files WHERE id = ? AND status = 0 RETURNING id")
+            .bind(file_id)
+            .fetch_optional(&mut *tx).await?;
+        purged.extend(deleted);
+    }
+    // 外键级联只在开启 foreign_keys 的连接上生效，这里显式清理
+    for &file_id in &purged {
+        sqlx::query("DELETE FROM file_tags WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM tasks WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    stats.files_purged += purged.len() as u64;
+
+    for file_id in purged {
+        let path = thumbnail_path(cache_dir, file_id);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => stats.thumbnails_removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("删除缩略图失败: {} - {}", path, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+
+    #[tokio::test]
+    async fn test_purge_respects_retention() {
+        let cache = tempfile::tempdir().unwrap();
+        let cache_dir = cache.path().to_str().unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime, status, lost_at) VALUES
+             (1, 1, '', 'online.txt', 1, 1, 1, NULL),
+             (2, 1, '', 'old.txt', 1, 1, 0, datetime('now', '-40 days')),
+             (3, 1, '', 'recent.txt', 1, 1, 0, datetime('now', '-1 days'))"
+        )
+        .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO tags (id, name, category) VALUES (1, 'Keep', 'user')")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO file_tags (file_id, tag_id, source) VALUES (2, 1, 'manual'), (3, 1, 'manual')")
+            .execute(&pool).await.unwrap();
+        std::fs::write(thumbnail_path(cache_dir, 2), b"webp").unwrap();
+
+        let stats = purge_lost_files(&pool, 1, 30, cache_dir).await.unwrap();
+        assert_eq!(stats, PurgeStats { files_purged: 1, thumbnails_removed: 1 });
+        assert!(!std::path::Path::new(&thumbnail_path(cache_dir, 2)).exists());
+
+        let remaining: Vec<i32> = sqlx::query_scalar("SELECT id FROM files ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(remaining, vec![1, 3]);
+        let links: Vec<i32> = sqlx::query_scalar("SELECT file_id FROM file_tags").fetch_all(&pool).await.unwrap();
+        assert_eq!(links, vec![3]);
+
+        let stats = purge_lost_files(&pool, 1, 0, cache_dir).await.unwrap();
+        assert_eq!(stats.files_purged, 1);
+    }
+
+    #[tokio::test]
+    async fn test_purge_skips_file_back_online() {
+        let cache = tempfile::tempdir().unwrap();
+        let cache_dir = cache.path().to_str().unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (id, library_id, parent_path, filename, size, mtime, status, lost_at) VALUES
+             (1, 1, '', 'lost.txt', 1, 1, 0, datetime('now', '-40 days')),
+             (2, 1, '', 'back.txt', 1, 1, 0, datetime('now', '-40 days'))"
+        )
+        .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO tags (id, name, category) VALUES (1, 'Keep', 'user')")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO file_tags (file_id, tag_id, source) VALUES (1, 1, 'manual'), (2, 1, 'manual')")
+            .execute(&pool).await.unwrap();
+        for id in [1, 2] {
+            std::fs::write(thumbnail_path(cache_dir, id), b"webp").unwrap();
+        }
+
+        // 查询候选之后、删除之前，文件 2 被扫描重新发现
+        sqlx::query("UPDATE files SET status = 1, lost_at = NULL WHERE id = 2")
+            .execute(&pool).await.unwrap();
+        let mut stats = PurgeStats::default();
+        purge_batch(&pool, &[1, 2], cache_dir, &mut stats).await.unwrap();
+        assert_eq!(stats, PurgeStats { files_purged: 1, thumbnails_removed: 1 });
+
+        let remaining: Vec<i32> = sqlx::query_scalar("SELECT id FROM files").fetch_all(&pool).await.unwrap();
+        assert_eq!(remaining, vec![2]);
+        let links: Vec<i32> = sqlx::query_scalar("SELECT file_id FROM file_tags").fetch_all(&pool).await.unwrap();
+        assert_eq!(links, vec![2]);
+        assert!(std::path::Path::new(&thumbnail_path(cache_dir, 2)).exists());
+        assert!(!std::path::Path::new(&thumbnail_path(cache_dir, 1)).exists());
+    }
+}
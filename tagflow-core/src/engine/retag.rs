@@ -0,0 +1,117 @@
+//! 重新打标签
+//!
+//! 对资源库中的在线文件重新运行标签管道，只替换指定标签器的输出。
+//! 用于修改类型映射、时间粒度等配置后刷新已有文件，或为升级前入库的文件补齐标签。
+
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::engine::scanner::writer::{ScanWriter, DEFAULT_BATCH_SIZE};
+use crate::engine::scanner::ScanProgress;
+use crate::engine::tagger::TaggingPipeline;
+use crate::infra::storage::StorageManager;
+use crate::models::db::Library;
+
+/// 每次从数据库读取的文件数
+const RETAG_PAGE_SIZE: i64 = 500;
+
+/// 重新打标签，`source` 为空时替换所有已启用标签器的输出，返回处理的文件数
+///
+/// 指定的标签器在该资源库中被禁用时，直接删除它此前生成的标签。
+pub async fn retag_library(pool: &SqlitePool, library: &Library, source: Option<&str>) -> anyhow::Result<u64> {
+    // 只替换单个标签器的输出时，只运行该标签器及其依赖，避免无谓的文件读取
+    let pipeline = match source {
+        Some(source) => TaggingPipeline::load_for_source(pool, library, source).await?,
+        None => TaggingPipeline::load(pool, library).await?,
+    };
+
+    if let Some(source) = source
+        && !pipeline.sources().contains(&source)
+    {
+        let res = sqlx::query(
+            "DELETE FROM file_tags WHERE source = ? AND file_id IN (SELECT id FROM files WHERE library_id = ?)"
+        )
+        .bind(source)
+        .bind(library.id)
+        .execute(pool)
+        .await?;
+        info!("资源库 {} 已禁用标签器 {}，移除 {} 个标签关联", library.id, source, res.rows_affected());
+        return Ok(0);
+    }
+
+    let op = StorageManager::get_operator(library)?;
+    let progress = ScanProgress::default();
+    let mut writer = ScanWriter::new(pool, library.id, &progress, DEFAULT_BATCH_SIZE);
+    let mut retagged = 0u64;
+    let mut last_id = 0;
+
+    loop {
+        let files: Vec<(i32, String, i64, i64)> = sqlx::query_as(
+            "SELECT id, parent_path || filename, size, mtime FROM files
+             WHERE library_id = ? AND status = 1 AND id > ? ORDER BY id LIMIT ?"
+        )
+        .bind(library.id)
+        .bind(last_id)
+        .bind(RETAG_PAGE_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let Some(&(last, ..)) = files.last() else { break };
+        last_id = last;
+
+        for (file_id, path, size, mtime) in files {
+            let mut tags = pipeline.run(&op, &path, size.max(0) as u64, mtime).await;
+            if let Some(source) = source {
+                tags.retain(|t| t.source == source);
+            }
+            writer.retag(file_id, tags).await?;
+            retagged += 1;
+        }
+    }
+    writer.flush().await?;
+
+    info!("资源库 {} 重新打标签完成，共 {} 个文件", library.id, retagged);
+    Ok(retagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::scanner::Scanner;
+    use crate::infra::db::test_pool;
+
+    async fn type_tags(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT t.name FROM file_tags ft JOIN tags t ON t.id = ft.tag_id WHERE ft.source = 'type' ORDER BY t.name"
+        )
+        .fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retag_after_mapping_change() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cover.psd"), b"8BPS").unwrap();
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let load = || sqlx::query_as::<_, Library>("SELECT * FROM libraries WHERE id = 1").fetch_one(&pool);
+
+        Scanner::new(pool.clone()).scan_library(&load().await.unwrap()).await.unwrap();
+        assert_eq!(type_tags(&pool).await, vec!["Other"]);
+
+        sqlx::query(r#"UPDATE libraries SET config_json = '{"type_mapping":{"psd":"Image/Design"}}' WHERE id = 1"#)
+            .execute(&pool).await.unwrap();
+        let count = retag_library(&pool, &load().await.unwrap(), Some("type")).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(type_tags(&pool).await, vec!["Design"]);
+        let time_links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_tags WHERE source = 'time'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(time_links, 1);
+
+        // 禁用后重新打标签即移除该标签器的输出
+        sqlx::query(r#"UPDATE libraries SET config_json = '{"disabled_taggers":["type"]}' WHERE id = 1"#)
+            .execute(&pool).await.unwrap();
+        retag_library(&pool, &load().await.unwrap(), Some("type")).await.unwrap();
+        assert!(type_tags(&pool).await.is_empty());
+    }
+}
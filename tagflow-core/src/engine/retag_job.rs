@@ -0,0 +1,209 @@
+//! 重新打标签任务管理
+//!
+//! 重新打标签需要读取文件头，大型资源库可能耗时数十分钟，远超 HTTP 请求超时，
+//! 因此与扫描一样在后台 Tokio 任务中执行。每个任务在 `retag_runs` 表中留下一条记录，
+//! 任务 ID 即记录 ID。任务逐个资源库执行，处理每个资源库前等待其写锁，与扫描互斥。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use sqlx::SqlitePool;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::engine::retag::retag_library;
+use crate::engine::scan_job::{ScanJobManager, ScanRunStatus};
+use crate::models::db::Library;
+
+/// 重新打标签任务提交错误
+#[derive(Debug, Error)]
+pub enum RetagJobError {
+    /// 相同范围的任务正在进行
+    #[error("重新打标签任务 {job_id} 正在进行")]
+    AlreadyRunning { job_id: i64 },
+    /// 创建任务记录失败
+    #[error("创建重新打标签记录失败: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// 重新打标签任务管理器
+///
+/// 克隆开销很低，所有克隆共享同一份运行状态。
+#[derive(Clone)]
+pub struct RetagJobManager {
+    db: SqlitePool,
+    scans: ScanJobManager,
+    /// 资源库 ID (为空表示全部资源库) -> 正在运行的任务 ID
+    running: Arc<Mutex<HashMap<Option<i32>, i64>>>,
+}
+
+impl RetagJobManager {
+    pub fn new(db: SqlitePool, scans: ScanJobManager) -> Self {
+        Self { db, scans, running: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// 提交任务，立即返回任务 ID
+    ///
+    /// `library_id` 为空时依次处理所有资源库；`tagger` 含义同 [`retag_library`] 的 `source`。
+    pub async fn enqueue(&self, library_id: Option<i32>, tagger: Option<&str>) -> Result<i64, RetagJobError> {
+        let job_id = {
+            let mut running = self.running.lock().await;
+            if let Some(&job_id) = running.get(&library_id) {
+                return Err(RetagJobError::AlreadyRunning { job_id });
+            }
+
+            let job_id: i64 = sqlx::query_scalar(
+                "INSERT INTO retag_runs (library_id, tagger, status) VALUES (?, ?, ?) RETURNING id"
+            )
+            .bind(library_id)
+            .bind(tagger)
+            .bind(ScanRunStatus::Running as i32)
+            .fetch_one(&self.db)
+            .await?;
+            running.insert(library_id, job_id);
+            job_id
+        };
+
+        let manager = self.clone();
+        let tagger = tagger.map(str::to_string);
+        tokio::spawn(async move {
+            manager.run(job_id, library_id, tagger).await;
+        });
+
+        Ok(job_id)
+    }
+
+    /// 查询某个范围当前正在运行的任务 ID
+    pub async fn running_job(&self, library_id: Option<i32>) -> Option<i64> {
+        self.running.lock().await.get(&library_id).copied()
+    }
+
+    /// 执行任务并写回运行记录
+    async fn run(&self, job_id: i64, library_id: Option<i32>, tagger: Option<String>) {
+        info!("重新打标签任务 {} 开始: 资源库 {:?}, 标签器 {:?}", job_id, library_id, tagger);
+
+        // 在独立任务中执行，panic 时也能正确收尾
+        let db = self.db.clone();
+        let scans = self.scans.clone();
+        let result = tokio::spawn(async move {
+            let libraries: Vec<Library> =
+                sqlx::query_as("SELECT * FROM libraries WHERE ?1 IS NULL OR id = ?1 ORDER BY id")
+                    .bind(library_id)
+                    .fetch_all(&db)
+                    .await?;
+
+            let mut retagged = 0u64;
+            for library in libraries {
+                let lock = scans.library_lock(library.id);
+                let _guard = lock.lock().await;
+                retagged += retag_library(&db, &library, tagger.as_deref())
+                    .await
+                    .with_context(|| format!("资源库 {}", library.name))?;
+                sqlx::query("UPDATE retag_runs SET files_retagged = ? WHERE id = ?")
+                    .bind(retagged as i64)
+                    .bind(job_id)
+                    .execute(&db)
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await;
+
+        let (status, error_msg) = match result {
+            Ok(Ok(())) => {
+                info!("重新打标签任务 {} 完成", job_id);
+                (ScanRunStatus::Completed, None)
+            }
+            Ok(Err(e)) => {
+                error!("重新打标签任务 {} 失败: {:#}", job_id, e);
+                (ScanRunStatus::Failed, Some(format!("{:#}", e)))
+            }
+            Err(e) => {
+                error!("重新打标签任务 {} 异常退出: {}", job_id, e);
+                (ScanRunStatus::Failed, Some(e.to_string()))
+            }
+        };
+
+        if let Err(e) = sqlx::query(
+            "UPDATE retag_runs SET status = ?, finished_at = CURRENT_TIMESTAMP, error_msg = ? WHERE id = ?"
+        )
+        .bind(status as i32)
+        .bind(error_msg)
+        .bind(job_id)
+        .execute(&self.db)
+        .await
+        {
+            error!("更新重新打标签记录 {} 失败: {}", job_id, e);
+        }
+
+        self.running.lock().await.remove(&library_id);
+    }
+}
+
+/// 将上次进程退出时仍处于"进行中"的任务记录标记为失败
+///
+/// 应在服务启动、提交任何任务之前调用。
+pub async fn fail_interrupted_retags(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let res = sqlx::query(
+        "UPDATE retag_runs SET status = ?, finished_at = CURRENT_TIMESTAMP, error_msg = '服务重启，任务中断'
+         WHERE status = ?"
+    )
+    .bind(ScanRunStatus::Failed as i32)
+    .bind(ScanRunStatus::Running as i32)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::scanner::Scanner;
+    use crate::infra::db::test_pool;
+    use crate::models::db::RetagRun;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_retag_job_waits_for_library_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("b.jpg"), b"b").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let scans = ScanJobManager::new(pool.clone());
+        let manager = RetagJobManager::new(pool.clone(), scans.clone());
+
+        // 持有资源库写锁期间任务排队等待，同范围的任务不能重复提交
+        let lock = scans.library_lock(library.id);
+        let guard = lock.lock().await;
+        let job_id = manager.enqueue(Some(library.id), Some("type")).await.unwrap();
+        assert!(matches!(
+            manager.enqueue(Some(library.id), None).await,
+            Err(RetagJobError::AlreadyRunning { job_id: running }) if running == job_id
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(manager.running_job(Some(library.id)).await, Some(job_id));
+        drop(guard);
+
+        for _ in 0..100 {
+            if manager.running_job(Some(library.id)).await.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let run: RetagRun = sqlx::query_as("SELECT * FROM retag_runs WHERE id = ?")
+            .bind(job_id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(run.status, ScanRunStatus::Completed as i32);
+        assert_eq!((run.files_retagged, run.tagger.as_deref()), (2, Some("type")));
+        assert!(run.finished_at.is_some());
+    }
+}
@@ -0,0 +1,274 @@
+//! 扫描任务管理
+//!
+//! 在后台 Tokio 任务中执行 `Scanner::scan_library`，并保证同一资源库同一时刻只有一个扫描在运行。
+//! 每次扫描都会在 `scan_runs` 表中留下一条记录，任务 ID 即记录 ID。
+//! 扫描成功后按资源库配置的 `lost_retention_days` 清理长期丢失的文件。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::engine::purge::purge_lost_files;
+use crate::engine::scanner::{ScanProgress, ScanStats, Scanner};
+use crate::infra::thumbnail::THUMBNAIL_CACHE_DIR;
+use crate::models::db::Library;
+
+/// 扫描运行状态
+#[repr(i32)]
+pub enum ScanRunStatus {
+    Running = 1,     // 进行中
+    Completed = 2,   // 已完成
+    Failed = 3,      // 失败
+}
+
+/// 扫描任务提交错误
+#[derive(Debug, Error)]
+pub enum ScanJobError {
+    /// 该资源库已有扫描正在进行
+    #[error("资源库 {library_id} 已有扫描任务 {job_id} 正在进行")]
+    AlreadyRunning { library_id: i32, job_id: i64 },
+    /// 创建扫描记录失败
+    #[error("创建扫描记录失败: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// 正在运行的扫描
+pub struct RunningScan {
+    pub job_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub progress: Arc<ScanProgress>,
+}
+
+/// 扫描任务管理器
+///
+/// 克隆开销很低，所有克隆共享同一份运行状态。
+#[derive(Clone)]
+pub struct ScanJobManager {
+    db: SqlitePool,
+    /// library_id -> 正在运行的扫描
+    running: Arc<Mutex<HashMap<i32, Arc<RunningScan>>>>,
+    /// library_id -> 资源库写锁，全量扫描与增量同步互斥
+    locks: Arc<std::sync::Mutex<HashMap<i32, Arc<Mutex<()>>>>>,
+}
+
+impl ScanJobManager {
+    pub fn new(db: SqlitePool) -> Self {
+        Self {
+            db,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 获取资源库写锁
+    ///
+    /// 任何会修改资源库索引的扫描 (全量或增量) 都应先持有此锁。
+    pub fn library_lock(&self, library_id: i32) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(library_id)
+            .or_default()
+            .clone()
+    }
+
+    /// 提交扫描任务，立即返回任务 ID，扫描在后台执行
+    ///
+    /// # 返回
+    /// - `Ok(job_id)`: 任务已提交，`job_id` 为 `scan_runs` 记录 ID
+    /// - `Err(ScanJobError::AlreadyRunning)`: 该资源库已有扫描在运行
+    pub async fn enqueue(&self, library: Library) -> Result<i64, ScanJobError> {
+        let scan = {
+            let mut running = self.running.lock().await;
+            if let Some(scan) = running.get(&library.id) {
+                return Err(ScanJobError::AlreadyRunning { library_id: library.id, job_id: scan.job_id });
+            }
+
+            let (job_id, started_at): (i64, DateTime<Utc>) = sqlx::query_as(
+                "INSERT INTO scan_runs (library_id, status) VALUES (?, ?) RETURNING id, started_at"
+            )
+            .bind(library.id)
+            .bind(ScanRunStatus::Running as i32)
+            .fetch_one(&self.db)
+            .await?;
+
+            let scan = Arc::new(RunningScan {
+                job_id,
+                started_at,
+                progress: Arc::new(ScanProgress::default()),
+            });
+            running.insert(library.id, scan.clone());
+            scan
+        };
+
+        let job_id = scan.job_id;
+        let manager = self.clone();
+        tokio::spawn(async move {
+            manager.run(library, scan).await;
+        });
+
+        Ok(job_id)
+    }
+
+    /// 查询资源库当前正在运行的扫描
+    pub async fn running_scan(&self, library_id: i32) -> Option<Arc<RunningScan>> {
+        self.running.lock().await.get(&library_id).cloned()
+    }
+
+    /// 执行扫描并写回运行记录
+    async fn run(&self, library: Library, scan: Arc<RunningScan>) {
+        let library_id = library.id;
+        let job_id = scan.job_id;
+        info!("扫描任务 {} 开始: 资源库 {}", job_id, library.name);
+
+        // 在独立任务中扫描，panic 时也能正确收尾
+        let db = self.db.clone();
+        let progress = scan.progress.clone();
+        let lock = self.library_lock(library_id);
+        let result = tokio::spawn(async move {
+            let _guard = lock.lock().await;
+            let stats = Scanner::new(db.clone()).scan_library_with_progress(&library, &progress).await?;
+            apply_retention(&db, &library).await;
+            Ok::<_, anyhow::Error>(stats)
+        })
+        .await;
+
+        let (status, error_msg) = match result {
+            Ok(Ok(_)) => {
+                info!("扫描任务 {} 完成", job_id);
+                (ScanRunStatus::Completed, None)
+            }
+            Ok(Err(e)) => {
+                error!("扫描任务 {} 失败: {}", job_id, e);
+                (ScanRunStatus::Failed, Some(e.to_string()))
+            }
+            Err(e) => {
+                error!("扫描任务 {} 异常退出: {}", job_id, e);
+                (ScanRunStatus::Failed, Some(e.to_string()))
+            }
+        };
+
+        if let Err(e) = finish_run(&self.db, job_id, status, &scan.progress.stats(), error_msg.as_deref()).await {
+            error!("更新扫描记录 {} 失败: {}", job_id, e);
+        }
+
+        self.running.lock().await.remove(&library_id);
+    }
+}
+
+/// 按资源库的保留期清理丢失文件；失败只记录日志，不影响扫描结果
+async fn apply_retention(pool: &SqlitePool, library: &Library) {
+    let retention_days = match library.config() {
+        Ok(config) => config.lost_retention_days,
+        Err(_) => None,
+    };
+    if let Some(days) = retention_days
+        && let Err(e) = purge_lost_files(pool, library.id, days, THUMBNAIL_CACHE_DIR).await
+    {
+        warn!("资源库 {} 清理丢失文件失败: {}", library.name, e);
+    }
+}
+
+/// 将上次进程退出时仍处于"进行中"的扫描记录标记为失败
+///
+/// 应在服务启动、提交任何扫描之前调用。
+pub async fn fail_interrupted_runs(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let res = sqlx::query(
+        "UPDATE scan_runs SET status = ?, finished_at = CURRENT_TIMESTAMP, error_msg = '服务重启，扫描中断'
+         WHERE status = ?"
+    )
+    .bind(ScanRunStatus::Failed as i32)
+    .bind(ScanRunStatus::Running as i32)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// 写入扫描结束状态与统计
+async fn finish_run(
+    pool: &SqlitePool,
+    job_id: i64,
+    status: ScanRunStatus,
+    stats: &ScanStats,
+    error_msg: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE scan_runs SET status = ?, finished_at = CURRENT_TIMESTAMP,
+             files_seen = ?, files_added = ?, files_modified = ?, files_moved = ?, files_restored = ?, files_lost = ?,
+             errors = ?, error_msg = ?
+         WHERE id = ?"
+    )
+    .bind(status as i32)
+    .bind(stats.files_seen)
+    .bind(stats.files_added)
+    .bind(stats.files_modified)
+    .bind(stats.files_moved)
+    .bind(stats.files_restored)
+    .bind(stats.files_lost)
+    .bind(stats.errors)
+    .bind(error_msg)
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+    use crate::models::db::ScanRun;
+    use std::time::Duration;
+
+    async fn wait_idle(manager: &ScanJobManager, library_id: i32) {
+        for _ in 0..100 {
+            if manager.running_scan(library_id).await.is_none() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("扫描任务未在预期时间内结束");
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_rejects_concurrent_scan_and_records_run() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("b.txt"), b"b").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+        let library_id = library.id;
+        let again: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+
+        let manager = ScanJobManager::new(pool.clone());
+        let job_id = manager.enqueue(library).await.unwrap();
+        assert!(matches!(
+            manager.enqueue(again).await,
+            Err(ScanJobError::AlreadyRunning { job_id: running, .. }) if running == job_id
+        ));
+
+        wait_idle(&manager, library_id).await;
+
+        let scanned: Option<DateTime<Utc>> =
+            sqlx::query_scalar("SELECT last_scanned_at FROM libraries WHERE id = ?")
+                .bind(library_id)
+                .fetch_one(&pool).await.unwrap();
+        assert!(scanned.is_some());
+
+        let run: ScanRun = sqlx::query_as("SELECT * FROM scan_runs WHERE id = ?")
+            .bind(job_id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(run.status, ScanRunStatus::Completed as i32);
+        assert!(run.finished_at.is_some());
+        assert_eq!((run.files_seen, run.files_added, run.files_lost), (2, 2, 0));
+    }
+}
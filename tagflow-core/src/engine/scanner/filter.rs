@@ -0,0 +1,176 @@
+//! 扫描排除规则
+//!
+//! 规则来源有两处，均采用 gitignore 语法：
+//! - 资源库配置 `config_json` 中的 `exclude` / `include` 列表 (相对于资源库根目录)
+//! - 目录树中的 `.tagflowignore` 文件 (相对于文件所在目录，向下继承)
+
+use std::sync::Arc;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use crate::models::db::LibraryConfig;
+
+/// 目录级排除规则文件名
+pub const IGNORE_FILE: &str = ".tagflowignore";
+
+/// 默认排除项：版本控制、依赖目录与各类 NAS/系统生成的元数据文件
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    ".git/",
+    ".svn/",
+    "node_modules/",
+    "@eaDir/",
+    "#recycle/",
+    ".DS_Store",
+    "._*",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// 资源库级过滤规则
+pub struct ScanFilter {
+    excludes: Gitignore,
+    /// 为空表示包含全部文件
+    includes: Option<Gitignore>,
+}
+
+impl ScanFilter {
+    /// 根据资源库配置构建过滤规则
+    pub fn from_config(config: &LibraryConfig) -> anyhow::Result<Self> {
+        let mut excludes: Vec<&str> = Vec::new();
+        if config.default_excludes {
+            excludes.extend_from_slice(DEFAULT_EXCLUDES);
+        }
+        excludes.push(IGNORE_FILE);
+        excludes.extend(config.exclude.iter().map(String::as_str));
+
+        let includes = if config.include.is_empty() {
+            None
+        } else {
+            Some(build_matcher(config.include.iter().map(String::as_str))?)
+        };
+
+        Ok(Self {
+            excludes: build_matcher(excludes)?,
+            includes,
+        })
+    }
+
+    /// 判断路径是否被排除；`path` 为相对资源库根目录的路径 (不含首尾 `/`)
+    pub fn is_excluded(&self, path: &str, is_dir: bool) -> bool {
+        self.excludes.matched(path, is_dir).is_ignore()
+    }
+
+    /// 判断文件是否满足包含规则
+    pub fn is_included(&self, path: &str) -> bool {
+        match &self.includes {
+            Some(includes) => includes.matched_path_or_any_parents(path, false).is_ignore(),
+            None => true,
+        }
+    }
+}
+
+/// 目录中 `.tagflowignore` 的规则
+pub struct IgnoreFile {
+    /// 规则文件所在目录 (相对资源库根目录，空串表示根目录，否则以 `/` 结尾)
+    dir: String,
+    matcher: Gitignore,
+}
+
+impl IgnoreFile {
+    /// 解析 `.tagflowignore` 内容
+    pub fn parse(dir: &str, content: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: dir.to_string(),
+            matcher: build_matcher(content.lines())?,
+        })
+    }
+
+    fn matched(&self, path: &str, is_dir: bool) -> Match<()> {
+        match path.strip_prefix(&self.dir) {
+            Some(rel) => match self.matcher.matched(rel, is_dir) {
+                Match::Ignore(_) => Match::Ignore(()),
+                Match::Whitelist(_) => Match::Whitelist(()),
+                Match::None => Match::None,
+            },
+            None => Match::None,
+        }
+    }
+}
+
+/// 按由深到浅的顺序应用目录规则，越深的 `.tagflowignore` 优先级越高 (与 git 一致)
+pub fn is_ignored(ignore_files: &[Arc<IgnoreFile>], path: &str, is_dir: bool) -> bool {
+    for file in ignore_files.iter().rev() {
+        match file.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+fn build_matcher<'a>(lines: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("/");
+    for line in lines {
+        builder
+            .add_line(None, line)
+            .map_err(|e| anyhow::anyhow!("无效的排除规则 '{}': {}", line, e))?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> LibraryConfig {
+        LibraryConfig::parse(Some(json)).unwrap()
+    }
+
+    #[test]
+    fn test_default_excludes() {
+        let filter = ScanFilter::from_config(&LibraryConfig::default()).unwrap();
+        assert!(filter.is_excluded("Work/.git", true));
+        assert!(filter.is_excluded("node_modules", true));
+        assert!(filter.is_excluded("Photos/@eaDir", true));
+        assert!(filter.is_excluded("Photos/.DS_Store", false));
+        assert!(filter.is_excluded("Thumbs.db", false));
+        assert!(filter.is_excluded("Work/.tagflowignore", false));
+        assert!(!filter.is_excluded("Work/Design", true));
+        assert!(!filter.is_excluded("Work/main.rs", false));
+
+        let filter = ScanFilter::from_config(&config(r#"{"default_excludes": false}"#)).unwrap();
+        assert!(!filter.is_excluded("node_modules", true));
+    }
+
+    #[test]
+    fn test_config_include_exclude() {
+        let filter = ScanFilter::from_config(&config(
+            r#"{"exclude": ["*.tmp", "/cache/"], "include": ["*.jpg", "Docs/"]}"#,
+        ))
+        .unwrap();
+        assert!(filter.is_excluded("a/b.tmp", false));
+        assert!(filter.is_excluded("cache", true));
+        assert!(!filter.is_excluded("sub/cache", true));
+
+        assert!(filter.is_included("2024/trip/a.jpg"));
+        assert!(filter.is_included("Docs/report.pdf"));
+        assert!(!filter.is_included("notes.txt"));
+    }
+
+    #[test]
+    fn test_nested_ignore_files() {
+        let root = Arc::new(IgnoreFile::parse("", "*.log\nbuild/\n").unwrap());
+        let nested = Arc::new(IgnoreFile::parse("Work/", "!keep.log\n/secret.txt\n").unwrap());
+        let stack = vec![root, nested];
+
+        assert!(is_ignored(&stack, "a.log", false));
+        assert!(is_ignored(&stack, "Work/debug.log", false));
+        assert!(!is_ignored(&stack, "Work/keep.log", false));
+        assert!(is_ignored(&stack, "Work/secret.txt", false));
+        assert!(!is_ignored(&stack, "Work/Sub/secret.txt", false));
+        assert!(is_ignored(&stack, "Work/build", true));
+        assert!(!is_ignored(&stack, "Work/build", false));
+    }
+}
@@ -0,0 +1,96 @@
+//! 文件内容哈希
+//!
+//! 为移动/重命名检测计算内容指纹。哈希值带有模式前缀 (`sampled:` / `full:`)，
+//! 不同模式下得到的指纹互不匹配。
+
+use futures_util::stream::StreamExt;
+use opendal::Operator;
+
+use crate::models::db::HashMode;
+
+/// 采样哈希每段读取的字节数
+const SAMPLE_SIZE: u64 = 4096;
+
+/// 按指定模式计算文件内容哈希，`HashMode::Off` 时返回 `None`
+pub async fn compute_hash(op: &Operator, path: &str, size: u64, mode: HashMode) -> anyhow::Result<Option<String>> {
+    let digest = match mode {
+        HashMode::Off => return Ok(None),
+        HashMode::Sampled => format!("sampled:{}", sampled_hash(op, path, size).await?),
+        HashMode::Full => format!("full:{}", full_hash(op, path, size).await?),
+    };
+    Ok(Some(digest))
+}
+
+/// 读取文件首、中、尾三段并连同文件大小一起哈希
+async fn sampled_hash(op: &Operator, path: &str, size: u64) -> anyhow::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+
+    let reader = op.reader(path).await?;
+    for range in sample_ranges(size) {
+        let buf = reader.read(range).await?;
+        for chunk in buf {
+            hasher.update(&chunk);
+        }
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// 流式读取整个文件计算哈希
+async fn full_hash(op: &Operator, path: &str, size: u64) -> anyhow::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+
+    let mut stream = op.reader(path).await?.into_bytes_stream(..).await?;
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk?);
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// 计算采样区间；小文件直接整体读取
+fn sample_ranges(size: u64) -> Vec<std::ops::Range<u64>> {
+    if size <= SAMPLE_SIZE * 3 {
+        return std::iter::once(0..size).collect();
+    }
+    let mid = size / 2 - SAMPLE_SIZE / 2;
+    vec![
+        0..SAMPLE_SIZE,
+        mid..mid + SAMPLE_SIZE,
+        size - SAMPLE_SIZE..size,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_ranges() {
+        assert_eq!(sample_ranges(100), std::iter::once(0..100).collect::<Vec<_>>());
+        assert_eq!(
+            sample_ranges(100_000),
+            vec![0..4096, 47_952..52_048, 95_904..100_000]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_modes_are_distinct() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.bin"), vec![7u8; 20_000]).unwrap();
+        std::fs::write(dir.path().join("b.bin"), vec![7u8; 20_000]).unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+
+        let a = compute_hash(&op, "a.bin", 20_000, HashMode::Sampled).await.unwrap().unwrap();
+        let b = compute_hash(&op, "b.bin", 20_000, HashMode::Sampled).await.unwrap().unwrap();
+        let full = compute_hash(&op, "a.bin", 20_000, HashMode::Full).await.unwrap().unwrap();
+        assert_eq!(a, b);
+        assert!(a.starts_with("sampled:"));
+        assert!(full.starts_with("full:"));
+        assert_eq!(compute_hash(&op, "a.bin", 20_000, HashMode::Off).await.unwrap(), None);
+    }
+}
@@ -1,127 +1,680 @@
-use std::collections::HashMap;
-use futures_util::stream::StreamExt;
+pub mod filter;
+pub mod hash;
+pub mod walker;
+pub mod writer;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicI64, Ordering};
+use opendal::Operator;
+use serde::Serialize;
 use sqlx::SqlitePool;
-use crate::models::db::Library;
+use crate::models::db::{HashMode, Library};
 use crate::infra::storage::StorageManager;
-use crate::engine::tagger::PathTagger;
-use crate::core::tag::TagManager;
-use tracing::info;
+use crate::engine::tagger::TaggingPipeline;
+use filter::ScanFilter;
+use hash::compute_hash;
+use walker::LibraryWalker;
+use writer::{ScanWriter, DEFAULT_BATCH_SIZE};
+use tracing::{debug, info, warn};
+
+/// 扫描统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScanStats {
+    pub files_seen: i64,
+    pub files_added: i64,
+    pub files_modified: i64,
+    pub files_moved: i64,
+    /// 重新出现而恢复在线的丢失文件
+    pub files_restored: i64,
+    pub files_lost: i64,
+    pub errors: i64,
+}
+
+/// 扫描实时进度
+///
+/// 由扫描任务与 API 共享，计数器在扫描过程中实时递增。
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    /// 扫描开始时数据库中已索引的文件数，可作为进度条的预估总数
+    expected_total: AtomicI64,
+    files_seen: AtomicI64,
+    files_added: AtomicI64,
+    files_modified: AtomicI64,
+    files_moved: AtomicI64,
+    files_restored: AtomicI64,
+    files_lost: AtomicI64,
+    errors: AtomicI64,
+}
+
+impl ScanProgress {
+    pub fn expected_total(&self) -> i64 {
+        self.expected_total.load(Ordering::Relaxed)
+    }
+
+    /// 读取当前统计快照
+    pub fn stats(&self) -> ScanStats {
+        ScanStats {
+            files_seen: self.files_seen.load(Ordering::Relaxed),
+            files_added: self.files_added.load(Ordering::Relaxed),
+            files_modified: self.files_modified.load(Ordering::Relaxed),
+            files_moved: self.files_moved.load(Ordering::Relaxed),
+            files_restored: self.files_restored.load(Ordering::Relaxed),
+            files_lost: self.files_lost.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    fn incr(counter: &AtomicI64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn decr(counter: &AtomicI64) {
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 扫描范围
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScanScope {
+    /// 相对资源库根目录的目录路径：根目录为空串，其余以 `/` 结尾 (与 `files.parent_path` 一致)
+    pub dir: String,
+    /// 是否包含子目录
+    pub recursive: bool,
+}
+
+impl ScanScope {
+    /// 整个资源库
+    pub fn root() -> Self {
+        Self { dir: String::new(), recursive: true }
+    }
+
+    /// 去重并合并已被递归范围覆盖的范围，合并后各范围遍历的目录互不重叠
+    pub fn merge(scopes: impl IntoIterator<Item = ScanScope>) -> Vec<ScanScope> {
+        let scopes: BTreeSet<ScanScope> = scopes.into_iter().collect();
+        let recursive: Vec<String> = scopes.iter().filter(|s| s.recursive).map(|s| s.dir.clone()).collect();
+        scopes
+            .into_iter()
+            .filter(|scope| {
+                !recursive.iter().any(|dir| {
+                    scope.dir.starts_with(dir.as_str()) && (scope.dir != *dir || !scope.recursive)
+                })
+            })
+            .collect()
+    }
+}
+
+/// 数据库中已索引的文件记录 (扫描快照)
+struct DbEntry {
+    id: i32,
+    size: i64,
+    mtime: i64,
+    hash: Option<String>,
+    status: i32,
+}
 
 pub struct Scanner {
     db: SqlitePool,
+    /// 每个写入事务包含的行数
+    batch_size: usize,
 }
 
 impl Scanner {
     pub fn new(db: SqlitePool) -> Self {
-        Self { db }
+        Self { db, batch_size: DEFAULT_BATCH_SIZE }
+    }
+
+    /// 设置每个写入事务包含的行数
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
     }
 
     /// 执行扫描主逻辑
-    pub async fn scan_library(&self, library: &Library) -> anyhow::Result<()> {
+    pub async fn scan_library(&self, library: &Library) -> anyhow::Result<ScanStats> {
+        self.scan_library_with_progress(library, &ScanProgress::default()).await
+    }
+
+    /// 执行全量扫描，并将实时计数写入 `progress`
+    ///
+    /// 单个目录或文件的读取失败只计入 `errors` 并跳过，不会中断整个扫描。
+    /// 被排除规则命中的文件不会被索引；此前已索引的此类文件会在本次扫描中标记为丢失。
+    pub async fn scan_library_with_progress(&self, library: &Library, progress: &ScanProgress) -> anyhow::Result<ScanStats> {
         info!("开始扫描资源库: {}", library.name);
+        let stats = self.scan_scopes(library, &[ScanScope::root()], progress).await?;
+
+        // 记录扫描完成时间
+        sqlx::query("UPDATE libraries SET last_scanned_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(library.id)
+            .execute(&self.db)
+            .await?;
+
+        info!("资源库 {} 扫描完成: {:?}", library.name, stats);
+        Ok(stats)
+    }
+
+    /// 仅同步指定范围内的文件 (供文件监听等增量场景使用)
+    ///
+    /// 逐目录进行差异对比：每次只加载当前目录的已索引记录，峰值内存取决于最大的单个目录。
+    /// 遍历到的目录把其中的文件记为本次扫描代数 (`files.scan_gen`)，遍历结束后范围内代数落后的
+    /// 文件 (所在目录已删除或被排除) 一次性标记丢失，无需在内存中保存已遍历的目录。
+    /// 移动/重命名在遍历结束后通过数据库按 (大小, 哈希) 配对识别，因此跨目录、跨范围同样有效。
+    /// 增量同步不会更新 `last_scanned_at`。
+    pub async fn scan_scopes(&self, library: &Library, scopes: &[ScanScope], progress: &ScanProgress) -> anyhow::Result<ScanStats> {
         let op = StorageManager::get_operator(library)?;
 
-        // 1. 获取数据库快照 (Path -> (Size, MTime))
-        let snapshot = self.get_db_snapshot(library.id).await?;
-        let mut remote_paths = snapshot; // 用于追踪哪些文件还在
+        let config = library.config()?;
+        let hash_mode = config.hash_mode;
+        let filter = ScanFilter::from_config(&config)?;
+        let pipeline = TaggingPipeline::load(&self.db, library).await?;
 
-        // 2. 递归遍历物理文件
-        let mut lister = op.lister_with("/").recursive(true).await?;
+        let scopes = ScanScope::merge(scopes.iter().cloned());
+        let mut expected_total = 0;
+        for scope in &scopes {
+            expected_total += self.count_indexed(library.id, scope).await?;
+        }
+        progress.expected_total.store(expected_total, Ordering::Relaxed);
 
-        while let Some(entry) = lister.next().await {
-            let entry = entry?;
-            let metadata = op.stat(entry.path()).await?;
+        // 本次扫描新增的记录 ID 均大于此值，用于移动检测
+        let max_id_before: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM files")
+            .fetch_one(&self.db)
+            .await?;
 
-            if metadata.is_dir() {
-                continue;
-            }
+        // 同一资源库的扫描由资源库写锁串行化，代数单调递增
+        let scan_gen: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(scan_gen), 0) + 1 FROM files WHERE library_id = ?")
+            .bind(library.id)
+            .fetch_one(&self.db)
+            .await?;
+
+        let mut writer = ScanWriter::new(&self.db, library.id, progress, self.batch_size).with_scan_gen(scan_gen);
+        let mut failed_dirs: Vec<String> = Vec::new();
 
-            let path = entry.path().to_string();
-            let size = metadata.content_length() as i64;
-            let mtime = metadata.last_modified().map(|t| t.timestamp()).unwrap_or(0);
+        // 1. 逐目录遍历物理文件 (排除规则在列举阶段生效)
+        for scope in &scopes {
+            let mut walker = LibraryWalker::open(&op, &filter, scope, config.stat_concurrency).await?;
 
-            // 3. 差异对比
-            if let Some((db_size, db_mtime)) = remote_paths.remove(&path) {
-                if db_size != size || db_mtime != mtime {
-                    // 文件已修改
-                    self.update_file(library.id, &path, size, mtime).await?;
+            while let Some(listing) = walker.next_dir().await {
+                let listing = match listing {
+                    Ok(listing) => listing,
+                    Err(e) => {
+                        warn!("{:#}", e);
+                        ScanProgress::incr(&progress.errors);
+                        continue;
+                    }
+                };
+                // 2. 与该目录的已索引记录对比；读取元数据失败的文件状态未知，保持原样
+                let mut snapshot = self.get_dir_snapshot(library.id, &listing.dir).await?;
+                for (path, e) in &listing.failed {
+                    warn!("{:#}", e);
+                    ScanProgress::incr(&progress.errors);
+                    snapshot.remove(&path[listing.dir.len()..]);
                 }
-                // 如果一致，则什么都不做
-            } else {
-                // 新增文件
-                self.insert_file(library.id, &path, size, mtime).await?;
+                for (path, metadata) in listing.files {
+                    ScanProgress::incr(&progress.files_seen);
+
+                    let size = metadata.content_length() as i64;
+                    let mtime = metadata.last_modified().map(|t| t.timestamp()).unwrap_or(0);
+                    let filename = &path[listing.dir.len()..];
+
+                    if let Some(db_entry) = snapshot.remove(filename) {
+                        if db_entry.size != size || db_entry.mtime != mtime {
+                            // 文件已修改 (同时恢复在线)
+                            let hash = self.hash_or_count_error(&op, &path, size, hash_mode, progress).await;
+                            let tags = pipeline.run(&op, &path, size as u64, mtime).await;
+                            writer.update(db_entry.id, size, mtime, hash, tags).await?;
+                            ScanProgress::incr(&progress.files_modified);
+                        } else {
+                            if db_entry.status == 0 {
+                                // 丢失的文件原样重新出现
+                                writer.restore(db_entry.id).await?;
+                                ScanProgress::incr(&progress.files_restored);
+                            }
+                            if db_entry.hash.is_none() && hash_mode != HashMode::Off {
+                                // 内容未变，但尚无哈希 (旧版本索引)：补算一次
+                                if let Some(hash) = self.hash_or_count_error(&op, &path, size, hash_mode, progress).await {
+                                    writer.set_hash(db_entry.id, hash).await?;
+                                }
+                            }
+                        }
+                    } else {
+                        // 新增文件 (可能是移动而来，遍历结束后再与丢失记录配对)
+                        let hash = self.hash_or_count_error(&op, &path, size, hash_mode, progress).await;
+                        let tags = pipeline.run(&op, &path, size as u64, mtime).await;
+                        writer.insert(&path, size, mtime, hash, tags).await?;
+                        ScanProgress::incr(&progress.files_added);
+                    }
+                }
+
+                // 目录中剩余的记录即为物理上已删除的文件
+                for entry in snapshot.into_values() {
+                    writer.mark_lost(entry.id).await?;
+                }
+                writer.mark_dir_seen(&listing.dir).await?;
             }
+
+            failed_dirs.extend(walker.failed_dirs().iter().cloned());
+        }
+
+        // 3. 范围内未被遍历到的目录 (已删除、被排除) 下的文件全部标记丢失，列举失败的目录除外
+        for scope in &scopes {
+            writer.mark_unseen_lost(&scope.dir, scope.recursive, &failed_dirs).await?;
         }
+        writer.flush().await?;
+
+        // 4. 移动检测：用 (大小, 哈希) 将本次新增的记录与丢失的旧记录配对，
+        //    旧记录迁移到新路径以保留文件 ID 与标签，新记录删除。
+        //    采样哈希只覆盖部分内容，仅与本次扫描中丢失的记录配对；完整哈希可与任意丢失记录配对
+        let pairs: Vec<(i32, String, i64, i64, i32)> = sqlx::query_as(
+            "SELECT n.id, n.parent_path || n.filename, n.size, n.mtime, o.id FROM files n
+             JOIN files o ON o.library_id = n.library_id AND o.size = n.size AND o.hash = n.hash
+             WHERE n.library_id = ?1 AND n.id > ?2 AND n.hash IS NOT NULL
+               AND o.id <= ?2 AND o.status = 0
+             ORDER BY n.id, o.id"
+        )
+        .bind(library.id)
+        .bind(max_id_before)
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut claimed_new = HashSet::new();
+        let mut claimed_old = HashSet::new();
+        for (new_id, path, size, mtime, old_id) in pairs {
+            if hash_mode != HashMode::Full && !writer.lost_in_run(old_id) {
+                continue;
+            }
+            if claimed_new.contains(&new_id) || !claimed_old.insert(old_id) {
+                continue;
+            }
+            claimed_new.insert(new_id);
+            debug!("检测到文件移动: #{} -> {}", old_id, path);
 
-        // 4. 清理阶段：remote_paths 中剩余的即为物理上已删除的文件
-        for (deleted_path, _) in remote_paths {
-            self.mark_as_lost(library.id, &deleted_path).await?;
+            let tags = pipeline.run(&op, &path, size as u64, mtime).await;
+            writer.discard(new_id).await?;
+            writer.relocate(old_id, &path, size, mtime, tags).await?;
+            ScanProgress::decr(&progress.files_added);
+            if writer.lost_in_run(old_id) {
+                ScanProgress::decr(&progress.files_lost);
+            }
+            ScanProgress::incr(&progress.files_moved);
         }
+        writer.flush().await?;
 
-        info!("资源库 {} 扫描完成", library.name);
-        Ok(())
+        Ok(progress.stats())
     }
 
     // --- 数据库操作辅助函数 ---
 
-    async fn get_db_snapshot(&self, lib_id: i32) -> anyhow::Result<HashMap<String, (i64, i64)>> {
-        let rows: Vec<(String, i64, i64)> = sqlx::query_as(
-            "SELECT parent_path || filename as path, size, mtime FROM files WHERE library_id = ?"
+    /// 计算内容哈希；读取失败时计入错误并返回 `None`，不中断扫描
+    async fn hash_or_count_error(
+        &self,
+        op: &Operator,
+        path: &str,
+        size: i64,
+        mode: HashMode,
+        progress: &ScanProgress,
+    ) -> Option<String> {
+        match compute_hash(op, path, size as u64, mode).await {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!("计算文件哈希失败: {} - {}", path, e);
+                ScanProgress::incr(&progress.errors);
+                None
+            }
+        }
+    }
+
+    /// 范围内已索引的在线文件数
+    async fn count_indexed(&self, lib_id: i32, scope: &ScanScope) -> anyhow::Result<i64> {
+        let sql = if scope.recursive {
+            "SELECT COUNT(*) FROM files
+             WHERE library_id = ?1 AND status != 0 AND substr(parent_path, 1, length(?2)) = ?2"
+        } else {
+            "SELECT COUNT(*) FROM files WHERE library_id = ?1 AND status != 0 AND parent_path = ?2"
+        };
+        Ok(sqlx::query_scalar(sql).bind(lib_id).bind(&scope.dir).fetch_one(&self.db).await?)
+    }
+
+    /// 单个目录的已索引记录 (文件名 -> 记录)
+    async fn get_dir_snapshot(&self, lib_id: i32, dir: &str) -> anyhow::Result<HashMap<String, DbEntry>> {
+        let rows: Vec<(String, i32, i64, i64, Option<String>, i32)> = sqlx::query_as(
+            "SELECT filename, id, size, mtime, hash, status FROM files WHERE library_id = ? AND parent_path = ?"
         )
         .bind(lib_id)
+        .bind(dir)
         .fetch_all(&self.db)
         .await?;
 
-        Ok(rows.into_iter().map(|(p, s, m)| (p, (s, m))).collect())
+        Ok(rows
+            .into_iter()
+            .map(|(name, id, size, mtime, hash, status)| (name, DbEntry { id, size, mtime, hash, status }))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tag::TagManager;
+    use crate::infra::db::test_pool;
+    use crate::infra::storage::tests::spawn_webdav_stub;
+
+    #[tokio::test]
+    async fn test_scan_webdav_library() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("Work/Design")).unwrap();
+        std::fs::write(dir.path().join("Work/Design/logo.png"), b"png").unwrap();
+        std::fs::write(dir.path().join("readme.txt"), b"hello").unwrap();
+
+        let server = spawn_webdav_stub(dir.path().to_path_buf()).await;
+        let pool = test_pool().await;
+        let config = serde_json::json!({ "endpoint": server, "username": "u", "password": "p" });
+        let res = sqlx::query("INSERT INTO libraries (name, protocol, base_path, config_json) VALUES ('dav', 'webdav', '/dav/', ?)")
+            .bind(config.to_string())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = ?")
+            .bind(res.last_insert_rowid())
+            .fetch_one(&pool).await.unwrap();
+
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let mut files: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT parent_path, filename, size FROM files ORDER BY filename"
+        )
+        .fetch_all(&pool).await.unwrap();
+        files.sort();
+        assert_eq!(files, vec![
+            ("".to_string(), "readme.txt".to_string(), 5),
+            ("Work/Design/".to_string(), "logo.png".to_string(), 3),
+        ]);
+    }
+
+    async fn local_library(pool: &SqlitePool, root: &std::path::Path, config_json: Option<&str>) -> Library {
+        let res = sqlx::query("INSERT INTO libraries (name, protocol, base_path, config_json) VALUES ('local', 'local', ?, ?)")
+            .bind(root.to_str().unwrap())
+            .bind(config_json)
+            .execute(pool).await.unwrap();
+        sqlx::query_as("SELECT * FROM libraries WHERE id = ?")
+            .bind(res.last_insert_rowid())
+            .fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_move_keeps_file_id_and_manual_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("Inbox")).unwrap();
+        std::fs::create_dir_all(dir.path().join("Archive/2024")).unwrap();
+        std::fs::write(dir.path().join("Inbox/photo.jpg"), vec![1u8; 30_000]).unwrap();
+        std::fs::write(dir.path().join("Inbox/other.jpg"), vec![2u8; 30_000]).unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, dir.path(), None).await;
+        let scanner = Scanner::new(pool.clone());
+        scanner.scan_library(&library).await.unwrap();
+
+        let (file_id, hash): (i32, Option<String>) =
+            sqlx::query_as("SELECT id, hash FROM files WHERE filename = 'photo.jpg'")
+                .fetch_one(&pool).await.unwrap();
+        assert!(hash.unwrap().starts_with("sampled:"));
+
+        // 手动标签
+        let tag_id = sqlx::query("INSERT INTO tags (name, category) VALUES ('Favorite', 'user')")
+            .execute(&pool).await.unwrap().last_insert_rowid();
+        TagManager::new(pool.clone()).link_file_to_tag(file_id, tag_id as i32, "manual").await.unwrap();
+
+        std::fs::rename(dir.path().join("Inbox/photo.jpg"), dir.path().join("Archive/2024/renamed.jpg")).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.files_moved, stats.files_added, stats.files_lost), (1, 0, 0));
+
+        let (parent, filename, status): (String, String, i32) =
+            sqlx::query_as("SELECT parent_path, filename, status FROM files WHERE id = ?")
+                .bind(file_id)
+                .fetch_one(&pool).await.unwrap();
+        assert_eq!((parent.as_str(), filename.as_str(), status), ("Archive/2024/", "renamed.jpg", 1));
+
+        let tags: Vec<(String, String)> = sqlx::query_as(
+            "SELECT t.name, ft.source FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+             WHERE ft.file_id = ? AND t.category != 'time' ORDER BY t.name"
+        )
+        .bind(file_id)
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(tags, vec![
+            ("2024".to_string(), "path".to_string()),
+            ("Favorite".to_string(), "manual".to_string()),
+            ("Image".to_string(), "type".to_string()),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stat_failure_keeps_file_online() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("b.txt"), b"b").unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, dir.path(), None).await;
+        let scanner = Scanner::new(pool.clone());
+        scanner.scan_library(&library).await.unwrap();
+
+        // 指向自身的符号链接仍会被列举，但 stat 失败 (ELOOP)
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+        std::os::unix::fs::symlink("a.txt", dir.path().join("a.txt")).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.errors, stats.files_lost), (1, 0));
+
+        let status: i32 = sqlx::query_scalar("SELECT status FROM files WHERE filename = 'a.txt'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(status, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_hash_does_not_pair_long_lost_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // 两个文件大小相同，只在采样区间之外不同
+        let original = vec![0u8; 40_000];
+        let mut different = original.clone();
+        different[8_000] = 1;
+        std::fs::write(dir.path().join("old.bin"), &original).unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, dir.path(), None).await;
+        let scanner = Scanner::new(pool.clone());
+        scanner.scan_library(&library).await.unwrap();
+        let (old_id, old_hash): (i32, String) = sqlx::query_as("SELECT id, hash FROM files WHERE filename = 'old.bin'")
+            .fetch_one(&pool).await.unwrap();
+
+        std::fs::remove_file(dir.path().join("old.bin")).unwrap();
+        scanner.scan_library(&library).await.unwrap();
+
+        // 此前扫描中已丢失的记录不参与采样哈希配对
+        std::fs::write(dir.path().join("new.bin"), &different).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.files_moved, stats.files_added), (0, 1));
+
+        let (new_id, new_hash): (i32, String) = sqlx::query_as("SELECT id, hash FROM files WHERE filename = 'new.bin'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(new_hash, old_hash);
+        assert_ne!(new_id, old_id);
+        let old_status: i32 = sqlx::query_scalar("SELECT status FROM files WHERE id = ?")
+            .bind(old_id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(old_status, 0);
+    }
+
+    #[tokio::test]
+    async fn test_hash_mode_off_disables_move_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"same").unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, dir.path(), Some(r#"{"hash_mode":"off"}"#)).await;
+        let scanner = Scanner::new(pool.clone());
+        scanner.scan_library(&library).await.unwrap();
+
+        std::fs::rename(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.files_moved, stats.files_added, stats.files_lost), (0, 1, 1));
     }
 
-    async fn insert_file(&self, lib_id: i32, full_path: &str, size: i64, mtime: i64) -> anyhow::Result<()> {
-        let (parent, filename) = self.split_path(full_path);
-        let ext = filename.split('.').next_back().map(|s| s.to_lowercase());
+    #[tokio::test]
+    async fn test_exclusion_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for d in ["Work/.git", "Work/node_modules/pkg", "Photos/@eaDir", "Photos/raw", "Docs"] {
+            std::fs::create_dir_all(root.join(d)).unwrap();
+        }
+        for f in [
+            "Work/main.rs", "Work/.git/HEAD", "Work/node_modules/pkg/index.js",
+            "Photos/a.jpg", "Photos/.DS_Store", "Photos/@eaDir/a.jpg", "Photos/raw/a.cr2",
+            "Photos/raw/keep.cr2", "Docs/draft.tmp", "Docs/report.pdf",
+        ] {
+            std::fs::write(root.join(f), f.as_bytes()).unwrap();
+        }
+        std::fs::write(root.join("Photos/.tagflowignore"), "raw/*\n!raw/keep.cr2\n").unwrap();
 
-        // 1. 插入文件记录
-        let res = sqlx::query(
-            "INSERT INTO files (library_id, parent_path, filename, extension, size, mtime) VALUES (?, ?, ?, ?, ?, ?)"
+        let pool = test_pool().await;
+        let library = local_library(&pool, root, Some(r#"{"exclude": ["*.tmp"]}"#)).await;
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let files: Vec<(String,)> = sqlx::query_as(
+            "SELECT parent_path || filename FROM files ORDER BY 1"
         )
-        .bind(lib_id).bind(&parent).bind(&filename).bind(ext).bind(size).bind(mtime)
-        .execute(&self.db).await?;
+        .fetch_all(&pool).await.unwrap();
+        let files: Vec<String> = files.into_iter().map(|(p,)| p).collect();
+        assert_eq!(files, vec!["Docs/report.pdf", "Photos/a.jpg", "Photos/raw/keep.cr2", "Work/main.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_directory_rename_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("Old/Sub")).unwrap();
+        std::fs::create_dir_all(root.join("Trash")).unwrap();
+        std::fs::write(root.join("Old/a.txt"), b"aaa").unwrap();
+        std::fs::write(root.join("Old/Sub/b.txt"), b"bbbb").unwrap();
+        std::fs::write(root.join("Trash/c.txt"), b"ccccc").unwrap();
 
-        let file_id = res.last_insert_rowid() as i32;
+        let pool = test_pool().await;
+        let library = local_library(&pool, root, None).await;
+        let scanner = Scanner::new(pool.clone()).with_batch_size(2);
+        scanner.scan_library(&library).await.unwrap();
+        let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM files ORDER BY parent_path || filename")
+            .fetch_all(&pool).await.unwrap();
 
-        // 2. 触发标签化 (Milestone 3 核心)
-        let tag_mgr = TagManager::new(self.db.clone());
-        let path_tagger = PathTagger::new(tag_mgr);
-        path_tagger.process_path(file_id, &parent).await?;
+        std::fs::rename(root.join("Old"), root.join("New")).unwrap();
+        std::fs::remove_dir_all(root.join("Trash")).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.files_moved, stats.files_added, stats.files_lost), (2, 0, 1));
 
-        Ok(())
+        // 按原路径排序: Old/Sub/b.txt, Old/a.txt, Trash/c.txt
+        let files: Vec<(i32, String, i32)> = sqlx::query_as(
+            "SELECT id, parent_path || filename, status FROM files ORDER BY parent_path || filename"
+        )
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(files, vec![
+            (ids[0], "New/Sub/b.txt".to_string(), 1),
+            (ids[1], "New/a.txt".to_string(), 1),
+            (ids[2], "Trash/c.txt".to_string(), 0),
+        ]);
     }
 
-    async fn update_file(&self, lib_id: i32, full_path: &str, size: i64, mtime: i64) -> anyhow::Result<()> {
-        let (parent, filename) = self.split_path(full_path);
-        sqlx::query(
-            "UPDATE files SET size = ?, mtime = ?, status = 1 WHERE library_id = ? AND parent_path = ? AND filename = ?"
+    #[tokio::test]
+    async fn test_scopes_mark_only_unvisited_files_lost() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("Work/Gone")).unwrap();
+        std::fs::create_dir_all(root.join("Other")).unwrap();
+        std::fs::write(root.join("Work/a.txt"), b"a").unwrap();
+        std::fs::write(root.join("Work/Gone/b.txt"), b"bb").unwrap();
+        std::fs::write(root.join("Other/c.txt"), b"ccc").unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, root, None).await;
+        let scanner = Scanner::new(pool.clone()).with_batch_size(2);
+
+        // 重叠的范围只遍历一次，不会重复入库
+        let scopes = [ScanScope::root(), ScanScope { dir: "Work/".into(), recursive: false }];
+        let stats = scanner.scan_scopes(&library, &scopes, &ScanProgress::default()).await.unwrap();
+        assert_eq!(stats.files_added, 3);
+
+        // 范围外的文件代数虽然落后，也不会被标记丢失
+        std::fs::remove_dir_all(root.join("Work/Gone")).unwrap();
+        std::fs::remove_dir_all(root.join("Other")).unwrap();
+        let scopes = [ScanScope { dir: "Work/".into(), recursive: true }];
+        let stats = scanner.scan_scopes(&library, &scopes, &ScanProgress::default()).await.unwrap();
+        assert_eq!((stats.files_seen, stats.files_lost), (1, 1));
+
+        let files: Vec<(String, i32)> = sqlx::query_as(
+            "SELECT parent_path || filename, status FROM files ORDER BY parent_path || filename"
         )
-        .bind(size).bind(mtime).bind(lib_id).bind(parent).bind(filename)
-        .execute(&self.db).await?;
-        Ok(())
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(files, vec![
+            ("Other/c.txt".to_string(), 1),
+            ("Work/Gone/b.txt".to_string(), 0),
+            ("Work/a.txt".to_string(), 1),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_reappearing_file_is_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, b"content").unwrap();
+
+        let pool = test_pool().await;
+        let library = local_library(&pool, dir.path(), Some(r#"{"hash_mode":"off"}"#)).await;
+        let scanner = Scanner::new(pool.clone());
+        scanner.scan_library(&library).await.unwrap();
+
+        // 移走后再原样放回 (大小与修改时间都不变)
+        let stash = tempfile::tempdir().unwrap();
+        std::fs::rename(&file, stash.path().join("a.txt")).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!(stats.files_lost, 1);
+        let lost_at: Option<String> = sqlx::query_scalar("SELECT lost_at FROM files").fetch_one(&pool).await.unwrap();
+        assert!(lost_at.is_some());
+
+        std::fs::rename(stash.path().join("a.txt"), &file).unwrap();
+        let stats = scanner.scan_library(&library).await.unwrap();
+        assert_eq!((stats.files_restored, stats.files_added, stats.files_modified), (1, 0, 0));
+
+        let (status, lost_at): (i32, Option<String>) = sqlx::query_as("SELECT status, lost_at FROM files")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!((status, lost_at), (1, None));
     }
 
-    async fn mark_as_lost(&self, lib_id: i32, full_path: &str) -> anyhow::Result<()> {
-        let (parent, filename) = self.split_path(full_path);
-        sqlx::query(
-            "UPDATE files SET status = 0 WHERE library_id = ? AND parent_path = ? AND filename = ?"
+    #[tokio::test]
+    async fn test_type_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("Image")).unwrap();
+        std::fs::write(dir.path().join("Image/shot.CR2"), b"II*\0raw").unwrap();
+        std::fs::write(dir.path().join("scan"), b"%PDF-1.4\n...").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"\xFF\xD8\xFF\xE0 jpeg").unwrap();
+        std::fs::write(dir.path().join("cover.psd"), b"8BPS").unwrap();
+
+        let pool = test_pool().await;
+        let config = r#"{"type_mapping":{"psd":"Image/Design"},"type_sniff":"always"}"#;
+        let library = local_library(&pool, dir.path(), Some(config)).await;
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let types: Vec<(String, String)> = sqlx::query_as(
+            "SELECT f.filename, COALESCE(p.name || '/', '') || t.name FROM files f
+             JOIN file_tags ft ON ft.file_id = f.id
+             JOIN tags t ON t.id = ft.tag_id AND t.category = 'type'
+             LEFT JOIN tags p ON p.id = t.parent_id
+             ORDER BY f.filename"
         )
-        .bind(lib_id).bind(parent).bind(filename)
-        .execute(&self.db).await?;
-        Ok(())
-    }
-
-    fn split_path(&self, full_path: &str) -> (String, String) {
-        let path = std::path::Path::new(full_path);
-        let parent = path.parent().and_then(|p| p.to_str()).unwrap_or("").to_string();
-        let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
-        // 保证 parent 以 / 结尾或为空
-        let parent = if parent.is_empty() { parent } else { format!("{}/", parent) };
-        (parent, filename)
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(types, vec![
+            ("cover.psd".to_string(), "Image/Design".to_string()),
+            // 扩展名错误时以文件头为准
+            ("notes.txt".to_string(), "Image".to_string()),
+            ("scan".to_string(), "Document/PDF".to_string()),
+            ("shot.CR2".to_string(), "Image/RAW".to_string()),
+        ]);
+
+        // 类型标签与同名目录标签互不干扰
+        let image_tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tags WHERE name = 'Image' AND parent_id IS NULL")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(image_tags, 2);
     }
 }
@@ -0,0 +1,304 @@
+//! 资源库目录遍历
+//!
+//! 逐个目录列举 (非递归 list)，在进入子目录之前应用排除规则，
+//! 因此被排除的子树既不会被列举，也不会被 stat。
+//! 每次只返回一个目录的文件，内存占用取决于最大的单个目录而非整个资源库。
+//! 列举结果已带大小与修改时间 (如 WebDAV) 的文件不再单独 stat，其余文件按配置的并发度并行 stat，
+//! 返回顺序与列举顺序一致。
+
+use std::sync::Arc;
+
+use futures_util::{stream, StreamExt};
+use opendal::{Entry, ErrorKind, Metadata, Metakey, Operator};
+use tracing::debug;
+
+use super::filter::{is_ignored, IgnoreFile, ScanFilter, IGNORE_FILE};
+use super::ScanScope;
+
+/// 等待遍历的目录
+struct PendingDir {
+    /// 相对资源库根目录的路径，根目录为 `/`，其余以 `/` 结尾
+    path: String,
+    /// 从根目录到此目录路径上所有 `.tagflowignore` 规则
+    ignore_files: Vec<Arc<IgnoreFile>>,
+}
+
+/// 已列举的目录
+struct ListedDir {
+    path: String,
+    entries: Vec<Entry>,
+    ignore_files: Vec<Arc<IgnoreFile>>,
+}
+
+/// 单个目录中未被排除的文件
+pub struct DirFiles {
+    /// 相对资源库根目录的目录路径，根目录为空串，其余以 `/` 结尾 (与 `files.parent_path` 一致)
+    pub dir: String,
+    /// 文件路径及元数据
+    pub files: Vec<(String, Metadata)>,
+    /// 读取元数据失败的文件路径及错误；其状态未知，不应被标记为丢失
+    pub failed: Vec<(String, anyhow::Error)>,
+}
+
+/// 资源库文件遍历器
+pub struct LibraryWalker<'a> {
+    op: &'a Operator,
+    filter: &'a ScanFilter,
+    pending_dirs: Vec<PendingDir>,
+    /// 已列举、尚未返回的起始目录
+    listed: Option<ListedDir>,
+    failed_dirs: Vec<String>,
+    recursive: bool,
+    /// 同时进行的 stat 请求数
+    stat_concurrency: usize,
+}
+
+impl<'a> LibraryWalker<'a> {
+    /// 列举扫描范围的起始目录并创建遍历器
+    ///
+    /// 资源库根目录不可访问 (如存储离线) 时直接返回错误，避免把整个资源库误判为已删除；
+    /// 子目录范围不存在时视为空目录 (例如目录已被删除)。
+    pub async fn open(
+        op: &'a Operator,
+        filter: &'a ScanFilter,
+        scope: &ScanScope,
+        stat_concurrency: usize,
+    ) -> anyhow::Result<Self> {
+        let mut walker = Self {
+            op,
+            filter,
+            pending_dirs: Vec::new(),
+            listed: None,
+            failed_dirs: Vec::new(),
+            recursive: scope.recursive,
+            stat_concurrency: stat_concurrency.max(1),
+        };
+
+        if scope.dir.is_empty() {
+            let dir = PendingDir { path: "/".to_string(), ignore_files: Vec::new() };
+            walker.listed = Some(walker.list_dir(dir).await?);
+            return Ok(walker);
+        }
+
+        // 子目录范围：先加载祖先目录的 .tagflowignore，并确认范围本身未被排除
+        let mut ignore_files = Vec::new();
+        let mut ancestor = String::new();
+        for component in scope.dir.trim_end_matches('/').split('/') {
+            if let Some(file) = walker.load_ignore_file(&ancestor).await? {
+                ignore_files.push(Arc::new(file));
+            }
+            let rel = format!("{}{}", ancestor, component);
+            if filter.is_excluded(&rel, true) || is_ignored(&ignore_files, &rel, true) {
+                debug!("扫描范围已被排除: {}", scope.dir);
+                return Ok(walker);
+            }
+            ancestor = format!("{}/", rel);
+        }
+
+        match walker.list_dir(PendingDir { path: scope.dir.clone(), ignore_files }).await {
+            Ok(listed) => {
+                walker.listed = Some(listed);
+                Ok(walker)
+            }
+            Err(e) if is_not_found(&e) => Ok(walker),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 列举失败的子目录；其中已索引的文件状态未知，不应被标记为丢失
+    pub fn failed_dirs(&self) -> &[String] {
+        &self.failed_dirs
+    }
+
+    /// 返回下一个目录中未被排除的文件
+    ///
+    /// 目录列举失败时返回 `Some(Err)` 并记入 [`failed_dirs`](Self::failed_dirs)，调用方可记录后继续调用。
+    pub async fn next_dir(&mut self) -> Option<anyhow::Result<DirFiles>> {
+        let listed = match self.listed.take() {
+            Some(listed) => listed,
+            None => {
+                let dir = self.pending_dirs.pop()?;
+                let dir_path = dir.path.clone();
+                match self.list_dir(dir).await {
+                    Ok(listed) => listed,
+                    Err(e) => {
+                        self.failed_dirs.push(dir_path);
+                        return Some(Err(e));
+                    }
+                }
+            }
+        };
+        Some(Ok(self.collect_files(listed).await))
+    }
+
+    /// 将子目录压入待遍历队列，并对未被排除的文件读取元数据
+    async fn collect_files(&mut self, listed: ListedDir) -> DirFiles {
+        let dir = if listed.path == "/" { String::new() } else { listed.path };
+        let mut candidates = Vec::new();
+
+        for entry in listed.entries {
+            let path = entry.path();
+            if entry.metadata().is_dir() || path.ends_with('/') {
+                let rel = path.trim_end_matches('/');
+                if !self.recursive {
+                    continue;
+                }
+                if self.filter.is_excluded(rel, true) || is_ignored(&listed.ignore_files, rel, true) {
+                    debug!("跳过排除目录: {}", path);
+                    continue;
+                }
+                self.pending_dirs.push(PendingDir {
+                    path: path.to_string(),
+                    ignore_files: listed.ignore_files.clone(),
+                });
+                continue;
+            }
+
+            if self.filter.is_excluded(path, false)
+                || is_ignored(&listed.ignore_files, path, false)
+                || !self.filter.is_included(path)
+            {
+                continue;
+            }
+            candidates.push(entry);
+        }
+
+        // buffered 保证结果顺序与列举顺序一致
+        let op = self.op;
+        let results: Vec<Result<(String, Metadata), (String, anyhow::Error)>> = stream::iter(candidates)
+            .map(|entry| async move {
+                let (path, metadata) = entry.into_parts();
+                if has_scan_metadata(&metadata) {
+                    return Ok((path, metadata));
+                }
+                match op.stat(&path).await {
+                    Ok(metadata) => Ok((path, metadata)),
+                    Err(e) => {
+                        let err = anyhow::anyhow!("读取元数据失败: {} - {}", path, e);
+                        Err((path, err))
+                    }
+                }
+            })
+            .buffered(self.stat_concurrency)
+            .collect()
+            .await;
+
+        let mut files = Vec::with_capacity(results.len());
+        let mut failed = Vec::new();
+        for result in results {
+            match result {
+                Ok(file) => files.push(file),
+                Err(failure) => failed.push(failure),
+            }
+        }
+        DirFiles { dir, files, failed }
+    }
+
+    /// 列举目录并加载其中的 `.tagflowignore`
+    async fn list_dir(&self, dir: PendingDir) -> anyhow::Result<ListedDir> {
+        let entries = self
+            .op
+            .list(&dir.path)
+            .await
+            .map_err(|e| anyhow::Error::new(e).context(format!("列举目录失败: {}", dir.path)))?;
+
+        let mut ignore_files = dir.ignore_files;
+        let rel_dir = if dir.path == "/" { "" } else { dir.path.as_str() };
+        let ignore_path = format!("{}{}", rel_dir, IGNORE_FILE);
+        if entries.iter().any(|e| e.path() == ignore_path)
+            && let Some(file) = self.load_ignore_file(rel_dir).await?
+        {
+            ignore_files.push(Arc::new(file));
+        }
+
+        // list 结果包含目录自身，需要跳过
+        let entries = entries
+            .into_iter()
+            .filter(|e| e.path() != dir.path && e.path() != "/")
+            .collect();
+        Ok(ListedDir { path: dir.path, entries, ignore_files })
+    }
+
+    /// 读取目录中的 `.tagflowignore`，文件不存在时返回 `None`
+    async fn load_ignore_file(&self, rel_dir: &str) -> anyhow::Result<Option<IgnoreFile>> {
+        let ignore_path = format!("{}{}", rel_dir, IGNORE_FILE);
+        let content = match self.op.read(&ignore_path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => anyhow::bail!("读取排除规则失败: {} - {}", ignore_path, e),
+        };
+        let content = String::from_utf8_lossy(&content.to_vec()).to_string();
+        Ok(Some(IgnoreFile::parse(rel_dir, &content)?))
+    }
+}
+
+/// 列举结果是否已包含扫描所需的大小与修改时间
+fn has_scan_metadata(metadata: &Metadata) -> bool {
+    let keys = metadata.metakey();
+    keys.contains(Metakey::Complete)
+        || (keys.contains(Metakey::ContentLength) && keys.contains(Metakey::LastModified))
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<opendal::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opendal::EntryMode;
+
+    #[test]
+    fn test_has_scan_metadata() {
+        let mut metadata = Metadata::new(EntryMode::FILE);
+        assert!(!has_scan_metadata(&metadata));
+        metadata.set_content_length(10);
+        assert!(!has_scan_metadata(&metadata));
+        metadata.set_last_modified(chrono::Utc::now());
+        assert!(has_scan_metadata(&metadata));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_stat_keeps_listing_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            std::fs::write(dir.path().join(format!("{:02}.txt", i)), vec![0u8; i]).unwrap();
+        }
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+        let filter = ScanFilter::from_config(&Default::default()).unwrap();
+
+        let mut walker = LibraryWalker::open(&op, &filter, &ScanScope::root(), 1).await.unwrap();
+        let sequential = walker.next_dir().await.unwrap().unwrap();
+        let mut walker = LibraryWalker::open(&op, &filter, &ScanScope::root(), 8).await.unwrap();
+        let parallel = walker.next_dir().await.unwrap().unwrap();
+
+        let paths = |d: &DirFiles| d.files.iter().map(|(p, m)| (p.clone(), m.content_length())).collect::<Vec<_>>();
+        assert_eq!(paths(&sequential).len(), 20);
+        assert_eq!(paths(&sequential), paths(&parallel));
+        assert!(walker.next_dir().await.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_concurrent_stat_reports_failed_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..10 {
+            std::fs::write(dir.path().join(format!("{:02}.txt", i)), b"x").unwrap();
+        }
+        // 指向自身的符号链接会被列举，但 stat 失败
+        std::os::unix::fs::symlink("05.lnk", dir.path().join("05.lnk")).unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+        let filter = ScanFilter::from_config(&Default::default()).unwrap();
+
+        let mut walker = LibraryWalker::open(&op, &filter, &ScanScope::root(), 8).await.unwrap();
+        let listing = walker.next_dir().await.unwrap().unwrap();
+        assert_eq!(listing.files.len(), 10);
+        let failed: Vec<&str> = listing.failed.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(failed, vec!["05.lnk"]);
+    }
+}
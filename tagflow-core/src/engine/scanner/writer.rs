@@ -0,0 +1,411 @@
+//! 扫描结果批量写入
+//!
+//! 扫描过程中产生的新增、修改、移动与丢失标记先在内存中排队，
+//! 每攒够 `batch_size` 条就在一个事务中整体提交，避免逐行往返数据库。
+//! 标签管道的输出按标签器 (`file_tags.source`) 整体替换；标签 ID 在一次扫描内缓存，
+//! 相同的标签层级只解析一次。
+
+use std::collections::{HashMap, HashSet};
+
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::core::tag::TagManager;
+use crate::engine::tagger::TagOutput;
+use crate::models::db::TagCategory;
+
+use super::ScanProgress;
+
+/// 默认每个事务写入的行数
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// 待写入的变更
+enum WriteOp {
+    Insert { parent: String, filename: String, size: i64, mtime: i64, hash: Option<String>, tags: Vec<TagOutput> },
+    Update { file_id: i32, size: i64, mtime: i64, hash: Option<String>, tags: Vec<TagOutput> },
+    SetHash { file_id: i32, hash: String },
+    Relocate { file_id: i32, parent: String, filename: String, size: i64, mtime: i64, tags: Vec<TagOutput> },
+    Retag { file_id: i32, tags: Vec<TagOutput> },
+    MarkLost { file_id: i32 },
+    Restore { file_id: i32 },
+    MarkDirSeen { parent: String },
+    MarkUnseenLost { dir: String, recursive: bool, skipped: Vec<String> },
+    Discard { file_id: i32 },
+}
+
+/// 扫描写入缓冲
+pub struct ScanWriter<'a> {
+    db: &'a SqlitePool,
+    library_id: i32,
+    progress: &'a ScanProgress,
+    batch_size: usize,
+    /// 本次扫描的代数，写入新增与遍历到的文件
+    scan_gen: i64,
+    pending: Vec<WriteOp>,
+    /// (分类, 标签层级) -> 叶子标签 ID
+    tag_ids: HashMap<(TagCategory, Vec<String>), i32>,
+    /// 本次扫描中被标记为丢失的文件
+    lost_ids: HashSet<i32>,
+}
+
+impl<'a> ScanWriter<'a> {
+    pub fn new(db: &'a SqlitePool, library_id: i32, progress: &'a ScanProgress, batch_size: usize) -> Self {
+        Self {
+            db,
+            library_id,
+            progress,
+            batch_size: batch_size.max(1),
+            scan_gen: 0,
+            pending: Vec::with_capacity(batch_size.max(1)),
+            tag_ids: HashMap::new(),
+            lost_ids: HashSet::new(),
+        }
+    }
+
+    /// 设置本次扫描的代数
+    pub fn with_scan_gen(mut self, scan_gen: i64) -> Self {
+        self.scan_gen = scan_gen;
+        self
+    }
+
+    /// 新增文件并写入标签管道的输出
+    pub async fn insert(
+        &mut self,
+        full_path: &str,
+        size: i64,
+        mtime: i64,
+        hash: Option<String>,
+        tags: Vec<TagOutput>,
+    ) -> anyhow::Result<()> {
+        let (parent, filename) = split_path(full_path);
+        self.push(WriteOp::Insert { parent, filename, size, mtime, hash, tags }).await
+    }
+
+    /// 更新已修改文件的大小、时间与哈希，并替换各标签器的输出
+    pub async fn update(
+        &mut self,
+        file_id: i32,
+        size: i64,
+        mtime: i64,
+        hash: Option<String>,
+        tags: Vec<TagOutput>,
+    ) -> anyhow::Result<()> {
+        self.push(WriteOp::Update { file_id, size, mtime, hash, tags }).await
+    }
+
+    /// 补写内容哈希
+    pub async fn set_hash(&mut self, file_id: i32, hash: String) -> anyhow::Result<()> {
+        self.push(WriteOp::SetHash { file_id, hash }).await
+    }
+
+    /// 将已有记录迁移到新路径 (移动/重命名)
+    ///
+    /// 文件 ID 不变，因此手动标签与缩略图得以保留；自动标签 (路径、扩展名可能变化) 按新位置重新生成。
+    pub async fn relocate(
+        &mut self,
+        file_id: i32,
+        full_path: &str,
+        size: i64,
+        mtime: i64,
+        tags: Vec<TagOutput>,
+    ) -> anyhow::Result<()> {
+        let (parent, filename) = split_path(full_path);
+        self.push(WriteOp::Relocate { file_id, parent, filename, size, mtime, tags }).await
+    }
+
+    /// 替换文件上对应标签器的输出，其余标签不变
+    pub async fn retag(&mut self, file_id: i32, tags: Vec<TagOutput>) -> anyhow::Result<()> {
+        self.push(WriteOp::Retag { file_id, tags }).await
+    }
+
+    /// 标记文件丢失；实际状态发生变化的文件在提交时计入 `files_lost`
+    pub async fn mark_lost(&mut self, file_id: i32) -> anyhow::Result<()> {
+        self.push(WriteOp::MarkLost { file_id }).await
+    }
+
+    /// 将重新出现的丢失文件恢复为在线
+    pub async fn restore(&mut self, file_id: i32) -> anyhow::Result<()> {
+        self.push(WriteOp::Restore { file_id }).await
+    }
+
+    /// 记录目录 (不含子目录) 已在本次扫描中遍历，目录下的文件记为本次扫描代数
+    pub async fn mark_dir_seen(&mut self, parent: &str) -> anyhow::Result<()> {
+        self.push(WriteOp::MarkDirSeen { parent: parent.to_string() }).await
+    }
+
+    /// 标记范围内本次扫描未遍历到的文件丢失，`skipped` 中的目录 (含子目录) 除外
+    ///
+    /// 须在范围内所有目录都调用过 [`mark_dir_seen`](Self::mark_dir_seen) 之后调用。
+    pub async fn mark_unseen_lost(&mut self, dir: &str, recursive: bool, skipped: &[String]) -> anyhow::Result<()> {
+        self.push(WriteOp::MarkUnseenLost { dir: dir.to_string(), recursive, skipped: skipped.to_vec() }).await
+    }
+
+    /// 删除文件记录及其标签关联 (用于合并移动检测中重复入库的新记录)
+    pub async fn discard(&mut self, file_id: i32) -> anyhow::Result<()> {
+        self.push(WriteOp::Discard { file_id }).await
+    }
+
+    /// 文件是否在本次扫描中 (已提交的批次里) 被标记为丢失
+    pub fn lost_in_run(&self, file_id: i32) -> bool {
+        self.lost_ids.contains(&file_id)
+    }
+
+    async fn push(&mut self, op: WriteOp) -> anyhow::Result<()> {
+        self.pending.push(op);
+        if self.pending.len() >= self.batch_size {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 在一个事务中提交所有排队的变更
+    pub async fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.db.begin().await?;
+        let mut lost = Vec::new();
+        for op in std::mem::take(&mut self.pending) {
+            match op {
+                WriteOp::Insert { parent, filename, size, mtime, hash, tags } => {
+                    let ext = extension(&filename);
+                    let res = sqlx::query(
+                        "INSERT INTO files (library_id, parent_path, filename, extension, size, mtime, hash, scan_gen) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+                    )
+                    .bind(self.library_id).bind(&parent).bind(&filename).bind(ext).bind(size).bind(mtime).bind(hash).bind(self.scan_gen)
+                    .execute(&mut *tx).await?;
+
+                    let file_id = res.last_insert_rowid() as i32;
+                    self.replace_tags(&mut tx, file_id, &tags).await?;
+                }
+                WriteOp::Update { file_id, size, mtime, hash, tags } => {
+                    sqlx::query("UPDATE files SET size = ?, mtime = ?, hash = ?, status = 1, lost_at = NULL WHERE id = ?")
+                        .bind(size).bind(mtime).bind(hash).bind(file_id)
+                        .execute(&mut *tx).await?;
+
+                    self.replace_tags(&mut tx, file_id, &tags).await?;
+                }
+                WriteOp::SetHash { file_id, hash } => {
+                    sqlx::query("UPDATE files SET hash = ? WHERE id = ?")
+                        .bind(hash).bind(file_id)
+                        .execute(&mut *tx).await?;
+                }
+                WriteOp::Relocate { file_id, parent, filename, size, mtime, tags } => {
+                    let ext = extension(&filename);
+                    sqlx::query(
+                        "UPDATE files SET parent_path = ?, filename = ?, extension = ?, size = ?, mtime = ?, status = 1, lost_at = NULL,
+                                          scan_gen = ?
+                         WHERE id = ?"
+                    )
+                    .bind(&parent).bind(&filename).bind(ext).bind(size).bind(mtime).bind(self.scan_gen).bind(file_id)
+                    .execute(&mut *tx).await?;
+
+                    // 替换旧位置的自动标签
+                    self.replace_tags(&mut tx, file_id, &tags).await?;
+                }
+                WriteOp::Retag { file_id, tags } => {
+                    self.replace_tags(&mut tx, file_id, &tags).await?;
+                }
+                WriteOp::MarkLost { file_id } => {
+                    let res = sqlx::query("UPDATE files SET status = 0, lost_at = CURRENT_TIMESTAMP WHERE id = ? AND status != 0")
+                        .bind(file_id)
+                        .execute(&mut *tx).await?;
+                    if res.rows_affected() > 0 {
+                        lost.push(file_id);
+                    }
+                }
+                WriteOp::Restore { file_id } => {
+                    sqlx::query("UPDATE files SET status = 1, lost_at = NULL WHERE id = ?")
+                        .bind(file_id)
+                        .execute(&mut *tx).await?;
+                }
+                WriteOp::MarkDirSeen { parent } => {
+                    sqlx::query("UPDATE files SET scan_gen = ? WHERE library_id = ? AND parent_path = ?")
+                        .bind(self.scan_gen).bind(self.library_id).bind(&parent)
+                        .execute(&mut *tx).await?;
+                }
+                WriteOp::MarkUnseenLost { dir, recursive, skipped } => {
+                    let mut qb = QueryBuilder::<Sqlite>::new(
+                        "UPDATE files SET status = 0, lost_at = CURRENT_TIMESTAMP WHERE library_id = "
+                    );
+                    qb.push_bind(self.library_id)
+                        .push(" AND status != 0 AND scan_gen < ")
+                        .push_bind(self.scan_gen);
+                    if recursive {
+                        qb.push(" AND ");
+                        push_under_dir(&mut qb, &dir);
+                    } else {
+                        qb.push(" AND parent_path = ").push_bind(dir);
+                    }
+                    for skipped_dir in skipped {
+                        qb.push(" AND NOT ");
+                        push_under_dir(&mut qb, &skipped_dir);
+                    }
+                    qb.push(" RETURNING id");
+                    let ids: Vec<i32> = qb.build_query_scalar().fetch_all(&mut *tx).await?;
+                    lost.extend(ids);
+                }
+                WriteOp::Discard { file_id } => {
+                    sqlx::query("DELETE FROM file_tags WHERE file_id = ?")
+                        .bind(file_id)
+                        .execute(&mut *tx).await?;
+                    sqlx::query("DELETE FROM tasks WHERE file_id = ?")
+                        .bind(file_id)
+                        .execute(&mut *tx).await?;
+                    sqlx::query("DELETE FROM files WHERE id = ?")
+                        .bind(file_id)
+                        .execute(&mut *tx).await?;
+                }
+            }
+        }
+        tx.commit().await?;
+
+        for file_id in lost {
+            ScanProgress::incr(&self.progress.files_lost);
+            self.lost_ids.insert(file_id);
+        }
+        Ok(())
+    }
+
+    /// 按标签器替换文件的标签：先删除该 `source` 的旧关联，再关联新的叶子标签
+    ///
+    /// 同一标签已有其他来源 (如手动) 的关联时保留原关联。
+    async fn replace_tags(&mut self, conn: &mut SqliteConnection, file_id: i32, tags: &[TagOutput]) -> anyhow::Result<()> {
+        for output in tags {
+            sqlx::query("DELETE FROM file_tags WHERE file_id = ? AND source = ?")
+                .bind(file_id)
+                .bind(output.source)
+                .execute(&mut *conn).await?;
+
+            for parts in output.paths.iter().filter(|p| !p.is_empty()) {
+                let key = (output.category, parts.clone());
+                let tag_id = match self.tag_ids.get(&key) {
+                    Some(tag_id) => *tag_id,
+                    None => {
+                        let tag_id = TagManager::ensure_tags_on(conn, output.category, parts).await?;
+                        self.tag_ids.insert(key, tag_id);
+                        tag_id
+                    }
+                };
+                TagManager::link_file_to_tag_on(conn, file_id, tag_id, output.source).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 追加条件 `parent_path 位于 dir 之下 (含子目录)`
+fn push_under_dir(qb: &mut QueryBuilder<'_, Sqlite>, dir: &str) {
+    qb.push("substr(parent_path, 1, length(")
+        .push_bind(dir.to_string())
+        .push(")) = ")
+        .push_bind(dir.to_string());
+}
+
+/// 拆分为 (parent_path, filename)，parent_path 以 `/` 结尾或为空
+pub(crate) fn split_path(full_path: &str) -> (String, String) {
+    let path = std::path::Path::new(full_path);
+    let parent = path.parent().and_then(|p| p.to_str()).unwrap_or("").to_string();
+    let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+    let parent = if parent.is_empty() { parent } else { format!("{}/", parent) };
+    (parent, filename)
+}
+
+fn extension(filename: &str) -> Option<String> {
+    filename.split('.').next_back().map(|s| s.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+
+    fn output(source: &'static str, category: TagCategory, parts: &[&str]) -> TagOutput {
+        let paths = if parts.is_empty() { Vec::new() } else { vec![parts.iter().map(|s| s.to_string()).collect()] };
+        TagOutput { source, category, paths }
+    }
+
+    fn tags(dir: &[&str], file_type: &[&str]) -> Vec<TagOutput> {
+        vec![
+            output("path", TagCategory::Path, dir),
+            output("type", TagCategory::Type, file_type),
+            output("time", TagCategory::Time, &["2024", "03"]),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_batched_writes_share_tags() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+
+        let progress = ScanProgress::default();
+        let mut writer = ScanWriter::new(&pool, 1, &progress, 2);
+        writer.insert("Work/Design/a.png", 1, 1, None, tags(&["Work", "Design"], &["Image"])).await.unwrap();
+        writer.insert("Work/Design/b.png", 2, 2, None, tags(&["Work", "Design"], &["Image"])).await.unwrap();
+        // 第一批已自动提交，第二批仍在缓冲中
+        writer.insert("Work/c.txt", 3, 3, None, tags(&["Work"], &["Document", "Text"])).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM files").fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 2);
+
+        writer.mark_lost(1).await.unwrap();
+        writer.flush().await.unwrap();
+        writer.mark_lost(1).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(progress.stats().files_lost, 1);
+
+        let links: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT f.filename, t.name, ft.source FROM files f
+             JOIN file_tags ft ON ft.file_id = f.id JOIN tags t ON t.id = ft.tag_id
+             WHERE t.category = 'path' ORDER BY f.filename"
+        )
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(links, vec![
+            ("a.png".to_string(), "Design".to_string(), "path".to_string()),
+            ("b.png".to_string(), "Design".to_string(), "path".to_string()),
+            ("c.txt".to_string(), "Work".to_string(), "path".to_string()),
+        ]);
+        let tag_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tags").fetch_one(&pool).await.unwrap();
+        // Work, Design, Image, Document, Text, 2024, 03
+        assert_eq!(tag_count, 7);
+    }
+
+    #[tokio::test]
+    async fn test_retag_replaces_only_its_source() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+
+        let progress = ScanProgress::default();
+        let mut writer = ScanWriter::new(&pool, 1, &progress, 10);
+        writer.insert("Work/c.txt", 3, 3, None, tags(&["Work"], &["Document", "Text"])).await.unwrap();
+        writer.flush().await.unwrap();
+        // 手动标签
+        sqlx::query("INSERT INTO tags (id, name, category) VALUES (100, 'Keep', 'user')")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO file_tags (file_id, tag_id, source) VALUES (1, 100, 'manual')")
+            .execute(&pool).await.unwrap();
+
+        writer.retag(1, vec![output("type", TagCategory::Type, &["Document", "PDF"])]).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let links: Vec<(String, String)> = sqlx::query_as(
+            "SELECT t.name, ft.source FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+             WHERE ft.file_id = 1 ORDER BY ft.source, t.name"
+        )
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(links, vec![
+            ("Keep".to_string(), "manual".to_string()),
+            ("Work".to_string(), "path".to_string()),
+            ("03".to_string(), "time".to_string()),
+            ("PDF".to_string(), "type".to_string()),
+        ]);
+
+        // 空输出清除该标签器的全部标签
+        writer.retag(1, vec![output("time", TagCategory::Time, &[])]).await.unwrap();
+        writer.flush().await.unwrap();
+        let time_links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_tags WHERE source = 'time'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(time_links, 0);
+    }
+}
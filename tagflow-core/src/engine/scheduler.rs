@@ -0,0 +1,223 @@
+//! 定时扫描调度
+//!
+//! 每个资源库可以配置固定间隔 (`scan_interval_secs`) 或 cron 表达式 (`scan_cron`)。
+//! 调度器周期性检查 `next_scan_at` 已到期的资源库并通过 `ScanJobManager` 提交扫描；
+//! 上一次扫描仍在进行时跳过本次，直接顺延到下一个计划时间。
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::engine::scan_job::{ScanJobError, ScanJobManager};
+use crate::models::db::Library;
+
+/// 到期检查间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// 最小扫描间隔，避免误配置导致扫描首尾相接
+pub const MIN_INTERVAL_SECS: i64 = 60;
+
+/// 扫描计划
+#[derive(Debug, Clone)]
+pub enum ScanSchedule {
+    /// 固定间隔
+    Interval(chrono::Duration),
+    /// cron 表达式，按服务器本地时区计算
+    Cron(Box<cron::Schedule>),
+}
+
+impl ScanSchedule {
+    /// 解析扫描计划，两项均为空时返回 `None`
+    ///
+    /// cron 表达式支持标准 5 段 (`分 时 日 月 周`) 以及带秒的 6/7 段写法。
+    pub fn parse(interval_secs: Option<i64>, cron: Option<&str>) -> anyhow::Result<Option<Self>> {
+        let cron = cron.map(str::trim).filter(|s| !s.is_empty());
+        match (interval_secs, cron) {
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => anyhow::bail!("scan_interval_secs 与 scan_cron 不能同时设置"),
+            (Some(secs), None) => {
+                if secs < MIN_INTERVAL_SECS {
+                    anyhow::bail!("扫描间隔不能小于 {} 秒", MIN_INTERVAL_SECS);
+                }
+                Ok(Some(Self::Interval(chrono::Duration::seconds(secs))))
+            }
+            (None, Some(expr)) => {
+                let normalized = match expr.split_whitespace().count() {
+                    5 => format!("0 {}", expr),
+                    _ => expr.to_string(),
+                };
+                let schedule = cron::Schedule::from_str(&normalized)
+                    .map_err(|e| anyhow::anyhow!("无效的 cron 表达式 '{}': {}", expr, e))?;
+                Ok(Some(Self::Cron(Box::new(schedule))))
+            }
+        }
+    }
+
+    /// 读取资源库上的扫描计划
+    pub fn from_library(library: &Library) -> anyhow::Result<Option<Self>> {
+        Self::parse(library.scan_interval_secs, library.scan_cron.as_deref())
+    }
+
+    /// 计算 `after` 之后的下一次扫描时间
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Interval(interval) => Some(after + *interval),
+            Self::Cron(schedule) => schedule
+                .after(&after.with_timezone(&Local))
+                .next()
+                .map(|t| t.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// 定时扫描调度器
+pub struct ScanScheduler {
+    db: SqlitePool,
+    scans: ScanJobManager,
+}
+
+impl ScanScheduler {
+    pub fn new(db: SqlitePool, scans: ScanJobManager) -> Self {
+        Self { db, scans }
+    }
+
+    /// 调度主循环，不会返回
+    pub async fn run(self) {
+        info!("定时扫描调度器已启动");
+        loop {
+            if let Err(e) = self.tick(Utc::now()).await {
+                error!("定时扫描调度出错: {}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// 处理到期的资源库，返回提交的扫描数量
+    ///
+    /// 配置了计划但尚无 `next_scan_at` 的资源库只补齐下次时间，不立即扫描。
+    pub async fn tick(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+        let libraries: Vec<Library> = sqlx::query_as(
+            "SELECT * FROM libraries WHERE scan_interval_secs IS NOT NULL OR scan_cron IS NOT NULL"
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut enqueued = 0;
+        for library in libraries {
+            let library_id = library.id;
+            let schedule = match ScanSchedule::from_library(&library) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    warn!("资源库 {} 的扫描计划无效: {}", library.name, e);
+                    None
+                }
+            };
+
+            match library.next_scan_at {
+                Some(due) if due <= now => {
+                    match self.scans.enqueue(library).await {
+                        Ok(job_id) => {
+                            info!("定时扫描已提交: 资源库 {}, 任务 {}", library_id, job_id);
+                            enqueued += 1;
+                        }
+                        Err(ScanJobError::AlreadyRunning { job_id, .. }) => {
+                            info!("资源库 {} 的扫描任务 {} 仍在进行，跳过本次定时扫描", library_id, job_id);
+                        }
+                        Err(e) => error!("资源库 {} 定时扫描提交失败: {}", library_id, e),
+                    }
+                }
+                Some(_) => continue,
+                None => {}
+            }
+
+            let next = schedule.and_then(|s| s.next_after(now));
+            sqlx::query("UPDATE libraries SET next_scan_at = ? WHERE id = ?")
+                .bind(next)
+                .bind(library_id)
+                .execute(&self.db)
+                .await?;
+        }
+        Ok(enqueued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_schedule() {
+        assert!(ScanSchedule::parse(None, None).unwrap().is_none());
+        assert!(ScanSchedule::parse(None, Some("  ")).unwrap().is_none());
+        assert!(ScanSchedule::parse(Some(30), None).is_err());
+        assert!(ScanSchedule::parse(Some(3600), Some("0 3 * * *")).is_err());
+        assert!(ScanSchedule::parse(None, Some("not a cron")).is_err());
+
+        let now = Utc.with_ymd_and_hms(2026, 1, 12, 10, 0, 0).unwrap();
+        let interval = ScanSchedule::parse(Some(3600), None).unwrap().unwrap();
+        assert_eq!(interval.next_after(now), Some(now + chrono::Duration::hours(1)));
+
+        // 5 段与 6 段写法等价
+        let five = ScanSchedule::parse(None, Some("30 3 * * *")).unwrap().unwrap();
+        let six = ScanSchedule::parse(None, Some("0 30 3 * * *")).unwrap().unwrap();
+        let next = five.next_after(now).unwrap();
+        assert_eq!(Some(next), six.next_after(now));
+        assert!(next > now && next <= now + chrono::Duration::days(1));
+        let local = next.with_timezone(&Local);
+        assert_eq!(local.format("%H:%M:%S").to_string(), "03:30:00");
+    }
+
+    #[tokio::test]
+    async fn test_tick_enqueues_due_libraries_and_skips_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = test_pool().await;
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO libraries (name, protocol, base_path, scan_interval_secs, next_scan_at) VALUES
+             ('due', 'local', ?1, 3600, ?2),
+             ('later', 'local', ?1, 3600, ?3),
+             ('new', 'local', ?1, 3600, NULL),
+             ('manual', 'local', ?1, NULL, NULL)"
+        )
+        .bind(dir.path().to_str().unwrap())
+        .bind(now - chrono::Duration::minutes(1))
+        .bind(now + chrono::Duration::minutes(30))
+        .execute(&pool).await.unwrap();
+
+        let scans = ScanJobManager::new(pool.clone());
+        // 模拟 'due' 正在扫描，持有写锁让扫描任务停留在运行状态
+        let lock = scans.library_lock(1);
+        let guard = lock.lock().await;
+        let due: Library = sqlx::query_as("SELECT * FROM libraries WHERE id = 1").fetch_one(&pool).await.unwrap();
+        let job_id = scans.enqueue(due).await.unwrap();
+
+        let scheduler = ScanScheduler::new(pool.clone(), scans.clone());
+        assert_eq!(scheduler.tick(now).await.unwrap(), 0);
+        assert_eq!(scans.running_scan(1).await.unwrap().job_id, job_id);
+        drop(guard);
+
+        let next: Vec<(String, Option<DateTime<Utc>>)> =
+            sqlx::query_as("SELECT name, next_scan_at FROM libraries ORDER BY id")
+                .fetch_all(&pool).await.unwrap();
+        let hour = Some(now + chrono::Duration::hours(1));
+        assert_eq!(next[0], ("due".to_string(), hour));
+        assert_eq!(next[1], ("later".to_string(), Some(now + chrono::Duration::minutes(30))));
+        assert_eq!(next[2], ("new".to_string(), hour));
+        assert_eq!(next[3], ("manual".to_string(), None));
+
+        // 到期后正常提交
+        let later = now + chrono::Duration::minutes(31);
+        for _ in 0..100 {
+            if scans.running_scan(1).await.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(scheduler.tick(later).await.unwrap(), 1);
+        assert!(scans.running_scan(2).await.is_some());
+    }
+}
@@ -0,0 +1,307 @@
+//! 时间标签
+//!
+//! 把文件按 `年/月` (可选 `/日`) 归档，例如 `2024/03/15`。优先使用文件内嵌的拍摄时间：
+//! 照片读取 EXIF `DateTimeOriginal`，MP4/MOV 视频读取 `mvhd` 中的 `creation_time`；
+//! 都取不到时退回文件修改时间 (`files.mtime`)。
+
+use std::io::Cursor;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use futures_util::future::BoxFuture;
+use opendal::Operator;
+
+use crate::models::db::{TagCategory, TimeGranularity};
+
+use super::{FileContext, Tagger};
+
+/// 读取 EXIF 时最多读取的文件头长度 (JPEG 的 APP1 段不超过 64KB，HEIC/RAW 一般也在文件前部)
+const EXIF_READ_LEN: u64 = 256 * 1024;
+
+/// 读取 MP4 `moov` 盒时最多读取的长度，`mvhd` 通常是其第一个子盒
+const MOOV_READ_LEN: u64 = 64 * 1024;
+
+/// 遍历 MP4 顶层盒的数量上限，避免在损坏的文件上空转
+const MAX_TOP_LEVEL_BOXES: usize = 64;
+
+/// MP4 时间起点 (1904-01-01) 与 Unix 纪元相差的秒数
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// 时间标签器
+pub struct TimeTagger {
+    granularity: TimeGranularity,
+    /// 是否读取文件内嵌的拍摄时间
+    read_capture_time: bool,
+}
+
+impl TimeTagger {
+    pub fn new(granularity: TimeGranularity, read_capture_time: bool) -> Self {
+        Self { granularity, read_capture_time }
+    }
+
+    /// 生成文件的时间标签路径；关闭时间标签或没有可用时间时返回空
+    ///
+    /// `file_type` 为 [`TypeTagger`](super::TypeTagger) 的分类结果，用于决定读取哪种元数据。
+    pub async fn tag_path(&self, op: &Operator, path: &str, size: u64, mtime: i64, file_type: &[String]) -> Vec<String> {
+        if self.granularity == TimeGranularity::Off {
+            return Vec::new();
+        }
+
+        let captured = if self.read_capture_time {
+            match capture_time(op, path, size, file_type).await {
+                Ok(captured) => captured,
+                Err(e) => {
+                    tracing::debug!("读取拍摄时间失败: {} - {}", path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let date = captured
+            .map(|t| t.date())
+            .or_else(|| (mtime > 0).then(|| Local.timestamp_opt(mtime, 0).single()).flatten().map(|t| t.date_naive()));
+        date.map(|d| self.date_parts(d)).unwrap_or_default()
+    }
+
+    /// 将日期拆分为 ["2024", "03"] 或 ["2024", "03", "15"]
+    pub fn date_parts(&self, date: NaiveDate) -> Vec<String> {
+        let mut parts = vec![date.year().to_string(), format!("{:02}", date.month())];
+        if self.granularity == TimeGranularity::Day {
+            parts.push(format!("{:02}", date.day()));
+        }
+        parts
+    }
+}
+
+impl Tagger for TimeTagger {
+    fn source(&self) -> &'static str {
+        "time"
+    }
+
+    fn category(&self) -> TagCategory {
+        TagCategory::Time
+    }
+
+    /// 类型标签器被禁用时无法判断文件类型，只使用修改时间
+    fn tag<'a>(&'a self, file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>> {
+        Box::pin(async move {
+            let file_type = file.output("type").and_then(|paths| paths.first()).map_or(&[][..], Vec::as_slice);
+            let parts = self.tag_path(file.op, file.path, file.size, file.mtime, file_type).await;
+            Ok(if parts.is_empty() { Vec::new() } else { vec![parts] })
+        })
+    }
+}
+
+/// 读取文件内嵌的拍摄时间 (本地时间)，不支持的类型返回 `None`
+pub async fn capture_time(
+    op: &Operator,
+    path: &str,
+    size: u64,
+    file_type: &[String],
+) -> anyhow::Result<Option<NaiveDateTime>> {
+    match file_type.first().map(String::as_str) {
+        Some("Image") => {
+            let len = size.min(EXIF_READ_LEN);
+            if len == 0 {
+                return Ok(None);
+            }
+            let buf = op.read_with(path).range(0..len).await?.to_vec();
+            Ok(exif_datetime(&buf))
+        }
+        Some("Video") => {
+            let created = mp4_creation_time(op, path, size).await?;
+            Ok(created.map(|t| t.with_timezone(&Local).naive_local()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 从图片文件头解析 EXIF `DateTimeOriginal`，缺失时退回 `DateTime`
+pub fn exif_datetime(buf: &[u8]) -> Option<NaiveDateTime> {
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(buf)).ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| {
+            let field = exif.get_field(tag, exif::In::PRIMARY)?;
+            let exif::Value::Ascii(ref values) = field.value else { return None };
+            let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+            NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?
+                .and_hms_opt(dt.hour.into(), dt.minute.into(), dt.second.into())
+        })
+}
+
+/// 遍历 MP4/MOV 顶层盒找到 `moov`，读取其中 `mvhd` 的创建时间
+///
+/// `moov` 可能位于文件末尾 (未做 faststart 的录像)，因此按盒头逐个跳转，只读取必要的字节。
+async fn mp4_creation_time(op: &Operator, path: &str, size: u64) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let mut offset = 0u64;
+    for index in 0..MAX_TOP_LEVEL_BOXES {
+        if offset.checked_add(8).is_none_or(|end| end > size) {
+            break;
+        }
+        let header = op.read_with(path).range(offset..offset.saturating_add(16).min(size)).await?.to_vec();
+        let Some((box_type, box_len, header_len)) = parse_box_header(&header, size - offset) else { break };
+        if index == 0 && &box_type != b"ftyp" {
+            // 不是 ISO 基础媒体格式 (如 MKV、AVI)
+            return Ok(None);
+        }
+
+        if &box_type == b"moov" {
+            let start = offset + header_len;
+            let end = (offset + box_len).min(start.saturating_add(MOOV_READ_LEN));
+            let body = op.read_with(path).range(start..end).await?.to_vec();
+            return Ok(parse_mvhd(&body));
+        }
+        offset += box_len;
+    }
+    Ok(None)
+}
+
+/// 解析盒头，返回 (类型, 盒长度, 盒头长度)；`remaining` 为当前位置到文件末尾的字节数
+///
+/// 盒长度小于盒头或超出 `remaining` 时视为损坏，返回 `None`，因此 `offset + box_len` 不会越过文件末尾。
+fn parse_box_header(buf: &[u8], remaining: u64) -> Option<([u8; 4], u64, u64)> {
+    let len = u32::from_be_bytes(buf.get(0..4)?.try_into().ok()?) as u64;
+    let box_type: [u8; 4] = buf.get(4..8)?.try_into().ok()?;
+    let (box_len, header_len) = match len {
+        0 => (remaining, 8),
+        1 => (u64::from_be_bytes(buf.get(8..16)?.try_into().ok()?), 16),
+        n => (n, 8),
+    };
+    (box_len >= header_len && box_len <= remaining).then_some((box_type, box_len, header_len))
+}
+
+/// 在 `moov` 的内容中查找 `mvhd` 并读取创建时间，时间为 0 (未设置) 时返回 `None`
+fn parse_mvhd(moov: &[u8]) -> Option<DateTime<Utc>> {
+    let mut pos = 0usize;
+    while pos + 8 <= moov.len() {
+        let (box_type, box_len, header_len) = parse_box_header(&moov[pos..], (moov.len() - pos) as u64)?;
+        if &box_type == b"mvhd" {
+            let body = moov.get(pos + header_len as usize..)?;
+            let seconds = match body.first()? {
+                0 => u32::from_be_bytes(body.get(4..8)?.try_into().ok()?) as i64,
+                1 => i64::try_from(u64::from_be_bytes(body.get(4..12)?.try_into().ok()?)).ok()?,
+                _ => return None,
+            };
+            if seconds == 0 {
+                return None;
+            }
+            return Utc.timestamp_opt(seconds - MP4_EPOCH_OFFSET, 0).single();
+        }
+        pos = pos.checked_add(usize::try_from(box_len).ok()?)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造一个仅含 DateTimeOriginal 的最小 TIFF/EXIF 结构
+    fn tiff_with_date(date: &str) -> Vec<u8> {
+        let mut buf = b"II*\0".to_vec();
+        buf.extend(8u32.to_le_bytes());
+        // IFD0: ExifIFDPointer -> 26
+        buf.extend(1u16.to_le_bytes());
+        buf.extend(0x8769u16.to_le_bytes());
+        buf.extend(4u16.to_le_bytes());
+        buf.extend(1u32.to_le_bytes());
+        buf.extend(26u32.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        // Exif IFD: DateTimeOriginal -> 44
+        buf.extend(1u16.to_le_bytes());
+        buf.extend(0x9003u16.to_le_bytes());
+        buf.extend(2u16.to_le_bytes());
+        buf.extend(20u32.to_le_bytes());
+        buf.extend(44u32.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(date.as_bytes());
+        buf.push(0);
+        buf
+    }
+
+    /// 构造 ftyp + mdat + moov(mvhd v0) 的最小 MP4 结构，moov 位于文件末尾
+    fn mp4_with_creation(unix_secs: i64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(16u32.to_be_bytes());
+        buf.extend(b"ftypisom");
+        buf.extend(0u32.to_be_bytes());
+        buf.extend(12u32.to_be_bytes());
+        buf.extend(b"mdat");
+        buf.extend([0u8; 4]);
+        buf.extend(28u32.to_be_bytes());
+        buf.extend(b"moov");
+        buf.extend(20u32.to_be_bytes());
+        buf.extend(b"mvhd");
+        buf.extend([0u8; 4]);
+        buf.extend(((unix_secs + MP4_EPOCH_OFFSET) as u32).to_be_bytes());
+        buf.extend([0u8; 4]);
+        buf
+    }
+
+    #[test]
+    fn test_malformed_box_length() {
+        // 64 位盒长度为 0 或远超剩余字节时视为损坏，不能溢出或原地循环
+        for largesize in [0u64, 0xFFFF_FFFF_FFFF_FFF8] {
+            let mut moov = 1u32.to_be_bytes().to_vec();
+            moov.extend(b"free");
+            moov.extend(largesize.to_be_bytes());
+            moov.extend([0u8; 16]);
+            assert_eq!(parse_mvhd(&moov), None);
+        }
+        assert_eq!(parse_box_header(b"\0\0\0\x20moov", 16), None);
+    }
+
+    #[tokio::test]
+    async fn test_mp4_oversized_box() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buf = mp4_with_creation(1_643_889_600);
+        // 将 mdat 改为声明 0xFFFFFFFFFFFFFFF8 字节的 64 位盒
+        buf.splice(16..28, [1u32.to_be_bytes().as_slice(), b"mdat", &0xFFFF_FFFF_FFFF_FFF8u64.to_be_bytes()].concat());
+        std::fs::write(dir.path().join("bad.mp4"), &buf).unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+        assert_eq!(mp4_creation_time(&op, "bad.mp4", buf.len() as u64).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_exif_datetime() {
+        let parsed = exif_datetime(&tiff_with_date("2021:07:04 10:30:00")).unwrap();
+        assert_eq!(parsed.to_string(), "2021-07-04 10:30:00");
+        assert_eq!(exif_datetime(b"not an image"), None);
+    }
+
+    #[tokio::test]
+    async fn test_tag_path_prefers_capture_time() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.tif"), tiff_with_date("2021:07:04 10:30:00")).unwrap();
+        // 2022-02-03T12:00:00Z
+        std::fs::write(dir.path().join("b.mp4"), mp4_with_creation(1_643_889_600)).unwrap();
+        std::fs::write(dir.path().join("c.txt"), b"text").unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+
+        let image = vec!["Image".to_string()];
+        let video = vec!["Video".to_string()];
+        let text = vec!["Document".to_string(), "Text".to_string()];
+        let mtime = Local.with_ymd_and_hms(2019, 12, 31, 12, 0, 0).unwrap().timestamp();
+
+        let day = TimeTagger::new(TimeGranularity::Day, true);
+        assert_eq!(day.tag_path(&op, "a.tif", 64, mtime, &image).await, vec!["2021", "07", "04"]);
+        let month = TimeTagger::new(TimeGranularity::Month, true);
+        let created = Utc.timestamp_opt(1_643_889_600, 0).unwrap().with_timezone(&Local);
+        assert_eq!(
+            month.tag_path(&op, "b.mp4", 56, mtime, &video).await,
+            vec![created.year().to_string(), format!("{:02}", created.month())]
+        );
+        // 无内嵌时间时退回 mtime
+        assert_eq!(month.tag_path(&op, "c.txt", 4, mtime, &text).await, vec!["2019", "12"]);
+        let mtime_only = TimeTagger::new(TimeGranularity::Month, false);
+        assert_eq!(mtime_only.tag_path(&op, "a.tif", 64, mtime, &image).await, vec!["2019", "12"]);
+        let off = TimeTagger::new(TimeGranularity::Off, true);
+        assert!(off.tag_path(&op, "a.tif", 64, mtime, &image).await.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+//! 文件类型标签
+//!
+//! 按扩展名把文件归入层级类型树 (如 `Image/RAW`、`Document/PDF`、`Code/Rust`)，
+//! 扩展名缺失或未知时读取文件头的魔数进行识别。无法识别的文件归入 `Other`。
+//! 资源库可通过 `config_json.type_mapping` 覆盖或扩充扩展名映射。
+
+use std::collections::HashMap;
+
+use futures_util::future::BoxFuture;
+use opendal::Operator;
+
+use crate::models::db::{TagCategory, TypeSniff};
+
+use super::{FileContext, Tagger};
+
+/// 嗅探文件类型需要读取的文件头长度
+pub const MAGIC_LEN: usize = 32;
+
+/// 无法识别的文件所属类型
+const OTHER: &str = "Other";
+
+/// 内置扩展名映射：(类型路径, 扩展名列表)
+const DEFAULT_MAPPING: &[(&str, &[&str])] = &[
+    ("Image", &["jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff", "heic", "heif", "avif", "svg", "ico"]),
+    ("Image/RAW", &["cr2", "cr3", "nef", "nrw", "arw", "dng", "orf", "rw2", "raf", "pef", "srw", "x3f"]),
+    ("Video", &["mp4", "m4v", "mov", "mkv", "avi", "webm", "wmv", "flv", "mts", "m2ts", "3gp", "mpg", "mpeg", "ogv"]),
+    ("Audio", &["mp3", "flac", "wav", "aac", "m4a", "ogg", "opus", "wma", "ape", "aiff"]),
+    ("Document/PDF", &["pdf"]),
+    ("Document/Word", &["doc", "docx", "odt", "rtf", "pages"]),
+    ("Document/Spreadsheet", &["xls", "xlsx", "ods", "csv", "numbers"]),
+    ("Document/Presentation", &["ppt", "pptx", "odp", "key"]),
+    ("Document/Text", &["txt", "md", "markdown", "rst", "log"]),
+    ("Document/Ebook", &["epub", "mobi", "azw3"]),
+    ("Code/Rust", &["rs"]),
+    ("Code/Python", &["py", "pyi", "ipynb"]),
+    ("Code/JavaScript", &["js", "mjs", "cjs", "jsx"]),
+    ("Code/TypeScript", &["ts", "tsx"]),
+    ("Code/Go", &["go"]),
+    ("Code/Java", &["java"]),
+    ("Code/Kotlin", &["kt", "kts"]),
+    ("Code/C", &["c", "h"]),
+    ("Code/C++", &["cpp", "cc", "cxx", "hpp", "hh"]),
+    ("Code/C#", &["cs"]),
+    ("Code/Swift", &["swift"]),
+    ("Code/Ruby", &["rb"]),
+    ("Code/PHP", &["php"]),
+    ("Code/Shell", &["sh", "bash", "zsh", "ps1"]),
+    ("Code/Web", &["html", "htm", "css", "scss", "vue"]),
+    ("Code/SQL", &["sql"]),
+    ("Code/Config", &["json", "yaml", "yml", "toml", "xml", "ini"]),
+    ("Archive", &["zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst"]),
+];
+
+/// 文件类型标签器
+pub struct TypeTagger {
+    /// 扩展名 (小写) -> 类型路径
+    by_extension: HashMap<String, Vec<String>>,
+    /// 何时读取文件头
+    sniff: TypeSniff,
+}
+
+impl Default for TypeTagger {
+    fn default() -> Self {
+        let mut by_extension = HashMap::new();
+        for (path, extensions) in DEFAULT_MAPPING {
+            for ext in *extensions {
+                by_extension.insert(ext.to_string(), split_type_path(path));
+            }
+        }
+        Self { by_extension, sniff: TypeSniff::default() }
+    }
+}
+
+impl TypeTagger {
+    /// 在内置映射基础上应用资源库的自定义映射 (扩展名 -> `"Image/Design"` 形式的类型路径)
+    pub fn with_mapping(mapping: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut tagger = Self::default();
+        for (ext, path) in mapping {
+            let ext = ext.trim().trim_start_matches('.').to_lowercase();
+            let parts = split_type_path(path);
+            if ext.is_empty() || parts.is_empty() {
+                anyhow::bail!("无效的类型映射: '{}' -> '{}'", ext, path);
+            }
+            tagger.by_extension.insert(ext, parts);
+        }
+        Ok(tagger)
+    }
+
+    /// 设置文件头嗅探策略
+    pub fn with_sniff(mut self, sniff: TypeSniff) -> Self {
+        self.sniff = sniff;
+        self
+    }
+
+    /// 识别存储中文件的类型；需要时读取文件头，读取失败只记录日志并退回按扩展名分类
+    pub async fn detect(&self, op: &Operator, path: &str, size: u64) -> Vec<String> {
+        let filename = path.rsplit_once('/').map_or(path, |(_, name)| name);
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext);
+        let sniff_head = match self.sniff {
+            TypeSniff::Off => false,
+            TypeSniff::Unknown => self.classify_extension(extension).is_none(),
+            TypeSniff::Always => true,
+        };
+
+        let head = if sniff_head {
+            match read_head(op, path, size).await {
+                Ok(head) => Some(head),
+                Err(e) => {
+                    tracing::debug!("读取文件头失败: {} - {}", path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.classify(extension, head.as_deref())
+    }
+
+    /// 仅按扩展名分类，未知扩展名返回 `None`
+    pub fn classify_extension(&self, extension: Option<&str>) -> Option<Vec<String>> {
+        let ext = extension?.to_lowercase();
+        self.by_extension.get(&ext).cloned()
+    }
+
+    /// 按扩展名与文件头分类
+    ///
+    /// - 扩展名已知且文件头无法识别或与之相符时，以扩展名为准 (扩展名能区分更细的子类，如 RAW)
+    /// - 扩展名已知且文件头是通用容器格式 (ZIP、Ogg、RIFF) 时，以扩展名为准：
+    ///   docx、epub 等本身就是 ZIP 包，`.ogv` 视频同样使用 Ogg 封装
+    /// - 文件头可识别且与扩展名不符时 (扩展名缺失或错误)，以文件头为准
+    /// - 都无法识别时归入 `Other`
+    pub fn classify(&self, extension: Option<&str>, head: Option<&[u8]>) -> Vec<String> {
+        let by_ext = self.classify_extension(extension);
+        let by_magic = head.and_then(sniff_magic).map(split_type_path);
+
+        match (by_ext, by_magic) {
+            (Some(ext), _) if head.is_some_and(is_container) => ext,
+            (Some(ext), Some(magic)) if !ext.starts_with(&magic) => magic,
+            (Some(ext), _) => ext,
+            (None, Some(magic)) => magic,
+            (None, None) => vec![OTHER.to_string()],
+        }
+    }
+}
+
+impl Tagger for TypeTagger {
+    fn source(&self) -> &'static str {
+        "type"
+    }
+
+    fn category(&self) -> TagCategory {
+        TagCategory::Type
+    }
+
+    fn tag<'a>(&'a self, file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>> {
+        Box::pin(async move { Ok(vec![self.detect(file.op, file.path, file.size).await]) })
+    }
+}
+
+/// 根据文件头魔数识别类型
+pub fn sniff_magic(head: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| head.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(b"\xFF\xD8\xFF") || starts(b"\x89PNG\r\n\x1a\n") || starts(b"GIF87a") || starts(b"GIF89a") {
+        return Some("Image");
+    }
+    if starts(b"RIFF") && at(8, b"WEBP") {
+        return Some("Image");
+    }
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        return Some("Image");
+    }
+    if at(4, b"ftyp") {
+        let brand = head.get(8..12)?;
+        return match brand {
+            b"heic" | b"heix" | b"mif1" | b"msf1" | b"avif" => Some("Image"),
+            b"M4A " => Some("Audio"),
+            b"crx " => Some("Image/RAW"),
+            _ => Some("Video"),
+        };
+    }
+    if starts(b"\x1A\x45\xDF\xA3") || (starts(b"RIFF") && at(8, b"AVI ")) {
+        return Some("Video");
+    }
+    if starts(b"ID3") || starts(b"fLaC") || starts(b"OggS") || (starts(b"RIFF") && at(8, b"WAVE")) {
+        return Some("Audio");
+    }
+    if head.len() >= 2 && head[0] == 0xFF && (head[1] & 0xE0) == 0xE0 && !starts(b"\xFF\xD8") {
+        return Some("Audio");
+    }
+    if starts(b"%PDF") {
+        return Some("Document/PDF");
+    }
+    if starts(b"PK\x03\x04") || starts(b"Rar!\x1A\x07") || starts(b"7z\xBC\xAF\x27\x1C") || starts(b"\x1F\x8B") {
+        return Some("Archive");
+    }
+    None
+}
+
+/// 文件头是否为承载多种内容的通用容器格式，此类文件头无法否定已知扩展名
+fn is_container(head: &[u8]) -> bool {
+    head.starts_with(b"PK\x03\x04") || head.starts_with(b"OggS") || head.starts_with(b"RIFF")
+}
+
+/// 根据文件头魔数识别 MIME 类型，用于扩展名无法判断的文件下载
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| head.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(b"\xFF\xD8\xFF") {
+        return Some("image/jpeg");
+    }
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        return Some("image/tiff");
+    }
+    if starts(b"RIFF") {
+        return match head.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"AVI " => Some("video/x-msvideo"),
+            b"WAVE" => Some("audio/wav"),
+            _ => None,
+        };
+    }
+    if at(4, b"ftyp") {
+        return match head.get(8..12)? {
+            b"heic" | b"heix" => Some("image/heic"),
+            b"avif" => Some("image/avif"),
+            b"M4A " => Some("audio/mp4"),
+            b"qt  " => Some("video/quicktime"),
+            _ => Some("video/mp4"),
+        };
+    }
+    if starts(b"\x1A\x45\xDF\xA3") {
+        return Some("video/x-matroska");
+    }
+    if starts(b"ID3") {
+        return Some("audio/mpeg");
+    }
+    if starts(b"fLaC") {
+        return Some("audio/flac");
+    }
+    if starts(b"OggS") {
+        return Some("audio/ogg");
+    }
+    if starts(b"%PDF") {
+        return Some("application/pdf");
+    }
+    if starts(b"PK\x03\x04") {
+        return Some("application/zip");
+    }
+    if starts(b"\x1F\x8B") {
+        return Some("application/gzip");
+    }
+    None
+}
+
+/// 读取文件头用于魔数识别，空文件返回空缓冲
+pub async fn read_head(op: &Operator, path: &str, size: u64) -> anyhow::Result<Vec<u8>> {
+    let len = size.min(MAGIC_LEN as u64);
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let buf = op.read_with(path).range(0..len).await?;
+    Ok(buf.to_vec())
+}
+
+/// 将 `"Image/RAW"` 拆分为 ["Image", "RAW"]
+fn split_type_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(parts: Vec<String>) -> String {
+        parts.join("/")
+    }
+
+    #[test]
+    fn test_classify_by_extension() {
+        let tagger = TypeTagger::default();
+        assert_eq!(path(tagger.classify(Some("CR2"), None)), "Image/RAW");
+        assert_eq!(path(tagger.classify(Some("pdf"), None)), "Document/PDF");
+        assert_eq!(path(tagger.classify(Some("rs"), None)), "Code/Rust");
+        assert_eq!(path(tagger.classify(Some("mkv"), None)), "Video");
+        assert_eq!(path(tagger.classify(Some("unknown"), None)), "Other");
+        assert_eq!(path(tagger.classify(None, None)), "Other");
+    }
+
+    #[test]
+    fn test_magic_overrides_missing_or_wrong_extension() {
+        let tagger = TypeTagger::default();
+        let jpeg = b"\xFF\xD8\xFF\xE0\0\x10JFIF";
+        let pdf = b"%PDF-1.7\n";
+        assert_eq!(path(tagger.classify(None, Some(jpeg))), "Image");
+        assert_eq!(path(tagger.classify(Some("txt"), Some(pdf))), "Document/PDF");
+        // 扩展名与文件头顶层类型一致时保留更细的子类
+        assert_eq!(path(tagger.classify(Some("dng"), Some(b"II*\0\x08\0\0\0"))), "Image/RAW");
+        // 文件头无法识别时以扩展名为准
+        assert_eq!(path(tagger.classify(Some("rs"), Some(b"fn main() {}"))), "Code/Rust");
+
+        let mp4 = b"\0\0\0\x18ftypisom\0\0\x02\0";
+        assert_eq!(sniff_magic(mp4), Some("Video"));
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0";
+        assert_eq!(sniff_magic(heic), Some("Image"));
+    }
+
+    #[test]
+    fn test_container_header_keeps_known_extension() {
+        let tagger = TypeTagger::default().with_sniff(TypeSniff::Always);
+        let zip = b"PK\x03\x04\x14\0\x06\0\x08\0\0\0!\0";
+        assert_eq!(path(tagger.classify(Some("docx"), Some(zip))), "Document/Word");
+        assert_eq!(path(tagger.classify(Some("epub"), Some(zip))), "Document/Ebook");
+        assert_eq!(path(tagger.classify(Some("ogv"), Some(b"OggS\0\x02\0\0"))), "Video");
+        // 扩展名缺失或未知时仍以容器文件头为准
+        assert_eq!(path(tagger.classify(None, Some(zip))), "Archive");
+        assert_eq!(path(tagger.classify(Some("bin"), Some(b"OggS\0\x02\0\0"))), "Audio");
+        // 具体格式的文件头与扩展名冲突时仍然覆盖
+        assert_eq!(path(tagger.classify(Some("docx"), Some(b"%PDF-1.7\n"))), "Document/PDF");
+    }
+
+    #[test]
+    fn test_custom_mapping() {
+        let mapping = HashMap::from([
+            ("psd".to_string(), "Image/Design".to_string()),
+            (".LOG".to_string(), "Code/Logs".to_string()),
+        ]);
+        let tagger = TypeTagger::with_mapping(&mapping).unwrap();
+        assert_eq!(path(tagger.classify(Some("psd"), None)), "Image/Design");
+        assert_eq!(path(tagger.classify(Some("log"), None)), "Code/Logs");
+
+        let invalid = HashMap::from([("x".to_string(), " / ".to_string())]);
+        assert!(TypeTagger::with_mapping(&invalid).is_err());
+    }
+}
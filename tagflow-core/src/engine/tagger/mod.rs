@@ -1,30 +1,87 @@
-use crate::core::tag::TagManager;
+//! 自动标签
+//!
+//! 每个 [`Tagger`] 为文件生成一类标签层级，由 [`TaggingPipeline`] 在文件新增、修改与移动时依次调用。
+//! 标签器的名称同时写入 `file_tags.source`，重新打标签时只替换该标签器自己的输出，
+//! 手动标签与其他标签器的结果不受影响。
 
-pub struct PathTagger {
-    tag_manager: TagManager,
+pub mod capture_time;
+pub mod file_type;
+pub mod pipeline;
+pub mod rule;
+
+pub use capture_time::TimeTagger;
+pub use file_type::TypeTagger;
+pub use pipeline::{TagOutput, TaggingPipeline};
+pub use rule::RuleTagger;
+
+use futures_util::future::BoxFuture;
+use opendal::Operator;
+
+use crate::models::db::TagCategory;
+
+/// 待打标签的文件
+pub struct FileContext<'a> {
+    pub op: &'a Operator,
+    /// 相对资源库根目录的完整路径
+    pub path: &'a str,
+    pub size: u64,
+    pub mtime: i64,
+    /// 管道中排在前面的标签器的输出
+    pub previous: &'a [TagOutput],
 }
 
-impl PathTagger {
-    pub fn new(tag_manager: TagManager) -> Self {
-        Self { tag_manager }
+impl FileContext<'_> {
+    /// 读取前序标签器的输出，例如时间标签器据此判断文件是照片还是视频
+    pub fn output(&self, source: &str) -> Option<&[Vec<String>]> {
+        self.previous
+            .iter()
+            .find(|o| o.source == source)
+            .map(|o| o.paths.as_slice())
     }
+}
+
+/// 标签器
+pub trait Tagger: Send + Sync {
+    /// 标签器名称，同时作为 `file_tags.source`
+    fn source(&self) -> &'static str;
 
-    /// 处理文件的路径标签
-    pub async fn process_path(&self, file_id: i32, parent_path: &str) -> anyhow::Result<()> {
-        // 将 "Projects/2024/Design/" 拆分为 ["Projects", "2024", "Design"]
-        let parts: Vec<String> = parent_path
+    /// 生成的标签所属分类
+    fn category(&self) -> TagCategory;
+
+    /// 为文件生成标签，每个元素是一条从根到叶的标签路径
+    ///
+    /// 返回错误时保留该文件原有的标签，不做替换。
+    fn tag<'a>(&'a self, file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>>;
+}
+
+/// 按目录层级生成路径标签
+pub struct PathTagger;
+
+impl PathTagger {
+    /// 将 "Projects/2024/Design/" 拆分为 ["Projects", "2024", "Design"]
+    pub fn path_parts(parent_path: &str) -> Vec<String> {
+        parent_path
             .split('/')
             .filter(|s| !s.is_empty())
             .map(|s| s.to_string())
-            .collect();
+            .collect()
+    }
+}
+
+impl Tagger for PathTagger {
+    fn source(&self) -> &'static str {
+        "path"
+    }
 
-        if !parts.is_empty() {
-            // 确保层级标签存在并获取叶子 ID
-            let leaf_tag_id = self.tag_manager.ensure_path_tags(parts).await?;
-            // 关联文件到叶子标签
-            self.tag_manager.link_file_to_tag(file_id, leaf_tag_id, "auto").await?;
-        }
+    fn category(&self) -> TagCategory {
+        TagCategory::Path
+    }
 
-        Ok(())
+    fn tag<'a>(&'a self, file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>> {
+        Box::pin(async move {
+            let parent = file.path.rsplit_once('/').map_or("", |(parent, _)| parent);
+            let parts = Self::path_parts(parent);
+            Ok(if parts.is_empty() { Vec::new() } else { vec![parts] })
+        })
     }
 }
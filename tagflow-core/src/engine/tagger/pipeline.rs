@@ -0,0 +1,186 @@
+//! 标签管道
+//!
+//! 按顺序运行已注册的标签器，并收集各自的输出交给扫描写入器落库。
+
+use opendal::Operator;
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::models::db::{Library, LibraryConfig, TagCategory};
+
+use super::{FileContext, PathTagger, RuleTagger, Tagger, TimeTagger, TypeTagger};
+
+/// 内置标签器名称，按运行顺序排列 (时间标签依赖类型标签的结果)
+pub const BUILTIN_TAGGERS: &[&str] = &["path", "type", "time", "rule"];
+
+/// 单个标签器的输出
+#[derive(Debug, Clone)]
+pub struct TagOutput {
+    /// 标签器名称 (`file_tags.source`)
+    pub source: &'static str,
+    pub category: TagCategory,
+    /// 标签路径列表，为空表示清除该标签器的全部标签
+    pub paths: Vec<Vec<String>>,
+}
+
+/// 标签管道
+#[derive(Default)]
+pub struct TaggingPipeline {
+    taggers: Vec<Box<dyn Tagger>>,
+}
+
+impl TaggingPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个标签器
+    pub fn register(mut self, tagger: impl Tagger + 'static) -> Self {
+        self.taggers.push(Box::new(tagger));
+        self
+    }
+
+    /// 加载资源库的完整标签管道：内置标签器之后追加从数据库读取的规则标签器
+    pub async fn load(db: &SqlitePool, library: &Library) -> anyhow::Result<Self> {
+        let config = library.config()?;
+        let pipeline = Self::for_library(&config)?;
+        if !is_enabled(&config, "rule") {
+            return Ok(pipeline);
+        }
+        Ok(pipeline.register(RuleTagger::load(db, library.id).await?))
+    }
+
+    /// 加载只含 `source` 及其依赖的标签器的管道，供单个标签器重新打标签时使用
+    ///
+    /// 其他标签器 (例如读取文件内容的类型、时间标签器) 不会运行；`source` 被禁用时返回空管道。
+    pub async fn load_for_source(db: &SqlitePool, library: &Library, source: &str) -> anyhow::Result<Self> {
+        let config = library.config()?;
+        if source == "rule" {
+            // 规则只匹配路径与扩展名，无需构建内置标签器，也不读取存储
+            if !is_enabled(&config, "rule") {
+                return Ok(Self::new());
+            }
+            return Ok(Self::new().register(RuleTagger::load(db, library.id).await?));
+        }
+        let mut pipeline = Self::for_library(&config)?;
+        let needed = dependencies(source);
+        pipeline.taggers.retain(|t| t.source() == source || needed.contains(&t.source()));
+        Ok(pipeline)
+    }
+
+    /// 按资源库配置构建不依赖数据库的内置标签器，跳过 `disabled_taggers` 中列出的标签器
+    pub fn for_library(config: &LibraryConfig) -> anyhow::Result<Self> {
+        if let Some(unknown) = config.disabled_taggers.iter().find(|name| !BUILTIN_TAGGERS.contains(&name.as_str())) {
+            anyhow::bail!("未知的标签器: {}", unknown);
+        }
+        let enabled = |name: &str| is_enabled(config, name);
+
+        let mut pipeline = Self::new();
+        if enabled("path") {
+            pipeline = pipeline.register(PathTagger);
+        }
+        if enabled("type") {
+            pipeline = pipeline.register(TypeTagger::with_mapping(&config.type_mapping)?.with_sniff(config.type_sniff));
+        }
+        if enabled("time") {
+            pipeline = pipeline.register(TimeTagger::new(config.time_tags, config.capture_time));
+        }
+        Ok(pipeline)
+    }
+
+    /// 管道中标签器的名称
+    pub fn sources(&self) -> Vec<&'static str> {
+        self.taggers.iter().map(|t| t.source()).collect()
+    }
+
+    /// 依次运行所有标签器；出错的标签器只记录日志，其输出不包含在结果中
+    pub async fn run(&self, op: &Operator, path: &str, size: u64, mtime: i64) -> Vec<TagOutput> {
+        let mut outputs: Vec<TagOutput> = Vec::with_capacity(self.taggers.len());
+        for tagger in &self.taggers {
+            let file = FileContext { op, path, size, mtime, previous: &outputs };
+            let result = tagger.tag(&file).await;
+            match result {
+                Ok(paths) => outputs.push(TagOutput { source: tagger.source(), category: tagger.category(), paths }),
+                Err(e) => warn!("标签器 {} 处理失败: {} - {:#}", tagger.source(), path, e),
+            }
+        }
+        outputs
+    }
+}
+
+/// 标签器运行前需要先运行的标签器
+fn dependencies(source: &str) -> &'static [&'static str] {
+    match source {
+        "time" => &["type"],
+        _ => &[],
+    }
+}
+
+fn is_enabled(config: &LibraryConfig, name: &str) -> bool {
+    !config.disabled_taggers.iter().any(|d| d == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::BoxFuture;
+
+    /// 总是失败的标签器
+    struct Failing;
+
+    impl Tagger for Failing {
+        fn source(&self) -> &'static str {
+            "failing"
+        }
+
+        fn category(&self) -> TagCategory {
+            TagCategory::User
+        }
+
+        fn tag<'a>(&'a self, _file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>> {
+            Box::pin(async { anyhow::bail!("boom") })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_respects_disabled_taggers() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("Work")).unwrap();
+        std::fs::write(dir.path().join("Work/main.rs"), b"fn main() {}").unwrap();
+        let op = Operator::new(opendal::services::Fs::default().root(dir.path().to_str().unwrap()))
+            .unwrap()
+            .finish();
+
+        let config = LibraryConfig::parse(Some(r#"{"disabled_taggers":["time"]}"#)).unwrap();
+        let pipeline = TaggingPipeline::for_library(&config).unwrap().register(Failing);
+        assert_eq!(pipeline.sources(), vec!["path", "type", "failing"]);
+
+        let outputs = pipeline.run(&op, "Work/main.rs", 12, 1_700_000_000).await;
+        let summary: Vec<(&str, Vec<Vec<String>>)> = outputs.into_iter().map(|o| (o.source, o.paths)).collect();
+        assert_eq!(summary, vec![
+            ("path", vec![vec!["Work".to_string()]]),
+            ("type", vec![vec!["Code".to_string(), "Rust".to_string()]]),
+        ]);
+
+        let invalid = LibraryConfig::parse(Some(r#"{"disabled_taggers":["colour"]}"#)).unwrap();
+        assert!(TaggingPipeline::for_library(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_for_source_runs_only_needed_taggers() {
+        let pool = crate::infra::db::test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('lib', 'local', '/tmp')")
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+
+        let sources = |source: &'static str| {
+            let (pool, library) = (&pool, &library);
+            async move { TaggingPipeline::load_for_source(pool, library, source).await.unwrap().sources() }
+        };
+        assert_eq!(sources("path").await, vec!["path"]);
+        assert_eq!(sources("type").await, vec!["type"]);
+        // 时间标签器依赖类型标签器的输出
+        assert_eq!(sources("time").await, vec!["type", "time"]);
+        assert_eq!(sources("rule").await, vec!["rule"]);
+    }
+}
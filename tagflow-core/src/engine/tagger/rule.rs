@@ -0,0 +1,200 @@
+//! 规则标签
+//!
+//! 按用户在 `tag_rules` 中定义的规则为文件关联标签，例如
+//! "文件名匹配 `IMG_\d+`" 或 "路径匹配 `**/invoices/**` 且扩展名为 pdf" → `Finance/Invoices`。
+//! 规则生成的是用户分类的标签，来源记为 `rule`，规则变化后可通过重新打标签整体替换。
+
+use futures_util::future::BoxFuture;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::models::db::{RuleConditions, TagCategory, TagRule};
+
+use super::{FileContext, Tagger};
+
+/// 编译后的规则
+pub struct CompiledRule {
+    filename_regex: Option<Regex>,
+    path_glob: Option<GlobMatcher>,
+    extensions: Vec<String>,
+    tag: Vec<String>,
+}
+
+impl CompiledRule {
+    /// 编译并校验规则；没有任何条件或标签路径为空时返回错误
+    pub fn new(conditions: &RuleConditions, tag_path: &str) -> anyhow::Result<Self> {
+        let tag: Vec<String> = tag_path
+            .split('/')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        if tag.is_empty() {
+            anyhow::bail!("标签路径不能为空");
+        }
+
+        let filename_regex = conditions
+            .filename_regex
+            .as_deref()
+            .map(|re| Regex::new(re).map_err(|e| anyhow::anyhow!("文件名正则无效: {}", e)))
+            .transpose()?;
+        let path_glob = conditions
+            .path_glob
+            .as_deref()
+            .map(|glob| {
+                GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .build()
+                    .map(|g| g.compile_matcher())
+                    .map_err(|e| anyhow::anyhow!("路径通配无效: {}", e))
+            })
+            .transpose()?;
+        let extensions: Vec<String> = conditions
+            .extensions
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+
+        if filename_regex.is_none() && path_glob.is_none() && extensions.is_empty() {
+            anyhow::bail!("规则至少需要一个匹配条件");
+        }
+        Ok(Self { filename_regex, path_glob, extensions, tag })
+    }
+
+    /// 文件路径是否满足全部条件
+    pub fn matches(&self, path: &str) -> bool {
+        let filename = path.rsplit_once('/').map_or(path, |(_, name)| name);
+        if let Some(re) = &self.filename_regex
+            && !re.is_match(filename)
+        {
+            return false;
+        }
+        if let Some(glob) = &self.path_glob
+            && !glob.is_match(path)
+        {
+            return false;
+        }
+        if !self.extensions.is_empty() {
+            let ext = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+            if !ext.is_some_and(|ext| self.extensions.contains(&ext)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 规则标签器
+#[derive(Default)]
+pub struct RuleTagger {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleTagger {
+    /// 编译规则，无效的规则记录日志后跳过
+    pub fn new(rules: &[TagRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|rule| rule.enabled)
+            .filter_map(|rule| match rule.conditions().and_then(|c| CompiledRule::new(&c, &rule.tag_path)) {
+                Ok(compiled) => Some(compiled),
+                Err(e) => {
+                    warn!("跳过无效的标签规则 #{} ({}): {}", rule.id, rule.name, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// 加载适用于资源库的规则 (包括全局规则)
+    pub async fn load(db: &SqlitePool, library_id: i32) -> anyhow::Result<Self> {
+        let rules: Vec<TagRule> = sqlx::query_as(
+            "SELECT * FROM tag_rules WHERE enabled = 1 AND (library_id IS NULL OR library_id = ?) ORDER BY id"
+        )
+        .bind(library_id)
+        .fetch_all(db)
+        .await?;
+        Ok(Self::new(&rules))
+    }
+
+    /// 命中规则的标签路径 (去重)
+    pub fn matching_tags(&self, path: &str) -> Vec<Vec<String>> {
+        let mut tags: Vec<Vec<String>> = Vec::new();
+        for rule in self.rules.iter().filter(|r| r.matches(path)) {
+            if !tags.contains(&rule.tag) {
+                tags.push(rule.tag.clone());
+            }
+        }
+        tags
+    }
+}
+
+impl Tagger for RuleTagger {
+    fn source(&self) -> &'static str {
+        "rule"
+    }
+
+    fn category(&self) -> TagCategory {
+        TagCategory::User
+    }
+
+    fn tag<'a>(&'a self, file: &'a FileContext<'a>) -> BoxFuture<'a, anyhow::Result<Vec<Vec<String>>>> {
+        Box::pin(async move { Ok(self.matching_tags(file.path)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i32, conditions: &str, tag_path: &str) -> TagRule {
+        TagRule {
+            id,
+            library_id: None,
+            name: format!("rule {}", id),
+            conditions: conditions.to_string(),
+            tag_path: tag_path.to_string(),
+            enabled: true,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let tagger = RuleTagger::new(&[
+            rule(1, r#"{"filename_regex":"^IMG_\\d+"}"#, "Camera"),
+            rule(2, r#"{"path_glob":"**/invoices/**","extensions":["PDF"]}"#, "Finance/Invoices"),
+            rule(3, r#"{"extensions":["pdf"]}"#, "Finance/Invoices"),
+            // 无效规则被跳过
+            rule(4, r#"{"filename_regex":"("}"#, "Broken"),
+        ]);
+
+        assert_eq!(tagger.matching_tags("DCIM/IMG_0001.jpg"), vec![vec!["Camera".to_string()]]);
+        assert!(tagger.matching_tags("DCIM/img_0001.jpg").is_empty());
+        // 两条规则命中同一标签只输出一次
+        assert_eq!(
+            tagger.matching_tags("2024/invoices/march.pdf"),
+            vec![vec!["Finance".to_string(), "Invoices".to_string()]]
+        );
+        assert_eq!(tagger.matching_tags("invoices/notes.txt"), Vec::<Vec<String>>::new());
+    }
+
+    #[test]
+    fn test_rule_validation() {
+        let empty = RuleConditions::default();
+        assert!(CompiledRule::new(&empty, "A").is_err());
+        let ext = RuleConditions { extensions: vec!["pdf".to_string()], ..Default::default() };
+        assert!(CompiledRule::new(&ext, " / ").is_err());
+        let glob = RuleConditions { path_glob: Some("[".to_string()), ..Default::default() };
+        assert!(CompiledRule::new(&glob, "A").is_err());
+        // `*` 不跨越目录
+        let star = RuleConditions { path_glob: Some("*.pdf".to_string()), ..Default::default() };
+        let star = CompiledRule::new(&star, "A").unwrap();
+        assert!(star.matches("a.pdf"));
+        assert!(!star.matches("sub/a.pdf"));
+    }
+}
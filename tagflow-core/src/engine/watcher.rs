@@ -0,0 +1,426 @@
+//! 本地资源库实时监听
+//!
+//! 基于 inotify (notify crate) 监听 `protocol = "local"` 且配置了 `"watch": true` 的资源库。
+//! 事件经过防抖与合并后转换为若干扫描范围，交给 `Scanner::scan_scopes` 处理，
+//! 因此新增、修改、删除与移动的判定逻辑与全量扫描完全一致。
+//! 事件队列溢出或监听出错时退化为一次全量扫描。
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::engine::scan_job::{ScanJobError, ScanJobManager};
+use crate::engine::scanner::{ScanProgress, ScanScope, Scanner};
+use crate::models::db::Library;
+
+/// 最后一个事件之后的静默时间，超过即处理本批事件
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// 一批事件最长等待时间，避免持续写入时迟迟不处理
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(5);
+/// 资源库正在全量扫描时的重试间隔
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// 事件通道容量，写满即视为溢出
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// 监听线程发往处理任务的信号
+enum WatchSignal {
+    Paths(Vec<PathBuf>),
+    Rescan,
+}
+
+/// 监听管理器
+///
+/// 克隆开销很低，所有克隆共享同一组监听。
+#[derive(Clone)]
+pub struct WatchManager {
+    db: SqlitePool,
+    scans: ScanJobManager,
+    /// library_id -> 停止信号
+    watchers: Arc<Mutex<HashMap<i32, CancellationToken>>>,
+}
+
+impl WatchManager {
+    pub fn new(db: SqlitePool, scans: ScanJobManager) -> Self {
+        Self {
+            db,
+            scans,
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 为所有启用监听的本地资源库启动监听，返回启动数量
+    pub async fn start_all(&self) -> anyhow::Result<usize> {
+        let libraries: Vec<Library> = sqlx::query_as("SELECT * FROM libraries WHERE protocol = 'local'")
+            .fetch_all(&self.db)
+            .await?;
+
+        let mut started = 0;
+        for library in libraries {
+            let name = library.name.clone();
+            match self.start(library) {
+                Ok(true) => started += 1,
+                Ok(false) => {}
+                Err(e) => warn!("资源库 {} 启动监听失败: {}", name, e),
+            }
+        }
+        Ok(started)
+    }
+
+    /// 启动资源库监听
+    ///
+    /// # 返回
+    /// - `Ok(true)`: 已启动 (或已在运行)
+    /// - `Ok(false)`: 资源库未启用监听或不是本地资源库
+    pub fn start(&self, library: Library) -> anyhow::Result<bool> {
+        if library.protocol != "local" || !library.config()?.watch {
+            return Ok(false);
+        }
+        if self.watchers.lock().unwrap().contains_key(&library.id) {
+            return Ok(true);
+        }
+
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let overflowed = Arc::new(AtomicBool::new(false));
+        let overflow_flag = overflowed.clone();
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let signal = match res {
+                Ok(event) if event.need_rescan() => WatchSignal::Rescan,
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => return,
+                Ok(event) => WatchSignal::Paths(event.paths),
+                Err(e) => {
+                    warn!("文件监听出错，将退化为全量扫描: {}", e);
+                    WatchSignal::Rescan
+                }
+            };
+            if tx.try_send(signal).is_err() {
+                overflow_flag.store(true, Ordering::Relaxed);
+            }
+        })?;
+        let base_path = PathBuf::from(&library.base_path);
+        watcher.watch(&base_path, RecursiveMode::Recursive)?;
+
+        let cancel = CancellationToken::new();
+        self.watchers.lock().unwrap().insert(library.id, cancel.clone());
+
+        info!("已启动资源库 {} 的文件监听: {}", library.name, library.base_path);
+        let worker = WatchWorker {
+            db: self.db.clone(),
+            scans: self.scans.clone(),
+            library_id: library.id,
+            base_path,
+            overflowed,
+        };
+        let watchers = self.watchers.clone();
+        tokio::spawn(async move {
+            // watcher 随任务结束一起释放
+            let _watcher = watcher;
+            worker.run(rx, cancel).await;
+            watchers.lock().unwrap().remove(&worker.library_id);
+        });
+
+        Ok(true)
+    }
+
+    /// 停止资源库监听
+    pub fn stop(&self, library_id: i32) {
+        if let Some(cancel) = self.watchers.lock().unwrap().remove(&library_id) {
+            cancel.cancel();
+            info!("已停止资源库 {} 的文件监听", library_id);
+        }
+    }
+}
+
+/// 单个资源库的事件处理任务
+struct WatchWorker {
+    db: SqlitePool,
+    scans: ScanJobManager,
+    library_id: i32,
+    base_path: PathBuf,
+    overflowed: Arc<AtomicBool>,
+}
+
+impl WatchWorker {
+    async fn run(&self, mut rx: mpsc::Receiver<WatchSignal>, cancel: CancellationToken) {
+        let mut dirty: HashSet<String> = HashSet::new();
+        let mut rescan = false;
+        let mut batch_started: Option<Instant> = None;
+        let mut deadline = Instant::now();
+
+        loop {
+            let pending = batch_started.is_some();
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                signal = rx.recv() => {
+                    let Some(signal) = signal else { break };
+                    match signal {
+                        WatchSignal::Rescan => rescan = true,
+                        WatchSignal::Paths(paths) => {
+                            dirty.extend(paths.iter().filter_map(|p| relative_path(&self.base_path, p)));
+                        }
+                    }
+                    let now = Instant::now();
+                    let started = *batch_started.get_or_insert(now);
+                    deadline = (now + DEBOUNCE).min(started + MAX_BATCH_DELAY);
+                }
+                _ = sleep_until(deadline), if pending => {
+                    rescan |= self.overflowed.swap(false, Ordering::Relaxed);
+                    match self.flush(&mut dirty, &mut rescan).await {
+                        true => batch_started = None,
+                        false => deadline = Instant::now() + RETRY_DELAY,
+                    }
+                }
+            }
+        }
+        debug!("资源库 {} 的监听任务已退出", self.library_id);
+    }
+
+    /// 处理一批事件，资源库被占用时返回 `false` 以便稍后重试
+    async fn flush(&self, dirty: &mut HashSet<String>, rescan: &mut bool) -> bool {
+        let library: Library = match sqlx::query_as("SELECT * FROM libraries WHERE id = ?")
+            .bind(self.library_id)
+            .fetch_optional(&self.db)
+            .await
+        {
+            Ok(Some(library)) => library,
+            Ok(None) => {
+                dirty.clear();
+                return true;
+            }
+            Err(e) => {
+                error!("读取资源库 {} 失败: {}", self.library_id, e);
+                return false;
+            }
+        };
+
+        if *rescan {
+            // 已有扫描在运行时它可能已走过发生变更的目录，保留标记稍后重新提交
+            if !self.enqueue_full_scan(library).await {
+                debug!("资源库 {} 正在扫描，稍后提交全量扫描", self.library_id);
+                return false;
+            }
+            dirty.clear();
+            *rescan = false;
+            return true;
+        }
+
+        let lock = self.scans.library_lock(self.library_id);
+        let Ok(_guard) = lock.try_lock() else {
+            debug!("资源库 {} 正在扫描，稍后处理 {} 个变更", self.library_id, dirty.len());
+            return false;
+        };
+
+        let scopes = build_scopes(&self.base_path, dirty.drain());
+        if scopes.is_empty() {
+            return true;
+        }
+        debug!("资源库 {} 增量同步: {:?}", self.library_id, scopes);
+
+        match Scanner::new(self.db.clone()).scan_scopes(&library, &scopes, &ScanProgress::default()).await {
+            Ok(stats) => debug!("资源库 {} 增量同步完成: {:?}", self.library_id, stats),
+            Err(e) => {
+                warn!("资源库 {} 增量同步失败，退化为全量扫描: {}", self.library_id, e);
+                drop(_guard);
+                if !self.enqueue_full_scan(library).await {
+                    *rescan = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// 提交全量扫描；资源库已有扫描在运行时返回 `false`，由调用方稍后重试
+    async fn enqueue_full_scan(&self, library: Library) -> bool {
+        match self.scans.enqueue(library).await {
+            Ok(job_id) => info!("资源库 {} 已提交全量扫描任务 {}", self.library_id, job_id),
+            Err(ScanJobError::AlreadyRunning { .. }) => return false,
+            Err(e) => error!("资源库 {} 提交全量扫描失败: {}", self.library_id, e),
+        }
+        true
+    }
+}
+
+/// 将事件路径转换为相对资源库根目录的路径 (`/` 分隔)，根目录自身返回 `None`
+fn relative_path(base: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(base).ok()?;
+    let rel = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    (!rel.is_empty()).then_some(rel)
+}
+
+/// 将变更路径合并为扫描范围
+///
+/// - 每个路径的父目录做一次非递归同步，覆盖文件的新增、修改与删除
+/// - 路径若不是现存文件 (新目录或已删除/移走的条目)，再对其做一次递归同步
+/// - 已被递归范围覆盖的范围会被合并掉
+fn build_scopes(base: &Path, paths: impl IntoIterator<Item = String>) -> Vec<ScanScope> {
+    let mut scopes: BTreeSet<ScanScope> = BTreeSet::new();
+    for rel in paths {
+        let parent = match rel.rfind('/') {
+            Some(idx) => rel[..=idx].to_string(),
+            None => String::new(),
+        };
+        scopes.insert(ScanScope { dir: parent, recursive: false });
+        if !base.join(&rel).is_file() {
+            scopes.insert(ScanScope { dir: format!("{}/", rel), recursive: true });
+        }
+    }
+
+    ScanScope::merge(scopes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::db::test_pool;
+
+    #[test]
+    fn test_relative_path() {
+        let base = Path::new("/data/photos/");
+        assert_eq!(relative_path(base, Path::new("/data/photos/2024/a.jpg")), Some("2024/a.jpg".into()));
+        assert_eq!(relative_path(base, Path::new("/data/photos")), None);
+        assert_eq!(relative_path(base, Path::new("/other/a.jpg")), None);
+    }
+
+    #[test]
+    fn test_build_scopes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("New/Sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("New/Sub/b.txt"), b"b").unwrap();
+
+        let scopes = build_scopes(
+            dir.path(),
+            ["a.txt", "New", "New/Sub/b.txt", "Gone"].map(String::from),
+        );
+        assert_eq!(scopes, vec![
+            ScanScope { dir: "".into(), recursive: false },
+            ScanScope { dir: "Gone/".into(), recursive: true },
+            ScanScope { dir: "New/".into(), recursive: true },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_overflow_schedules_full_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("A")).unwrap();
+        std::fs::create_dir_all(dir.path().join("B")).unwrap();
+        std::fs::write(dir.path().join("A/x.txt"), b"x").unwrap();
+        std::fs::write(dir.path().join("B/y.txt"), b"y").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path) VALUES ('w', 'local', ?)")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library_id: i32 = sqlx::query_scalar("SELECT id FROM libraries").fetch_one(&pool).await.unwrap();
+
+        let overflowed = Arc::new(AtomicBool::new(false));
+        let scans = ScanJobManager::new(pool.clone());
+        let worker = WatchWorker {
+            db: pool.clone(),
+            scans: scans.clone(),
+            library_id,
+            base_path: dir.path().to_path_buf(),
+            overflowed: overflowed.clone(),
+        };
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { worker.run(rx, cancel).await }
+        });
+
+        async fn wait_for_runs(pool: &SqlitePool, expected: i64) {
+            let mut completed = 0;
+            for _ in 0..100 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                completed = sqlx::query_scalar("SELECT COUNT(*) FROM scan_runs WHERE status = 2")
+                    .fetch_one(pool).await.unwrap();
+                if completed == expected {
+                    break;
+                }
+            }
+            assert_eq!(completed, expected);
+        }
+
+        // 队列写满丢弃事件后，只收到的部分路径不可信，应提交全量扫描而不是只同步 A/
+        tx.send(WatchSignal::Paths(vec![dir.path().join("A/x.txt")])).await.unwrap();
+        overflowed.store(true, Ordering::Relaxed);
+        wait_for_runs(&pool, 1).await;
+        let indexed: Vec<String> = sqlx::query_scalar("SELECT parent_path || filename FROM files ORDER BY 1")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(indexed, vec!["A/x.txt".to_string(), "B/y.txt".to_string()]);
+
+        // notify 要求重新扫描 (内核队列溢出) 时同样提交全量扫描
+        std::fs::write(dir.path().join("B/z.txt"), b"z").unwrap();
+        tx.send(WatchSignal::Paths(vec![dir.path().join("A/x.txt")])).await.unwrap();
+        tx.send(WatchSignal::Rescan).await.unwrap();
+        wait_for_runs(&pool, 2).await;
+        let status: i32 = sqlx::query_scalar("SELECT status FROM files WHERE filename = 'z.txt'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(status, 1);
+
+        // 溢出时已有扫描在运行 (此处持有资源库锁使其停在开始前)，不能丢弃全量扫描，需在其结束后补交
+        let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+        let lock = scans.library_lock(library_id);
+        let guard = lock.lock().await;
+        scans.enqueue(library).await.unwrap();
+        tx.send(WatchSignal::Rescan).await.unwrap();
+        tokio::time::sleep(DEBOUNCE * 2).await;
+        drop(guard);
+        wait_for_runs(&pool, 4).await;
+
+        cancel.cancel();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watcher_indexes_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.txt"), b"old").unwrap();
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO libraries (name, protocol, base_path, config_json) VALUES ('w', 'local', ?, '{\"watch\": true}')")
+            .bind(dir.path().to_str().unwrap())
+            .execute(&pool).await.unwrap();
+        let library: Library = sqlx::query_as("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+        let library_id = library.id;
+        Scanner::new(pool.clone()).scan_library(&library).await.unwrap();
+
+        let scans = ScanJobManager::new(pool.clone());
+        let watchers = WatchManager::new(pool.clone(), scans);
+        assert!(watchers.start(library).unwrap());
+
+        std::fs::create_dir_all(dir.path().join("Inbox")).unwrap();
+        std::fs::write(dir.path().join("Inbox/new.txt"), b"new").unwrap();
+        std::fs::remove_file(dir.path().join("old.txt")).unwrap();
+
+        let mut state = Vec::new();
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            state = sqlx::query_as::<_, (String, i32)>(
+                "SELECT parent_path || filename, status FROM files ORDER BY 1"
+            )
+            .fetch_all(&pool).await.unwrap();
+            if state == vec![("Inbox/new.txt".to_string(), 1), ("old.txt".to_string(), 0)] {
+                break;
+            }
+        }
+        assert_eq!(state, vec![("Inbox/new.txt".to_string(), 1), ("old.txt".to_string(), 0)]);
+
+        watchers.stop(library_id);
+    }
+}
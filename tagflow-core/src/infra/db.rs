@@ -20,4 +20,19 @@ pub async fn init_db(database_url: &str) -> anyhow::Result<SqlitePool> {
         .await?;
 
     Ok(pool)
-}
\ No newline at end of file
+}
+
+/// 创建仅供测试使用的内存数据库（已应用迁移）
+///
+/// 内存库在每个连接上都是独立的，因此连接池限定为单连接。
+#[cfg(test)]
+pub(crate) async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("无法创建内存数据库");
+    sqlx::query("PRAGMA foreign_keys = ON;").execute(&pool).await.unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
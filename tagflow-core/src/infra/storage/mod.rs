@@ -1,22 +1,183 @@
 use opendal::{services, Operator};
-use crate::models::db::Library;
+use crate::models::db::{Library, LibraryConfig};
 
 pub struct StorageManager;
 
 impl StorageManager {
     /// 根据 Library 配置初始化 OpenDAL 算子
     pub fn get_operator(library: &Library) -> anyhow::Result<Operator> {
-        match library.protocol.as_str() {
+        let config = library.config()?;
+        Self::build_operator(&library.protocol, &library.base_path, &config)
+    }
+
+    /// 根据协议、根路径与扩展配置构建算子
+    ///
+    /// 供尚未落库的资源库（如连接测试）复用同一套初始化逻辑。
+    pub fn build_operator(protocol: &str, base_path: &str, config: &LibraryConfig) -> anyhow::Result<Operator> {
+        match protocol {
             "local" => {
-                let builder = services::Fs::default().root(&library.base_path);
+                let builder = services::Fs::default().root(base_path);
                 let op = Operator::new(builder)?.finish();
                 Ok(op)
             }
             "webdav" => {
-                // 预留 WebDAV 实现空间
-                anyhow::bail!("WebDAV 协议暂未在此阶段启用")
+                let endpoint = config
+                    .endpoint
+                    .as_deref()
+                    .filter(|e| !e.is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("WebDAV 资源库缺少 endpoint 配置"))?;
+
+                let mut builder = services::Webdav::default()
+                    .endpoint(endpoint)
+                    .root(base_path);
+                if let Some(username) = &config.username {
+                    builder = builder.username(username);
+                }
+                if let Some(password) = &config.password {
+                    builder = builder.password(password);
+                }
+                let op = Operator::new(builder)?.finish();
+                Ok(op)
             }
-            _ => anyhow::bail!("不支持的协议: {}", library.protocol),
+            _ => anyhow::bail!("不支持的协议: {}", protocol),
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{Method, Request, StatusCode},
+        response::Response,
+        Router,
+    };
+    use std::path::PathBuf;
+
+    /// 本地 WebDAV 替身服务：仅实现扫描所需的 PROPFIND 与 GET
+    ///
+    /// 将 `root` 目录挂载在 `/dav` 前缀下，返回服务地址 (如 `http://127.0.0.1:port`)。
+    pub(crate) async fn spawn_webdav_stub(root: PathBuf) -> String {
+        let app = Router::new().fallback(handle_dav).with_state(root);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn handle_dav(State(root): State<PathBuf>, req: Request<Body>) -> Response {
+        let raw = req.uri().path().to_string();
+        let decoded = percent_decode(&raw);
+        let rel = decoded.trim_start_matches("/dav").trim_start_matches('/');
+        let target = root.join(rel);
+
+        if req.method() == Method::GET {
+            return match tokio::fs::read(&target).await {
+                Ok(bytes) => Response::new(Body::from(bytes)),
+                Err(_) => status(StatusCode::NOT_FOUND),
+            };
+        }
+        if req.method().as_str() != "PROPFIND" {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let Ok(meta) = std::fs::metadata(&target) else {
+            return status(StatusCode::NOT_FOUND);
+        };
+        let depth = req
+            .headers()
+            .get("depth")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("1")
+            .to_string();
+
+        let base_href = format!("/dav/{}", rel);
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+        xml.push_str(&dav_entry(&base_href, &meta));
+        if meta.is_dir() && depth != "0" {
+            let base = base_href.trim_end_matches('/');
+            for entry in std::fs::read_dir(&target).unwrap().flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let child_meta = entry.metadata().unwrap();
+                xml.push_str(&dav_entry(&format!("{}/{}", base, name), &child_meta));
+            }
+        }
+        xml.push_str("</D:multistatus>");
+
+        Response::builder()
+            .status(StatusCode::MULTI_STATUS)
+            .header("content-type", "application/xml")
+            .body(Body::from(xml))
+            .unwrap()
+    }
+
+    fn dav_entry(href: &str, meta: &std::fs::Metadata) -> String {
+        let mtime: chrono::DateTime<chrono::Utc> = meta.modified().unwrap().into();
+        let (href, props) = if meta.is_dir() {
+            let href = if href.ends_with('/') { href.to_string() } else { format!("{}/", href) };
+            (href, "<D:resourcetype><D:collection/></D:resourcetype>".to_string())
+        } else {
+            (
+                href.to_string(),
+                format!("<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>", meta.len()),
+            )
+        };
+        format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:getlastmodified>{}</D:getlastmodified>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+            href,
+            mtime.format("%a, %d %b %Y %H:%M:%S GMT"),
+            props
+        )
+    }
+
+    fn percent_decode(s: &str) -> String {
+        let bytes = s.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            // 在字节上取两位十六进制数，`%` 后紧跟多字节字符时不能按 `&str` 切片
+            if bytes[i] == b'%'
+                && let Some(hex) = bytes.get(i + 1..i + 3)
+                && hex.iter().all(u8::is_ascii_hexdigit)
+                && let Ok(hex) = std::str::from_utf8(hex)
+                && let Ok(b) = u8::from_str_radix(hex, 16)
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&out).to_string()
+    }
+
+    fn status(code: StatusCode) -> Response {
+        Response::builder().status(code).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_webdav_requires_endpoint() {
+        let err = StorageManager::build_operator("webdav", "/photos", &LibraryConfig::default());
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("/dav/a%20b/%E6%96%87"), "/dav/a b/文");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("a%41"), "aA");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%é1/%+1"), "%é1/%+1");
+        assert_eq!(percent_decode("x%文"), "x%文");
+    }
+
+    #[test]
+    fn test_unknown_protocol() {
+        assert!(StorageManager::build_operator("ftp", "/", &LibraryConfig::default()).is_err());
+    }
+}
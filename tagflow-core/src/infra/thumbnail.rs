@@ -7,6 +7,14 @@ use std::process::Command;
 use sqlx::{SqlitePool, Row};
 use tracing::{debug, warn, error, info};
 
+/// 缩略图缓存目录
+pub const THUMBNAIL_CACHE_DIR: &str = "./cache";
+
+/// 缩略图文件路径
+pub fn thumbnail_path(cache_dir: &str, file_id: i32) -> String {
+    format!("{}/{}.webp", cache_dir, file_id)
+}
+
 /// 缩略图生成器
 pub struct ThumbnailGenerator {
     cache_dir: String,
@@ -54,7 +62,7 @@ impl ThumbnailGenerator {
 
         // 构建完整路径
         let full_path = format!("{}{}{}", base_path, parent_path, filename);
-        let output_path = format!("{}/{}.webp", self.cache_dir, file_id);
+        let output_path = thumbnail_path(&self.cache_dir, file_id);
 
         // 检查源文件是否存在
         if !Path::new(&full_path).exists() {
@@ -130,7 +138,7 @@ impl ThumbnailGenerator {
     /// # 返回
     /// 缩略图文件路径
     pub fn get_thumbnail_path(&self, file_id: i32) -> String {
-        format!("{}/{}.webp", self.cache_dir, file_id)
+        thumbnail_path(&self.cache_dir, file_id)
     }
 
     /// 检查缩略图是否存在
@@ -2,7 +2,7 @@ use tracing::{info, warn, error, debug};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use axum::{
     extract::Request,
-    routing::{get, post, delete},
+    routing::{get, post, put, patch, delete},
     Router, middleware,
     middleware::Next,
     response::Response,
@@ -45,13 +45,35 @@ async fn main() -> anyhow::Result<()> {
     // 初始化管理员用户（如果不存在）
     ensure_admin_user(&pool).await?;
 
+    // 清理上次退出时未完成的扫描记录
+    let interrupted = tagflow_core::engine::scan_job::fail_interrupted_runs(&pool).await?;
+    if interrupted > 0 {
+        warn!("{} 个扫描记录因服务重启被标记为失败", interrupted);
+    }
+    let interrupted = tagflow_core::engine::retag_job::fail_interrupted_retags(&pool).await?;
+    if interrupted > 0 {
+        warn!("{} 个重新打标签记录因服务重启被标记为失败", interrupted);
+    }
+
     // 启动后台任务 Worker
     let pool_for_worker = pool.clone();
     tokio::spawn(async move {
-        tagflow_core::engine::worker::start_task_worker(pool_for_worker, "./cache".to_string()).await;
+        tagflow_core::engine::worker::start_task_worker(pool_for_worker, infra::thumbnail::THUMBNAIL_CACHE_DIR.to_string()).await;
     });
     info!("后台任务 Worker 已启动");
 
+    // 启动本地资源库文件监听
+    let state = api::AppState::new(pool);
+    match state.watchers.start_all().await {
+        Ok(n) if n > 0 => info!("已启动 {} 个资源库的文件监听", n),
+        Ok(_) => {}
+        Err(e) => warn!("启动文件监听失败: {}", e),
+    }
+
+    // 启动定时扫描调度器
+    let scheduler = tagflow_core::engine::scheduler::ScanScheduler::new(state.pool.clone(), state.scans.clone());
+    tokio::spawn(scheduler.run());
+
     // 构建路由
     // 1. 公开路由（无需认证）
     let auth_routes = Router::new()
@@ -61,23 +83,49 @@ async fn main() -> anyhow::Result<()> {
     // 2. 受保护的路由（需要认证）
     let protected_routes = Router::new()
         .route("/api/v1/tags/tree", get(api::tag::get_tag_tree))
+        .route("/api/v1/tags", post(api::tag::create_tag))
+        .route("/api/v1/tags/bulk", post(api::tag::bulk_tags))
+        .route("/api/v1/tags/:id", patch(api::tag::update_tag).delete(api::tag::delete_tag))
         .route("/api/v1/files", get(api::file::list_files))
+        .route("/api/v1/files/missing", get(api::file::list_missing_files))
+        .route("/api/v1/files/archive", post(api::archive::download_archive))
+        .route("/api/v1/search", get(api::search::search_files))
+        .route("/api/v1/files/:id", get(api::file::get_file))
         .route("/api/v1/files/:id/thumbnail", get(api::file::get_thumbnail))
+        .route("/api/v1/files/:id/content-token", post(api::file::create_file_content_token))
+        .route("/api/v1/files/:id/tags", post(api::tag::attach_tag))
+        .route("/api/v1/files/:id/tags/:tag_id", delete(api::tag::detach_tag))
         .route("/api/auth/update-password", post(api::auth::update_password))
         // Library 管理 API
         .route("/api/v1/libraries", get(api::library::list_libraries))
         .route("/api/v1/libraries", post(api::library::create_library))
         .route("/api/v1/libraries/test", post(api::library::test_library_connection))
         .route("/api/v1/libraries/:id", delete(api::library::delete_library))
+        .route("/api/v1/libraries/:id/schedule", put(api::library::update_schedule))
         .route("/api/v1/libraries/:id/scan", post(api::library::trigger_scan))
+        .route("/api/v1/libraries/:id/scans", get(api::library::list_scan_runs))
+        .route("/api/v1/libraries/:id/scans/current", get(api::library::get_scan_progress))
+        .route("/api/v1/libraries/:id/purge-lost", post(api::library::purge_lost))
+        .route("/api/v1/libraries/:id/retag", post(api::library::retag))
+        .route("/api/v1/retag-jobs/:id", get(api::library::get_retag_job))
+        .route("/api/v1/rules", get(api::rule::list_rules).post(api::rule::create_rule))
+        .route("/api/v1/rules/apply", post(api::rule::apply_rules))
+        .route("/api/v1/rules/:id", put(api::rule::update_rule).delete(api::rule::delete_rule))
         .layer(middleware::from_fn(api::auth::auth_middleware))
         .layer(middleware::from_fn(request_logging_middleware));
 
+    // 3. 文件内容路由（Authorization 头或 ?token= 文件内容令牌，供 <img>/<video> 直接引用）
+    let content_routes = Router::new()
+        .route("/api/v1/files/:id/content", get(api::file::get_file_content))
+        .route_layer(middleware::from_fn(api::auth::content_auth_middleware))
+        .layer(middleware::from_fn(request_logging_middleware));
+
     // 合并路由
     let app = Router::new()
         .merge(auth_routes)
         .merge(protected_routes)
-        .with_state(pool);
+        .merge(content_routes)
+        .with_state(state);
 
     // 启动服务器
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
@@ -1,9 +1,11 @@
 
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TagCategory {
     Path,
     Type,
@@ -22,6 +24,20 @@ impl ToString for TagCategory {
     }
 }
 
+impl std::str::FromStr for TagCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(TagCategory::Path),
+            "type" => Ok(TagCategory::Type),
+            "user" => Ok(TagCategory::User),
+            "time" => Ok(TagCategory::Time),
+            _ => anyhow::bail!("未知的标签分类: {}", s),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Library {
     pub id: i32,
@@ -30,6 +46,208 @@ pub struct Library {
     pub base_path: String,
     pub config_json: Option<String>,
     pub last_scanned_at: Option<DateTime<Utc>>,
+    /// 定时扫描间隔 (秒)
+    pub scan_interval_secs: Option<i64>,
+    /// 定时扫描 cron 表达式
+    pub scan_cron: Option<String>,
+    /// 下次计划扫描时间
+    pub next_scan_at: Option<DateTime<Utc>>,
+}
+
+impl Library {
+    /// 解析 `config_json` 中的扩展配置，为空时返回默认配置
+    pub fn config(&self) -> anyhow::Result<LibraryConfig> {
+        LibraryConfig::parse(self.config_json.as_deref())
+    }
+}
+
+/// 资源库扩展配置 (存储于 `libraries.config_json`)
+///
+/// ```json
+/// {
+///   "endpoint": "https://nas.local/dav",
+///   "username": "alice",
+///   "password": "secret",
+///   "hash_mode": "sampled",
+///   "exclude": ["*.tmp", "/cache/"],
+///   "include": [],
+///   "watch": false,
+///   "stat_concurrency": 8,
+///   "lost_retention_days": 30,
+///   "type_mapping": { "psd": "Image/Design" },
+///   "type_sniff": "unknown",
+///   "time_tags": "month",
+///   "capture_time": true,
+///   "disabled_taggers": ["time"]
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryConfig {
+    /// WebDAV 服务地址
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// WebDAV 用户名
+    #[serde(default)]
+    pub username: Option<String>,
+    /// WebDAV 密码
+    #[serde(default)]
+    pub password: Option<String>,
+    /// 内容哈希模式，用于检测文件移动/重命名
+    #[serde(default)]
+    pub hash_mode: HashMode,
+    /// 排除规则 (gitignore 语法，相对资源库根目录)
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// 包含规则；非空时只索引匹配的文件
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// 是否启用内置排除项 (.git、node_modules、@eaDir、.DS_Store 等)
+    #[serde(default = "default_true")]
+    pub default_excludes: bool,
+    /// 是否实时监听文件变化 (仅 local 协议)
+    #[serde(default)]
+    pub watch: bool,
+    /// 扫描时并发 stat 请求数，远程存储可适当调大
+    #[serde(default = "default_stat_concurrency")]
+    pub stat_concurrency: usize,
+    /// 丢失文件保留天数，超过后在扫描结束时清理；为空表示永久保留
+    #[serde(default)]
+    pub lost_retention_days: Option<u32>,
+    /// 自定义类型映射 (扩展名 -> 类型路径)，覆盖内置映射
+    #[serde(default)]
+    pub type_mapping: HashMap<String, String>,
+    /// 何时读取文件头识别类型
+    #[serde(default)]
+    pub type_sniff: TypeSniff,
+    /// 时间标签粒度
+    #[serde(default)]
+    pub time_tags: TimeGranularity,
+    /// 是否优先读取 EXIF/视频元数据中的拍摄时间 (否则只用修改时间)
+    #[serde(default = "default_true")]
+    pub capture_time: bool,
+    /// 禁用的自动标签器 (`path`、`type`、`time`、`rule`)
+    #[serde(default)]
+    pub disabled_taggers: Vec<String>,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            username: None,
+            password: None,
+            hash_mode: HashMode::default(),
+            exclude: Vec::new(),
+            include: Vec::new(),
+            default_excludes: true,
+            watch: false,
+            stat_concurrency: default_stat_concurrency(),
+            lost_retention_days: None,
+            type_mapping: HashMap::new(),
+            type_sniff: TypeSniff::default(),
+            time_tags: TimeGranularity::default(),
+            capture_time: true,
+            disabled_taggers: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_stat_concurrency() -> usize {
+    8
+}
+
+/// 文件内容哈希模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashMode {
+    /// 不计算哈希，关闭移动检测
+    Off,
+    /// 采样哈希：仅读取文件首、中、尾各一小段，适合大文件与远程存储；
+    /// 只与同一次扫描中丢失的文件配对为移动
+    #[default]
+    Sampled,
+    /// 完整哈希：读取全部内容，可与任意丢失的文件配对为移动
+    Full,
+}
+
+/// 文件头类型嗅探策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeSniff {
+    /// 不读取文件头，仅按扩展名分类
+    Off,
+    /// 仅在扩展名缺失或未知时读取文件头
+    #[default]
+    Unknown,
+    /// 总是读取文件头，可纠正错误的扩展名
+    Always,
+}
+
+/// 时间标签粒度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeGranularity {
+    /// 不生成时间标签
+    Off,
+    /// 年/月
+    #[default]
+    Month,
+    /// 年/月/日
+    Day,
+}
+
+impl LibraryConfig {
+    /// 从 JSON 字符串解析配置
+    pub fn parse(config_json: Option<&str>) -> anyhow::Result<Self> {
+        match config_json.map(str::trim) {
+            Some(s) if !s.is_empty() => serde_json::from_str(s)
+                .map_err(|e| anyhow::anyhow!("config_json 格式错误: {}", e)),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// 自动标签规则
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TagRule {
+    pub id: i32,
+    /// 适用的资源库，为空表示所有资源库
+    pub library_id: Option<i32>,
+    pub name: String,
+    /// 匹配条件 (JSON，见 [`RuleConditions`])
+    pub conditions: String,
+    /// 命中后关联的标签路径，如 `Finance/Invoices`
+    pub tag_path: String,
+    pub enabled: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl TagRule {
+    pub fn conditions(&self) -> anyhow::Result<RuleConditions> {
+        serde_json::from_str(&self.conditions).map_err(|e| anyhow::anyhow!("规则条件格式错误: {}", e))
+    }
+}
+
+/// 规则匹配条件，所有给出的条件都满足才算命中
+///
+/// ```json
+/// { "filename_regex": "^IMG_\\d+", "path_glob": "**/invoices/**", "extensions": ["pdf"] }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleConditions {
+    /// 文件名 (不含目录) 正则
+    #[serde(default)]
+    pub filename_regex: Option<String>,
+    /// 相对资源库根目录的完整路径通配，`*` 不跨越 `/`
+    #[serde(default)]
+    pub path_glob: Option<String>,
+    /// 扩展名列表 (不区分大小写)
+    #[serde(default)]
+    pub extensions: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -52,4 +270,41 @@ pub struct FileEntry {
     pub hash: Option<String>,
     pub status: i32,
     pub indexed_at: DateTime<Utc>,
-}
\ No newline at end of file
+    /// 被标记丢失的时间，在线文件为空
+    pub lost_at: Option<DateTime<Utc>>,
+}
+
+/// 扫描运行记录
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ScanRun {
+    pub id: i64,
+    pub library_id: i32,
+    /// 1=进行中, 2=已完成, 3=失败
+    pub status: i32,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub files_seen: i64,
+    pub files_added: i64,
+    pub files_modified: i64,
+    pub files_lost: i64,
+    pub errors: i64,
+    pub error_msg: Option<String>,
+    pub files_moved: i64,
+    pub files_restored: i64,
+}
+
+/// 重新打标签任务记录
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct RetagRun {
+    pub id: i64,
+    /// 为空表示所有资源库
+    pub library_id: Option<i32>,
+    /// 只替换该标签器的输出，为空表示全部
+    pub tagger: Option<String>,
+    /// 1=进行中, 2=已完成, 3=失败
+    pub status: i32,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub files_retagged: i64,
+    pub error_msg: Option<String>,
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
-use crate::models::db::{FileEntry, Library};
+use crate::models::db::{FileEntry, Library, RuleConditions, TagRule};
 use chrono::{DateTime, Utc};
+use sqlx::FromRow;
 
 #[derive(Serialize, Debug)]
 pub struct TagNode {
@@ -13,7 +14,10 @@ pub struct TagNode {
 #[derive(Serialize, Debug)]
 pub struct FileResponse {
     pub items: Vec<FileItem>,
+    /// 满足过滤条件的文件总数
     pub total: i64,
+    /// 下一页游标，没有更多数据时为空
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -24,14 +28,177 @@ pub struct FileItem {
     pub size: i64,
     pub mtime: i64,
     pub parent_path: String,
+    /// 1=在线, 0=丢失
+    pub status: i32,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct FileQuery {
     pub tag_id: Option<i32>,
     pub recursive: Option<bool>,
+    /// 标签查询表达式，例如 `Work AND NOT type:Image`
+    pub q: Option<String>,
     pub page: Option<i64>,
     pub limit: Option<i64>,
+    /// 上一页返回的 `next_cursor`，指定时忽略 `page`；仅支持按 mtime 排序
+    pub cursor: Option<String>,
+    /// 文件状态过滤，默认只返回在线文件
+    #[serde(default)]
+    pub status: FileStatusFilter,
+    /// 排序字段，默认 mtime
+    #[serde(default)]
+    pub sort: FileSort,
+    /// 排序方向，默认降序
+    #[serde(default)]
+    pub order: SortOrder,
+    pub library_id: Option<i32>,
+    /// 扩展名列表，逗号分隔，例如 `jpg,png` (不区分大小写)
+    pub ext: Option<String>,
+    /// 文件大小范围 (字节，闭区间)
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    /// 修改时间范围 (Unix 时间戳，闭区间)
+    pub mtime_from: Option<i64>,
+    pub mtime_to: Option<i64>,
+    /// 只返回该目录 (含子目录) 下的文件，例如 `Work/Design`
+    pub path_prefix: Option<String>,
+}
+
+impl FileQuery {
+    /// 解析 `ext` 参数为小写、去掉前导点的扩展名列表
+    pub fn extensions(&self) -> Vec<String> {
+        parse_extensions(self.ext.as_deref())
+    }
+}
+
+/// 解析逗号分隔的扩展名列表
+fn parse_extensions(ext: Option<&str>) -> Vec<String> {
+    ext.unwrap_or_default()
+        .split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+/// 文件列表排序字段
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSort {
+    Name,
+    Size,
+    #[default]
+    Mtime,
+    IndexedAt,
+    Extension,
+}
+
+impl FileSort {
+    /// 对应的排序表达式 (作用于别名为 `f` 的 files 表)
+    pub fn sql_column(self) -> &'static str {
+        match self {
+            FileSort::Name => "f.filename COLLATE NOCASE",
+            FileSort::Size => "f.size",
+            FileSort::Mtime => "f.mtime",
+            FileSort::IndexedAt => "f.indexed_at",
+            FileSort::Extension => "f.extension",
+        }
+    }
+}
+
+/// 排序方向
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// 文件列表游标，指向上一页最后一个文件的 `(mtime, id)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileCursor {
+    pub mtime: i64,
+    pub id: i32,
+}
+
+impl FileCursor {
+    /// 编码为不透明的字符串，客户端不应解析其内容
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.mtime, self.id)
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return None;
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let (mtime, id) = std::str::from_utf8(&bytes).ok()?.split_once(':')?;
+        Some(Self { mtime: mtime.parse().ok()?, id: id.parse().ok()? })
+    }
+}
+
+/// 文件状态过滤
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatusFilter {
+    #[default]
+    Online,
+    Lost,
+    All,
+}
+
+impl FileStatusFilter {
+    /// 对应的 SQL 条件 (作用于别名为 `f` 的 files 表)
+    pub fn sql_condition(self) -> &'static str {
+        match self {
+            FileStatusFilter::Online => "f.status = 1",
+            FileStatusFilter::Lost => "f.status = 0",
+            FileStatusFilter::All => "1 = 1",
+        }
+    }
+}
+
+/// 丢失文件查询参数
+#[derive(Deserialize, Debug)]
+pub struct MissingFileQuery {
+    pub library_id: Option<i32>,
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// 丢失文件
+#[derive(Serialize, Debug, FromRow)]
+pub struct MissingFileItem {
+    pub id: i32,
+    pub library_id: i32,
+    pub library_name: String,
+    /// 最后已知的相对路径
+    pub path: String,
+    pub size: i64,
+    pub mtime: i64,
+    /// 被标记丢失的时间
+    pub lost_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MissingFileResponse {
+    pub items: Vec<MissingFileItem>,
+    pub total: i64,
 }
 
 impl From<FileEntry> for FileItem {
@@ -43,6 +210,7 @@ impl From<FileEntry> for FileItem {
             size: entry.size,
             mtime: entry.mtime,
             parent_path: entry.parent_path,
+            status: entry.status,
         }
     }
 }
@@ -56,6 +224,21 @@ pub struct CreateLibraryRequest {
     pub protocol: String,
     pub base_path: String,
     pub config_json: Option<String>,
+    /// 定时扫描间隔 (秒)，与 `scan_cron` 互斥
+    #[serde(default)]
+    pub scan_interval_secs: Option<i64>,
+    /// 定时扫描 cron 表达式，与 `scan_interval_secs` 互斥
+    #[serde(default)]
+    pub scan_cron: Option<String>,
+}
+
+/// 更新定时扫描计划请求，两项均为空表示关闭定时扫描
+#[derive(Deserialize, Debug)]
+pub struct UpdateScheduleRequest {
+    #[serde(default)]
+    pub scan_interval_secs: Option<i64>,
+    #[serde(default)]
+    pub scan_cron: Option<String>,
 }
 
 /// 资源库响应
@@ -66,6 +249,9 @@ pub struct LibraryResponse {
     pub protocol: String,
     pub base_path: String,
     pub last_scanned_at: Option<DateTime<Utc>>,
+    pub scan_interval_secs: Option<i64>,
+    pub scan_cron: Option<String>,
+    pub next_scan_at: Option<DateTime<Utc>>,
 }
 
 impl From<Library> for LibraryResponse {
@@ -76,13 +262,296 @@ impl From<Library> for LibraryResponse {
             protocol: lib.protocol,
             base_path: lib.base_path,
             last_scanned_at: lib.last_scanned_at,
+            scan_interval_secs: lib.scan_interval_secs,
+            scan_cron: lib.scan_cron,
+            next_scan_at: lib.next_scan_at,
         }
     }
 }
 
+/// 扫描任务提交结果
+#[derive(Serialize, Debug)]
+pub struct ScanJobResponse {
+    pub job_id: i64,
+    pub library_id: i32,
+}
+
+/// 扫描实时进度
+#[derive(Serialize, Debug)]
+pub struct ScanProgressResponse {
+    pub job_id: i64,
+    pub library_id: i32,
+    pub started_at: DateTime<Utc>,
+    /// 扫描开始时已索引的文件数，用作进度条的预估总数
+    pub expected_total: i64,
+    pub files_seen: i64,
+    pub files_added: i64,
+    pub files_modified: i64,
+    pub files_moved: i64,
+    pub files_restored: i64,
+    pub files_lost: i64,
+    pub errors: i64,
+}
+
+/// 清理丢失文件结果
+#[derive(Serialize, Debug)]
+pub struct PurgeResponse {
+    pub files_purged: u64,
+    pub thumbnails_removed: u64,
+}
+
+/// 已提交的重新打标签任务
+#[derive(Serialize, Debug)]
+pub struct RetagJobResponse {
+    pub job_id: i64,
+    /// 为空表示所有资源库
+    pub library_id: Option<i32>,
+}
+
 /// 连接测试结果
 #[derive(Serialize)]
 pub struct TestConnectionResponse {
     pub reachable: bool,
     pub message: String,
 }
+
+/// 创建/更新标签规则请求
+#[derive(Deserialize, Debug)]
+pub struct TagRuleRequest {
+    pub name: String,
+    /// 为空表示适用于所有资源库
+    #[serde(default)]
+    pub library_id: Option<i32>,
+    pub conditions: RuleConditions,
+    /// 命中后关联的标签路径，如 `Finance/Invoices`
+    pub tag_path: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// 标签规则
+#[derive(Serialize, Debug)]
+pub struct TagRuleResponse {
+    pub id: i32,
+    pub library_id: Option<i32>,
+    pub name: String,
+    pub conditions: RuleConditions,
+    pub tag_path: String,
+    pub enabled: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl From<TagRule> for TagRuleResponse {
+    fn from(rule: TagRule) -> Self {
+        Self {
+            conditions: rule.conditions().unwrap_or_default(),
+            id: rule.id,
+            library_id: rule.library_id,
+            name: rule.name,
+            tag_path: rule.tag_path,
+            enabled: rule.enabled,
+            created_at: rule.created_at,
+        }
+    }
+}
+
+/// 标签规则列表查询参数
+#[derive(Deserialize, Debug)]
+pub struct TagRuleQuery {
+    /// 只返回适用于该资源库的规则 (含全局规则)
+    pub library_id: Option<i32>,
+}
+
+/// 创建用户标签请求
+#[derive(Deserialize, Debug)]
+pub struct CreateTagRequest {
+    pub name: String,
+    /// 父标签 (必须是用户标签)，为空表示根级
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+}
+
+/// 修改用户标签请求，省略的字段保持不变
+#[derive(Deserialize, Debug)]
+pub struct UpdateTagRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 省略表示不移动，`null` 表示移动到根级
+    #[serde(default, deserialize_with = "double_option")]
+    pub parent_id: Option<Option<i32>>,
+}
+
+/// 区分 "字段缺失" 与 "字段为 null"
+fn double_option<'de, D>(deserializer: D) -> Result<Option<Option<i32>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<i32>::deserialize(deserializer).map(Some)
+}
+
+/// 为文件手动关联标签请求
+#[derive(Deserialize, Debug)]
+pub struct AttachTagRequest {
+    pub tag_id: i32,
+}
+
+/// 批量标签操作请求，`file_ids` 与 `query` 必须且只能指定一个
+#[derive(Deserialize, Debug)]
+pub struct BulkTagRequest {
+    pub action: BulkTagAction,
+    pub tag_ids: Vec<i32>,
+    #[serde(default)]
+    pub file_ids: Option<Vec<i32>>,
+    #[serde(default)]
+    pub query: Option<BulkFileQuery>,
+}
+
+/// 批量标签操作类型
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkTagAction {
+    Apply,
+    Remove,
+}
+
+/// 按条件选择文件，字段含义与 `GET /api/v1/files` 的同名参数相同
+#[derive(Deserialize, Debug)]
+pub struct BulkFileQuery {
+    pub tag_id: Option<i32>,
+    pub recursive: Option<bool>,
+    /// 标签查询表达式
+    pub q: Option<String>,
+    pub library_id: Option<i32>,
+    #[serde(default)]
+    pub status: FileStatusFilter,
+    /// 扩展名列表，逗号分隔
+    pub ext: Option<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub mtime_from: Option<i64>,
+    pub mtime_to: Option<i64>,
+    pub path_prefix: Option<String>,
+}
+
+impl BulkFileQuery {
+    pub fn extensions(&self) -> Vec<String> {
+        parse_extensions(self.ext.as_deref())
+    }
+}
+
+/// 批量标签操作结果
+#[derive(Serialize, Debug)]
+pub struct BulkTagResponse {
+    pub files_matched: u64,
+    /// 新增、移除或由自动来源改记为手动的文件标签关联数
+    pub links_changed: u64,
+}
+
+/// 搜索查询参数
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    /// 搜索词，多个词以空白分隔且需全部命中
+    pub q: String,
+    /// 以下标签过滤与 `GET /api/v1/files` 相同
+    pub tag_id: Option<i32>,
+    pub recursive: Option<bool>,
+    /// 标签查询表达式，例如 `type:Document AND NOT Archive`
+    pub tags: Option<String>,
+    pub library_id: Option<i32>,
+    #[serde(default)]
+    pub status: FileStatusFilter,
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// 搜索结果，按相关度排序
+#[derive(Serialize, Debug)]
+pub struct SearchResponse {
+    pub items: Vec<FileItem>,
+    pub total: i64,
+}
+
+/// 文件详情
+#[derive(Serialize, Debug)]
+pub struct FileDetailResponse {
+    #[serde(flatten)]
+    pub file: FileEntry,
+    pub library_name: String,
+    /// 相对资源库根目录的完整路径
+    pub path: String,
+    /// 关联的全部标签，按分类与路径排序
+    pub tags: Vec<FileTagItem>,
+    /// 缩略图是否已生成
+    pub has_thumbnail: bool,
+    /// 未完成 (待处理、进行中或失败) 的任务
+    pub tasks: Vec<FileTaskItem>,
+}
+
+/// 文件关联的标签
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct FileTagItem {
+    pub id: i32,
+    pub name: String,
+    pub category: String,
+    /// 从根到该标签的完整路径，例如 `["Work", "Design"]`
+    pub path: Vec<String>,
+    /// 关联来源: path / type / time / rule / manual
+    pub source: String,
+}
+
+/// 文件的任务状态
+#[derive(Serialize, Debug, FromRow)]
+pub struct FileTaskItem {
+    pub id: i32,
+    pub task_type: String,
+    /// 0=待处理, 1=进行中, 3=失败
+    pub status: i32,
+    pub error_msg: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// 文件内容请求参数
+#[derive(Deserialize, Debug, Default)]
+pub struct FileContentQuery {
+    /// 为 true 时以附件形式下载，否则在浏览器中直接打开
+    #[serde(default)]
+    pub download: bool,
+}
+
+/// 文件内容令牌
+#[derive(Serialize, Debug)]
+pub struct ContentTokenResponse {
+    pub token: String,
+    /// 有效期 (秒)
+    pub expires_in: i64,
+}
+
+/// 打包下载请求，`file_ids` 与 `query` 必须且只能指定一个
+#[derive(Deserialize, Debug)]
+pub struct ArchiveRequest {
+    #[serde(default)]
+    pub file_ids: Option<Vec<i32>>,
+    #[serde(default)]
+    pub query: Option<BulkFileQuery>,
+    #[serde(default)]
+    pub layout: ArchiveLayout,
+    /// 压缩包文件名，默认 `tagflow.zip` (按标签目录打包时为标签名)
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// 压缩包内的目录结构
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveLayout {
+    /// 所有文件放在根目录
+    #[default]
+    Flat,
+    /// 按 `query.tag_id` 下的标签路径建立目录
+    TagPath,
+}
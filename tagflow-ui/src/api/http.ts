@@ -55,17 +55,88 @@ export const authApi = {
     }),
 }
 
+export interface BulkTagRequest {
+  action: 'apply' | 'remove'
+  tag_ids: number[]
+  file_ids?: number[]
+  query?: {
+    tag_id?: number
+    recursive?: boolean
+    q?: string
+    library_id?: number
+    status?: 'online' | 'lost' | 'all'
+    // 以下与文件列表的同名参数相同
+    ext?: string
+    min_size?: number
+    max_size?: number
+    mtime_from?: number
+    mtime_to?: number
+    path_prefix?: string
+  }
+}
+
 export const tagApi = {
   getTree: () => instance.get('/v1/tags/tree'),
+  // 用户标签增删改，parentId 为 null 表示移动到根级
+  create: (name: string, parentId?: number) => instance.post('/v1/tags', { name, parent_id: parentId }),
+  update: (id: number, data: { name?: string; parent_id?: number | null }) => instance.patch(`/v1/tags/${id}`, data),
+  delete: (id: number) => instance.delete(`/v1/tags/${id}`),
+  // 为单个文件手动关联/解除标签
+  attach: (fileId: number, tagId: number) => instance.post(`/v1/files/${fileId}/tags`, { tag_id: tagId }),
+  detach: (fileId: number, tagId: number) => instance.delete(`/v1/files/${fileId}/tags/${tagId}`),
+  // 批量添加/移除标签，按文件 ID 或查询条件选择文件
+  bulk: (data: BulkTagRequest) => instance.post('/v1/tags/bulk', data),
 }
 
 export const fileApi = {
   list: (params?: {
     tag_id?: number
     recursive?: boolean
+    // 标签查询表达式，例如 'Work AND NOT type:Image'
+    q?: string
     page?: number
     limit?: number
+    // 上一页返回的 next_cursor，指定时忽略 page (仅按 mtime 排序时可用)
+    cursor?: string
+    sort?: 'name' | 'size' | 'mtime' | 'indexed_at' | 'extension'
+    order?: 'asc' | 'desc'
+    library_id?: number
+    // 逗号分隔的扩展名，例如 'jpg,png'
+    ext?: string
+    min_size?: number
+    max_size?: number
+    mtime_from?: number
+    mtime_to?: number
+    path_prefix?: string
+    status?: 'online' | 'lost' | 'all'
   }) => instance.get('/v1/files', { params }),
+
+  // 文件详情 (含标签路径与来源、缩略图状态、未完成任务)
+  get: (id: number) => instance.get(`/v1/files/${id}`),
+
+  // 打包下载 (file_ids 与 query 二选一)，layout 为 'tag_path' 时按 query.tag_id 下的标签路径建立目录
+  archive: (data: {
+    file_ids?: number[]
+    query?: BulkTagRequest['query']
+    layout?: 'flat' | 'tag_path'
+    name?: string
+  }) => instance.post('/v1/files/archive', data, { responseType: 'blob', timeout: 0 }),
+
+  // 原文件地址，供 <video>/<img> 直接引用 (支持 Range 拖动进度)
+  // 这些元素无法携带 Authorization 头，先获取文件内容令牌附在 URL 上，令牌过期后需重新获取
+  contentUrl: async (id: number, download = false) => {
+    const { data } = await instance.post<{ token: string; expires_in: number }>(`/v1/files/${id}/content-token`)
+    const params = new URLSearchParams({ token: data.token })
+    if (download) params.set('download', 'true')
+    return `/api/v1/files/${id}/content?${params}`
+  },
+
+  // 丢失文件列表
+  missing: (params?: {
+    library_id?: number
+    page?: number
+    limit?: number
+  }) => instance.get('/v1/files/missing', { params }),
 }
 
 export const libraryApi = {
@@ -93,4 +164,60 @@ export const libraryApi = {
 
   // 触发扫描
   triggerScan: (id: number) => instance.post(`/v1/libraries/${id}/scan`),
+
+  // 重新打标签 (只替换指定标签器的输出)，后台执行，返回 job_id
+  retag: (id: number, tagger?: 'path' | 'type' | 'time' | 'rule') =>
+    instance.post(`/v1/libraries/${id}/retag`, null, { params: { tagger } }),
+
+  // 查询重新打标签任务状态
+  getRetagJob: (jobId: number) => instance.get(`/v1/retag-jobs/${jobId}`),
+}
+
+export interface RuleConditions {
+  filename_regex?: string | null
+  path_glob?: string | null
+  extensions?: string[]
+}
+
+export const searchApi = {
+  // 按文件名与路径搜索，可与标签过滤组合 (tags 为标签查询表达式)
+  search: (params: {
+    q: string
+    tag_id?: number
+    recursive?: boolean
+    tags?: string
+    library_id?: number
+    status?: 'online' | 'lost' | 'all'
+    page?: number
+    limit?: number
+  }) => instance.get('/v1/search', { params }),
+}
+
+export const ruleApi = {
+  // 列出标签规则 (指定资源库时包含全局规则)
+  list: (libraryId?: number) => instance.get('/v1/rules', { params: { library_id: libraryId } }),
+
+  // 创建标签规则
+  create: (data: {
+    name: string
+    library_id?: number | null
+    conditions: RuleConditions
+    tag_path: string
+    enabled?: boolean
+  }) => instance.post('/v1/rules', data),
+
+  // 更新标签规则
+  update: (id: number, data: {
+    name: string
+    library_id?: number | null
+    conditions: RuleConditions
+    tag_path: string
+    enabled?: boolean
+  }) => instance.put(`/v1/rules/${id}`, data),
+
+  // 删除标签规则
+  delete: (id: number) => instance.delete(`/v1/rules/${id}`),
+
+  // 对已有文件重新应用规则，后台执行，用 libraryApi.getRetagJob 查询进度
+  apply: (libraryId?: number) => instance.post('/v1/rules/apply', null, { params: { library_id: libraryId } }),
 }